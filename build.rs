@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const HEADER_NAME: &str = "fs_extra_ffi.h";
+const HEADER_GUARD: &str = "FS_EXTRA_FFI_H";
+// Directory to copy the header to as well, for packaging. Unset by default so
+// that the build only writes into OUT_DIR.
+const HEADER_DIR_VAR: &str = "FS_EXTRA_FFI_HEADER_DIR";
+
+struct Const {
+    docs: Vec<String>,
+    name: String,
+    value: String,
+}
+
+struct Field {
+    docs: Vec<String>,
+    name: String,
+    ty: String,
+}
+
+struct Struct {
+    docs: Vec<String>,
+    name: String,
+    // Structs without `#[repr(C)]` are only handed out behind pointers.
+    opaque: bool,
+    fields: Vec<Field>,
+}
+
+struct TypeAlias {
+    docs: Vec<String>,
+    name: String,
+    ty: String,
+}
+
+struct Function {
+    docs: Vec<String>,
+    name: String,
+    args: Vec<(String, String)>,
+    ret: Option<String>,
+}
+
+#[derive(Default)]
+struct Api {
+    consts: Vec<Const>,
+    structs: Vec<Struct>,
+    types: Vec<TypeAlias>,
+    functions: Vec<Function>,
+}
+
+fn main() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let src_dir = manifest_dir.join("src");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", src_dir.display());
+    println!("cargo:rerun-if-env-changed={}", HEADER_DIR_VAR);
+
+    let mut sources = Vec::new();
+    collect_sources(&src_dir, &mut sources);
+    sources.sort();
+
+    let target_family = env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default();
+    let mut api = Api::default();
+    for source in &sources {
+        println!("cargo:rerun-if-changed={}", source.display());
+        let content = fs::read_to_string(source).unwrap();
+        parse_source(&content, &target_family, &mut api);
+    }
+    check_types(&api);
+
+    let header = render(&api);
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    write_if_changed(&out_dir.join(HEADER_NAME), &header);
+
+    if let Some(header_dir) = env::var_os(HEADER_DIR_VAR) {
+        let header_dir = PathBuf::from(header_dir);
+        fs::create_dir_all(&header_dir).unwrap();
+        write_if_changed(&header_dir.join(HEADER_NAME), &header);
+    }
+}
+
+fn collect_sources(dir: &Path, sources: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_sources(&path, sources);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            sources.push(path);
+        }
+    }
+}
+
+fn write_if_changed(path: &Path, content: &str) {
+    if let Ok(old) = fs::read_to_string(path) {
+        if old == content {
+            return;
+        }
+    }
+    let mut file = fs::File::create(path).unwrap();
+    file.write_all(content.as_bytes()).unwrap();
+}
+
+fn parse_source(content: &str, target_family: &str, api: &mut Api) {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut docs = Vec::new();
+    let mut no_mangle = false;
+    let mut repr_c = false;
+    let mut enabled = true;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if let Some(doc) = doc_line(line) {
+            if !line.starts_with(' ') {
+                docs.push(doc);
+            }
+            i += 1;
+            continue;
+        }
+        if line.trim_start().starts_with("#[no_mangle]") || line.contains("#[export_name") {
+            // The header only sees items that start in the first column.
+            if !line.starts_with("#[no_mangle]") {
+                panic!("cannot put the item after `{}` into the header", line.trim());
+            }
+            no_mangle = true;
+            i += 1;
+            continue;
+        }
+        if line.starts_with("#[repr(") && !line.starts_with("#[repr(C)]") {
+            panic!("cannot put `{}` into the header", line.trim());
+        }
+        if line.starts_with("#[repr(C)]") {
+            repr_c = true;
+            i += 1;
+            continue;
+        }
+        if let Some(family) = cfg_family(line) {
+            enabled = family_matches(family, target_family);
+            i += 1;
+            continue;
+        }
+        if line.starts_with("#[") {
+            i += 1;
+            continue;
+        }
+
+        check_item(line, no_mangle, repr_c);
+        if !enabled {
+            skip_item(&lines, &mut i);
+        } else if line.starts_with("pub const ") {
+            let item = collect_until(&lines, &mut i, ";");
+            api.consts.push(parse_const(&item, docs.split_off(0)));
+        } else if line.starts_with("pub type ") {
+            let item = collect_until(&lines, &mut i, ";");
+            api.types.push(parse_type_alias(&item, docs.split_off(0)));
+        } else if line.starts_with("pub struct ") && line.trim_end().ends_with('{') {
+            let mut item = parse_struct_head(line, !repr_c, docs.split_off(0));
+            i += 1;
+            let mut field_docs = Vec::new();
+            while i < lines.len() && !lines[i].starts_with('}') {
+                let field = lines[i].trim();
+                if let Some(doc) = doc_line(field) {
+                    field_docs.push(doc);
+                } else if !field.is_empty() && !field.starts_with("//") {
+                    item.fields.push(parse_field(field, field_docs.split_off(0)));
+                }
+                i += 1;
+            }
+            api.structs.push(item);
+            i += 1;
+        } else if no_mangle && line.starts_with("pub ") && line.contains("fn ") {
+            let item = collect_until(&lines, &mut i, "{");
+            api.functions.push(parse_function(&item, docs.split_off(0)));
+        } else {
+            i += 1;
+        }
+        docs.clear();
+        no_mangle = false;
+        repr_c = false;
+        enabled = true;
+    }
+}
+
+/// Fails the build on items the header would miss or get wrong: a `#[no_mangle]`
+/// or `#[repr(C)]` on anything but a public function or braced struct, and
+/// public `extern "C"` functions that are not exported at all.
+fn check_item(line: &str, no_mangle: bool, repr_c: bool) {
+    let is_fn = line.starts_with("pub ") && line.contains("fn ");
+    let exported = no_mangle && is_fn && !line.contains('<');
+    let fits = if no_mangle {
+        exported
+    } else if repr_c {
+        line.starts_with("pub struct ") && line.trim_end().ends_with('{')
+    } else {
+        !(is_fn && line.contains("extern \"C\""))
+    };
+    if !fits {
+        panic!("cannot put `{}` into the header", line.trim());
+    }
+}
+
+/// Fails the build on any type the header cannot spell: everything an exported
+/// item uses must be a C primitive, a pointer, a C callback or a type of the
+/// API itself.
+fn check_types(api: &Api) {
+    let known: HashSet<String> = api.structs
+        .iter()
+        .map(|item| item.name.clone())
+        .chain(api.types.iter().map(|item| item.name.clone()))
+        .collect();
+    let mut uses: Vec<(&str, &str)> = Vec::new();
+    for item in &api.consts {
+        if !is_c_value(&item.value) {
+            panic!("cannot put the value of `{}` into the header", item.name);
+        }
+    }
+    for item in &api.types {
+        uses.push((&item.name, &item.ty));
+    }
+    for item in api.structs.iter().filter(|item| !item.opaque) {
+        for field in &item.fields {
+            uses.push((&item.name, &field.ty));
+        }
+    }
+    for item in &api.functions {
+        for (_, ty) in &item.args {
+            uses.push((&item.name, ty));
+        }
+        if let Some(ref ret) = item.ret {
+            uses.push((&item.name, ret));
+        }
+    }
+    for (name, ty) in uses {
+        if !is_c_type(ty, &known) {
+            panic!("cannot put type `{}` of `{}` into the header", ty, name);
+        }
+    }
+}
+
+fn is_c_type(ty: &str, known: &HashSet<String>) -> bool {
+    if let Some((args, ret)) = fn_pointer_parts(ty) {
+        // The parts are already C, with `const` and `*` around the name.
+        return args.iter().chain(Some(&ret)).all(|ty| {
+            let name = ty.replace("const", "").replace('*', "");
+            is_c_name(name.trim(), known)
+        });
+    }
+    let ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix("*const ").or_else(|| ty.strip_prefix("*mut ")) {
+        return is_c_type(inner, known);
+    }
+    is_c_name(&c_type(ty), known)
+}
+
+fn is_c_name(name: &str, known: &HashSet<String>) -> bool {
+    known.contains(name) || C_PRIMITIVES.contains(&name)
+}
+
+const C_PRIMITIVES: &[&str] = &["char", "void", "int", "bool", "uint8_t", "uint16_t", "uint32_t",
+                                "uint64_t", "int8_t", "int16_t", "int32_t", "int64_t", "size_t",
+                                "ptrdiff_t"];
+
+/// Whether `value` is a plain integer literal, the only kind of value the
+/// header defines.
+fn is_c_value(value: &str) -> bool {
+    let value = value.replace('_', "");
+    let digits = value.strip_prefix("0x").unwrap_or(&value);
+    !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_hexdigit())
+        && (value.starts_with("0x") || digits.chars().all(|ch| ch.is_ascii_digit()))
+}
+
+/// Moves `*i` past the item starting there, either up to its `;` or to the
+/// brace closing its body.
+fn skip_item(lines: &[&str], i: &mut usize) {
+    let mut depth = 0;
+    let mut opened = false;
+    while *i < lines.len() {
+        let line = lines[*i];
+        *i += 1;
+        for ch in line.chars() {
+            match ch {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth == 0 && (opened || line.trim_end().ends_with(';')) {
+            break;
+        }
+    }
+}
+
+/// Target family of a `#[cfg(unix)]` or `#[cfg(windows)]` line, the only cfgs
+/// the exported items use.
+fn cfg_family(line: &str) -> Option<&str> {
+    match line.trim_end() {
+        "#[cfg(unix)]" => Some("unix"),
+        "#[cfg(windows)]" => Some("windows"),
+        _ => None,
+    }
+}
+
+fn family_matches(family: &str, target_family: &str) -> bool {
+    target_family.split(',').any(|item| item == family)
+}
+
+fn doc_line(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    trimmed
+        .strip_prefix("///")
+        .map(|doc| doc.trim_end().to_string())
+}
+
+/// Joins lines starting at `*i` up to and including the one that finishes the
+/// item with `terminator` (outside of any parentheses).
+fn collect_until(lines: &[&str], i: &mut usize, terminator: &str) -> String {
+    let mut item = String::new();
+    while *i < lines.len() {
+        let line = lines[*i].trim();
+        *i += 1;
+        if !item.is_empty() {
+            item.push(' ');
+        }
+        item.push_str(line);
+        if paren_depth(&item) == 0 && line.ends_with(terminator) {
+            break;
+        }
+    }
+    item.trim_end_matches(terminator).trim().to_string()
+}
+
+fn paren_depth(text: &str) -> i32 {
+    text.chars().fold(0, |depth, ch| match ch {
+        '(' => depth + 1,
+        ')' => depth - 1,
+        _ => depth,
+    })
+}
+
+fn parse_const(item: &str, docs: Vec<String>) -> Const {
+    let item = item.trim_start_matches("pub const ");
+    let (name, rest) = split_once(item, ':');
+    let (_, value) = split_once(rest, '=');
+    Const {
+        docs,
+        name: name.trim().to_string(),
+        value: value.trim().to_string(),
+    }
+}
+
+fn parse_type_alias(item: &str, docs: Vec<String>) -> TypeAlias {
+    let item = item.trim_start_matches("pub type ");
+    let (name, ty) = split_once(item, '=');
+    TypeAlias {
+        docs,
+        name: name.trim().to_string(),
+        ty: ty.trim().to_string(),
+    }
+}
+
+fn parse_struct_head(line: &str, opaque: bool, docs: Vec<String>) -> Struct {
+    let name = line.trim_start_matches("pub struct ").trim_end_matches('{').trim();
+    Struct {
+        docs,
+        name: name.to_string(),
+        opaque,
+        fields: Vec::new(),
+    }
+}
+
+fn parse_field(line: &str, docs: Vec<String>) -> Field {
+    let line = line.trim_start_matches("pub ").trim_end_matches(',');
+    let (name, ty) = split_once(line, ':');
+    Field {
+        docs,
+        name: name.trim().to_string(),
+        ty: ty.trim().to_string(),
+    }
+}
+
+fn parse_function(item: &str, docs: Vec<String>) -> Function {
+    let start = item.find("fn ").unwrap() + 3;
+    let open = start + item[start..].find('(').unwrap();
+    let close = matching_paren(item, open);
+    let name = item[start..open].trim().to_string();
+    let args = split_top_level(&item[open + 1..close])
+        .into_iter()
+        .map(|arg| {
+            let (name, ty) = split_once(&arg, ':');
+            (name.trim().to_string(), ty.trim().to_string())
+        })
+        .collect();
+    let rest = item[close + 1..].trim();
+    let ret = rest.strip_prefix("->").map(|ret| ret.trim().to_string());
+    Function {
+        docs,
+        name,
+        args,
+        ret,
+    }
+}
+
+fn split_once(text: &str, sep: char) -> (&str, &str) {
+    let pos = text.find(sep).unwrap();
+    (&text[..pos], &text[pos + 1..])
+}
+
+fn matching_paren(text: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (pos, ch) in text.char_indices().skip_while(|&(pos, _)| pos < open) {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return pos;
+                }
+            }
+            _ => {}
+        }
+    }
+    panic!("unbalanced parentheses in `{}`", text);
+}
+
+fn split_top_level(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for ch in text.chars() {
+        match ch {
+            '(' | '<' => depth += 1,
+            ')' | '>' if !current.ends_with('-') => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn c_type(ty: &str) -> String {
+    let ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix("*const ") {
+        let inner = inner.trim();
+        return if inner.starts_with('*') {
+            format!("{} const*", c_type(inner))
+        } else {
+            format!("const {}*", c_type(inner))
+        };
+    }
+    if let Some(inner) = ty.strip_prefix("*mut ") {
+        return format!("{}*", c_type(inner));
+    }
+    let name = ty.rsplit("::").next().unwrap();
+    match name {
+        "c_char" => "char",
+        "c_void" => "void",
+        "c_int" => "int",
+        "bool" => "bool",
+        "u8" | "uint8_t" => "uint8_t",
+        "u16" | "uint16_t" => "uint16_t",
+        "u32" | "uint32_t" => "uint32_t",
+        "u64" | "uint64_t" => "uint64_t",
+        "i8" | "int8_t" => "int8_t",
+        "i16" | "int16_t" => "int16_t",
+        "i32" | "int32_t" => "int32_t",
+        "i64" | "int64_t" => "int64_t",
+        "usize" | "size_t" => "size_t",
+        "isize" | "ssize_t" => "ptrdiff_t",
+        other => other,
+    }
+    .to_string()
+}
+
+fn fn_pointer_parts(ty: &str) -> Option<(Vec<String>, String)> {
+    let mut ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix("Option<").and_then(|ty| ty.strip_suffix('>')) {
+        ty = inner.trim();
+    }
+    let ty = ty.trim_start_matches("unsafe ").trim();
+    if !ty.starts_with("extern \"C\" fn") {
+        return None;
+    }
+    let open = ty.find('(').unwrap();
+    let close = matching_paren(ty, open);
+    let args = split_top_level(&ty[open + 1..close])
+        .into_iter()
+        .map(|arg| match arg.find(':') {
+            Some(pos) if !arg[pos..].starts_with("::") => arg[pos + 1..].trim().to_string(),
+            _ => arg,
+        })
+        .map(|arg| c_type(&arg))
+        .collect();
+    let rest = ty[close + 1..].trim();
+    let ret = match rest.strip_prefix("->") {
+        Some(ret) => c_type(ret),
+        None => "void".to_string(),
+    };
+    Some((args, ret))
+}
+
+fn c_decl(ty: &str, name: &str) -> String {
+    match fn_pointer_parts(ty) {
+        Some((args, ret)) => format!("{} (*{})({})", ret, name, c_args(&args)),
+        None => format!("{} {}", c_type(ty), name),
+    }
+}
+
+fn c_args(args: &[String]) -> String {
+    if args.is_empty() {
+        "void".to_string()
+    } else {
+        args.join(", ")
+    }
+}
+
+fn c_value(value: &str) -> String {
+    let value = value.replace('_', "");
+    let simple = value.chars().all(|ch| ch.is_ascii_alphanumeric());
+    if simple {
+        value
+    } else {
+        format!("({})", value)
+    }
+}
+
+fn render_docs(out: &mut String, docs: &[String], indent: &str) {
+    for doc in docs {
+        if doc.is_empty() {
+            out.push_str(&format!("{}//\n", indent));
+        } else {
+            out.push_str(&format!("{}//{}\n", indent, doc));
+        }
+    }
+}
+
+/// Names of the types an item needs to be complete before it can be declared.
+fn value_deps(ty: &str, known: &HashSet<String>, deps: &mut Vec<String>) {
+    if let Some((args, ret)) = fn_pointer_parts(ty) {
+        for arg in args.iter().chain(Some(&ret)) {
+            value_deps(arg, known, deps);
+        }
+        return;
+    }
+    let ty = ty.trim();
+    if ty.starts_with('*') {
+        return;
+    }
+    let name = c_type(ty);
+    if known.contains(&name) {
+        deps.push(name);
+    }
+}
+
+fn render(api: &Api) -> String {
+    let mut out = String::new();
+    out.push_str("// fs_extra-ffi C API.\n");
+    out.push_str("//\n");
+    out.push_str("// Generated by build.rs from the crate sources, do not edit.\n\n");
+    out.push_str(&format!("#ifndef {0}\n#define {0}\n\n", HEADER_GUARD));
+    out.push_str("#include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n");
+    out.push_str("#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n");
+
+    for item in &api.consts {
+        render_docs(&mut out, &item.docs, "");
+        out.push_str(&format!("#define {} {}\n", item.name, c_value(&item.value)));
+    }
+    if !api.consts.is_empty() {
+        out.push('\n');
+    }
+
+    // Plain aliases first, struct fields may point to them.
+    let plain_types: Vec<&TypeAlias> = api.types
+        .iter()
+        .filter(|item| fn_pointer_parts(&item.ty).is_none())
+        .collect();
+    for item in &plain_types {
+        render_docs(&mut out, &item.docs, "");
+        out.push_str(&format!("typedef {};\n", c_decl(&item.ty, &item.name)));
+    }
+    if !plain_types.is_empty() {
+        out.push('\n');
+    }
+
+    for item in &api.structs {
+        out.push_str(&format!("typedef struct {0} {0};\n", item.name));
+    }
+    if !api.structs.is_empty() {
+        out.push('\n');
+    }
+
+    // Type definitions in dependency order: a struct holding another struct
+    // (or a callback taking one) by value must come after it.
+    let known: HashSet<String> = api.structs
+        .iter()
+        .map(|item| item.name.clone())
+        .chain(api.types.iter().map(|item| item.name.clone()))
+        .collect();
+    let mut pending: BTreeMap<usize, (String, Vec<String>)> = BTreeMap::new();
+    let mut texts = Vec::new();
+    for item in api.structs.iter().filter(|item| !item.opaque) {
+        let mut deps = Vec::new();
+        let mut text = String::new();
+        render_docs(&mut text, &item.docs, "");
+        text.push_str(&format!("struct {} {{\n", item.name));
+        for field in &item.fields {
+            value_deps(&field.ty, &known, &mut deps);
+            render_docs(&mut text, &field.docs, "    ");
+            text.push_str(&format!("    {};\n", c_decl(&field.ty, &field.name)));
+        }
+        text.push_str("};\n\n");
+        pending.insert(texts.len(), (item.name.clone(), deps));
+        texts.push(text);
+    }
+    for item in api.types.iter().filter(|item| fn_pointer_parts(&item.ty).is_some()) {
+        let mut deps = Vec::new();
+        value_deps(&item.ty, &known, &mut deps);
+        let mut text = String::new();
+        render_docs(&mut text, &item.docs, "");
+        text.push_str(&format!("typedef {};\n\n", c_decl(&item.ty, &item.name)));
+        pending.insert(texts.len(), (item.name.clone(), deps));
+        texts.push(text);
+    }
+
+    let mut emitted: HashSet<String> = HashSet::new();
+    while !pending.is_empty() {
+        let ready: Vec<usize> = pending
+            .iter()
+            .filter(|&(_, (name, deps))| {
+                deps.iter().all(|dep| dep == name || emitted.contains(dep))
+            })
+            .map(|(&index, _)| index)
+            .collect();
+        if ready.is_empty() {
+            panic!("cyclic by-value type dependencies in the FFI structs");
+        }
+        for index in ready {
+            let (name, _) = pending.remove(&index).unwrap();
+            out.push_str(&texts[index]);
+            emitted.insert(name);
+        }
+    }
+
+    for item in &api.functions {
+        render_docs(&mut out, &item.docs, "");
+        let args: Vec<String> = item.args
+            .iter()
+            .map(|(name, ty)| c_decl(ty, name))
+            .collect();
+        let ret = match item.ret {
+            Some(ref ret) => c_type(ret),
+            None => "void".to_string(),
+        };
+        out.push_str(&format!("{} {}({});\n\n", ret, item.name, c_args(&args)));
+    }
+
+    out.push_str("#ifdef __cplusplus\n} // extern \"C\"\n#endif\n\n");
+    out.push_str(&format!("#endif // {}\n", HEADER_GUARD));
+    out
+}
@@ -0,0 +1,112 @@
+// Cancellation tokens. A token is created by the caller, handed to any number
+// of operations and cancelled from another thread; the operations then stop at
+// the next file or buffer with `ERROR_CODE_CANCELLED`. Pausing a token holds
+// them there instead until it is resumed.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use engine;
+use super::{ERROR_CODE_CANCELLED, U64Result, guard_free};
+
+/// Cancellation token, created with `cancel_token_new` and released with
+/// `cancel_token_free`.
+pub struct CancelToken {
+    state: Arc<TokenState>,
+}
+
+/// What a token switches, shared with the jobs started with it.
+pub(crate) struct TokenState {
+    pub flag: AtomicBool,
+    pub pause: engine::Pause,
+}
+
+impl TokenState {
+    pub fn new() -> TokenState {
+        TokenState {
+            flag: AtomicBool::new(false),
+            pause: engine::Pause::new(),
+        }
+    }
+}
+
+/// State of `token` to keep past the call, for operations that outlive it.
+pub(crate) unsafe fn get_token_state(token: *const CancelToken) -> Option<Arc<TokenState>> {
+    token.as_ref().map(|token| token.state.clone())
+}
+
+/// Puts the bytes a cancelled operation got through into `ok` of its result.
+pub(crate) fn set_cancelled_done(result: &mut U64Result, cancel: Option<&engine::Cancel>) {
+    if let Some(cancel) = cancel {
+        if result.is_error && result.error.code == ERROR_CODE_CANCELLED {
+            result.ok = cancel.done();
+        }
+    }
+}
+
+/// Cancellation state of a blocking call made with `token`, which may be NULL.
+pub(crate) unsafe fn get_cancel<'t>(token: *const CancelToken) -> Option<engine::Cancel<'t>> {
+    token.as_ref().map(|token| engine::Cancel::new(&token.state.flag, vec![&token.state.pause]))
+}
+
+/// New token that is not cancelled yet.
+#[no_mangle]
+pub extern "C" fn cancel_token_new() -> *mut CancelToken {
+    Box::into_raw(Box::new(CancelToken { state: Arc::new(TokenState::new()) }))
+}
+
+/// Cancels every operation running with `token` and every later one. Safe to
+/// call from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_cancel(token: *const CancelToken) {
+    if let Some(token) = token.as_ref() {
+        token.state.flag.store(true, Ordering::SeqCst);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_is_cancelled(token: *const CancelToken) -> bool {
+    match token.as_ref() {
+        Some(token) => token.state.flag.load(Ordering::SeqCst),
+        None => false,
+    }
+}
+
+/// Pauses every operation running with `token`, and every later one, before
+/// its next buffer or file until `cancel_token_resume`. Cancelling the token
+/// still stops them. Safe to call from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_pause(token: *const CancelToken) {
+    if let Some(token) = token.as_ref() {
+        token.state.pause.set(true);
+    }
+}
+
+/// Lets the operations paused with `cancel_token_pause` continue.
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_resume(token: *const CancelToken) {
+    if let Some(token) = token.as_ref() {
+        token.state.pause.set(false);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_is_paused(token: *const CancelToken) -> bool {
+    match token.as_ref() {
+        Some(token) => token.state.pause.is_paused(),
+        None => false,
+    }
+}
+
+/// Releases `token`, which must not be in use by a running blocking call. Jobs
+/// started with it keep a reference of their own, and are resumed since nobody
+/// could do it past the handle.
+#[no_mangle]
+pub unsafe extern "C" fn cancel_token_free(token: *mut CancelToken) {
+    guard_free(|| {
+        if !token.is_null() {
+            let token = Box::from_raw(token);
+            token.state.pause.set(false);
+        }
+    })
+}
@@ -0,0 +1,2031 @@
+// Directory transfers and listings on `Path`s. fs_extra turns every path it
+// walks into a `String` and gives up on names that are not valid UTF-8, these
+// versions keep the `OsStr` all the way through and otherwise follow what the
+// fs_extra functions of the same name do.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::error;
+use std::ffi::{CStr, OsStr};
+use std::fmt;
+use std::fs::{self, File, FileTimes, Metadata};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf, StripPrefixError};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use fs_extra::dir::{self, DirEntryAttr, TransitProcessResult, TransitState};
+use fs_extra::error::{self as fs_extra_error, ErrorKind};
+use fs_extra::file;
+
+use filter::Filter;
+use xattr;
+use super::{Entry, free_c_string, get_default_entry, get_os_c_string, get_system_time};
+
+macro_rules! err {
+    (path: $path:expr, $kind:expr, $($arg:tt)*) => {
+        return Err(Error::new($kind, &format!($($arg)*)).with_path($path))
+    };
+    ($kind:expr, $($arg:tt)*) => {
+        return Err(Error::new($kind, &format!($($arg)*)))
+    };
+}
+
+/// A fs_extra error that keeps the OS error number fs_extra drops for most
+/// kinds, and the path the failure happened on when it is known.
+#[derive(Debug)]
+pub(crate) struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub os_error: Option<i32>,
+    pub path: Option<PathBuf>,
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: &str) -> Error {
+        Error {
+            kind,
+            message: message.to_string(),
+            os_error: None,
+            path: None,
+        }
+    }
+
+    /// Names `path` as the failed one, unless the error names one already.
+    pub fn with_path(mut self, path: &Path) -> Error {
+        if self.path.is_none() {
+            self.path = Some(path.to_path_buf());
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<fs_extra_error::Error> for Error {
+    fn from(err: fs_extra_error::Error) -> Error {
+        let message = err.to_string();
+        let os_error = match err.kind {
+            ErrorKind::Io(ref err) => err.raw_os_error(),
+            _ => None,
+        };
+        Error {
+            kind: err.kind,
+            message,
+            os_error,
+            path: None,
+        }
+    }
+}
+
+/// The kind and message fs_extra gives `err`, with its OS error number.
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        let os_error = err.raw_os_error();
+        Error { os_error, ..fs_extra_error::Error::from(err).into() }
+    }
+}
+
+impl From<StripPrefixError> for Error {
+    fn from(err: StripPrefixError) -> Error {
+        fs_extra_error::Error::from(err).into()
+    }
+}
+
+/// Maps an `io::Error` on `path` to an `Error` naming it.
+fn on(path: &Path) -> impl Fn(io::Error) -> Error + '_ {
+    move |err| Error::from(err).with_path(path)
+}
+
+/// `file::remove`.
+pub(crate) fn remove_file(path: &Path) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path).map_err(on(path))?;
+    }
+    Ok(())
+}
+
+/// `dir::remove`.
+pub(crate) fn remove_dir_all(path: &Path) -> Result<()> {
+    if path.exists() {
+        fs::remove_dir_all(path).map_err(on(path))?;
+    }
+    Ok(())
+}
+
+/// `dir::create`, or `dir::create_all` with `all`.
+pub(crate) fn create_dir(path: &Path, erase: bool, all: bool) -> Result<()> {
+    if erase {
+        remove_dir_all(path)?;
+    }
+    let created = if all { fs::create_dir_all(path) } else { fs::create_dir(path) };
+    created.map_err(on(path))
+}
+
+/// `file::write_all`.
+pub(crate) fn write_all(path: &Path, content: &str) -> Result<()> {
+    if path.exists() && !path.is_file() {
+        err!(path: path, ErrorKind::InvalidFile, "Path \"{}\" is not a file!", path.display());
+    }
+    File::create(path).and_then(|mut file| file.write_all(content.as_bytes())).map_err(on(path))
+}
+
+/// `file::read_to_string`.
+pub(crate) fn read_to_string(path: &Path) -> Result<String> {
+    if path.exists() && !path.is_file() {
+        err!(path: path, ErrorKind::InvalidFile, "Path \"{}\" is not a file!", path.display());
+    }
+    let mut content = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut content)).map_err(on(path))?;
+    Ok(content)
+}
+
+pub(crate) enum Action {
+    Copy,
+    Move,
+}
+
+/// Options of the directory transfers, the fs_extra ones and what this crate
+/// adds to them.
+#[derive(Clone)]
+pub(crate) struct Options {
+    pub dir: dir::CopyOptions,
+    pub filter: Option<Filter>,
+    pub preserve: Preserve,
+    pub symlinks: Symlinks,
+    /// Recreates the hard links between the copied files.
+    pub hard_links: bool,
+}
+
+/// What the walks do with the symlinks below the paths they are given, which
+/// are always followed.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Symlinks {
+    Follow,
+    Keep,
+    Skip,
+}
+
+/// Metadata the copies take over from their sources, for files and
+/// directories alike.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Preserve {
+    pub permissions: bool,
+    pub times: bool,
+    pub owner: bool,
+    pub xattrs: bool,
+}
+
+/// Options of the single file transfers.
+pub(crate) struct FileOptions {
+    pub file: file::CopyOptions,
+    pub preserve: Preserve,
+}
+
+pub(crate) struct Progress<'a> {
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+    pub file_bytes_copied: u64,
+    pub file_total_bytes: u64,
+    pub file_name: &'a OsStr,
+    pub state: TransitState,
+}
+
+pub(crate) type Handler<'h> = &'h mut dyn FnMut(&Progress) -> TransitProcessResult;
+
+/// Reports `(copied, total)` bytes of a file, returning false stops the copy.
+pub(crate) type FileHandler<'h> = &'h mut dyn FnMut(u64, u64) -> bool;
+
+/// Stop request of a cancellation token, checked before every file or entry and
+/// after every copied buffer. Operations also wait there while any of `pauses`
+/// is paused, those of their token and of their job.
+pub(crate) struct Cancel<'c> {
+    flag: &'c AtomicBool,
+    pauses: Vec<&'c Pause>,
+    done: Cell<u64>,
+}
+
+impl<'c> Cancel<'c> {
+    pub fn new(flag: &'c AtomicBool, pauses: Vec<&'c Pause>) -> Cancel<'c> {
+        Cancel {
+            flag,
+            pauses,
+            done: Cell::new(0),
+        }
+    }
+
+    /// Bytes the operation got through before it was cancelled.
+    pub fn done(&self) -> u64 {
+        self.done.get()
+    }
+
+    /// Records `done` when `result` is the cancellation error.
+    fn stop<T>(&self, result: Result<T>, done: u64) -> Result<T> {
+        if let Err(ref err) = result {
+            if is_cancelled(&err.kind) {
+                self.done.set(done);
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Operation cancelled")
+    }
+}
+
+impl error::Error for Cancelled {}
+
+/// True for the error an operation stops with once it is cancelled.
+pub(crate) fn is_cancelled(kind: &ErrorKind) -> bool {
+    match *kind {
+        ErrorKind::Io(ref err) => err.get_ref().is_some_and(|err| err.is::<Cancelled>()),
+        _ => false,
+    }
+}
+
+/// Pause switch of a job or a cancellation token.
+pub(crate) struct Pause {
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl Pause {
+    pub fn new() -> Pause {
+        Pause {
+            paused: Mutex::new(false),
+            resumed: Condvar::new(),
+        }
+    }
+
+    pub fn set(&self, paused: bool) {
+        *self.paused.lock().unwrap_or_else(PoisonError::into_inner) = paused;
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Blocks while paused. Cancelling does not wake the condvar, so `cancel`
+    /// is polled in between.
+    fn wait(&self, cancel: &AtomicBool) {
+        let mut paused = self.paused.lock().unwrap_or_else(PoisonError::into_inner);
+        while *paused && !cancel.load(Ordering::SeqCst) {
+            paused = self.resumed
+                .wait_timeout(paused, Duration::from_millis(100))
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+fn check(cancel: Option<&Cancel>) -> Result<()> {
+    let cancel = match cancel {
+        Some(cancel) => cancel,
+        None => return Ok(()),
+    };
+    while !cancel.flag.load(Ordering::SeqCst) {
+        match cancel.pauses.iter().find(|pause| pause.is_paused()) {
+            Some(pause) => pause.wait(cancel.flag),
+            None => break,
+        }
+    }
+    if cancel.flag.load(Ordering::SeqCst) {
+        let err = io::Error::new(io::ErrorKind::Interrupted, Cancelled);
+        return Err(Error::new(ErrorKind::Io(err), "Operation cancelled"));
+    }
+    Ok(())
+}
+
+/// Step of a transfer or removal a failure happened in.
+#[derive(Clone, Copy)]
+pub(crate) enum Phase {
+    Read,
+    Write,
+    CreateDir,
+    Remove,
+}
+
+/// Failure a transfer or removal went past in continue-on-error mode.
+pub(crate) struct Failure {
+    pub path: PathBuf,
+    pub phase: Phase,
+    pub error: Error,
+}
+
+/// How one path of a batch went.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Status {
+    Done,
+    Skipped,
+    Failed,
+    NotAttempted,
+}
+
+pub(crate) struct Outcome {
+    pub status: Status,
+    /// Bytes copied, moved or removed for the path.
+    pub bytes: u64,
+    /// Index of the first failure of the path in `Report::failures`.
+    pub failure: Option<usize>,
+}
+
+impl Outcome {
+    fn new(result: &Result<()>, skipped: bool, bytes: u64, failure: Option<usize>) -> Outcome {
+        let status = if result.is_err() || failure.is_some() {
+            Status::Failed
+        } else if skipped {
+            Status::Skipped
+        } else {
+            Status::Done
+        };
+        Outcome { status, bytes, failure }
+    }
+
+    fn not_attempted() -> Outcome {
+        Outcome {
+            status: Status::NotAttempted,
+            bytes: 0,
+            failure: None,
+        }
+    }
+}
+
+/// What an operation went past besides its total, filled in as it runs.
+pub(crate) struct Report {
+    /// Only kept in continue-on-error mode.
+    pub failures: Option<Vec<Failure>>,
+    /// One outcome per path of a batch, in the order of the paths.
+    pub items: Vec<Outcome>,
+    /// What a copy left out without failing, such as extended attributes the
+    /// target does not take.
+    pub warnings: Vec<Failure>,
+}
+
+impl Report {
+    pub(crate) fn new(continue_on_error: bool) -> Report {
+        Report {
+            failures: if continue_on_error { Some(Vec::new()) } else { None },
+            items: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    fn failure_count(&self) -> usize {
+        self.failures.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Index of the first failure recorded since there were `known` of them.
+    fn first_failure(&self, known: usize) -> Option<usize> {
+        if self.failure_count() > known { Some(known) } else { None }
+    }
+}
+
+type Phased<T> = std::result::Result<T, (Phase, Error)>;
+
+/// Maps an error of `phase` on `path` to a phased one naming it.
+fn at<E: Into<Error>>(phase: Phase, path: &Path) -> impl Fn(E) -> (Phase, Error) + '_ {
+    move |err| (phase, err.into().with_path(path))
+}
+
+/// True for the errors that end an operation even in continue-on-error mode.
+fn is_stop(err: &Error) -> bool {
+    match err.kind {
+        ErrorKind::Interrupted => true,
+        ref kind => is_cancelled(kind),
+    }
+}
+
+/// Copies one file, reporting progress after every buffer when `progress` is
+/// given. Returns 0 when the target exists and is skipped. A copy stopped by
+/// `progress` fails with `ErrorKind::Interrupted`, a cancelled one with the
+/// cancellation error, and neither leaves a target behind.
+pub(crate) fn copy_file(from: &Path,
+                        to: &Path,
+                        options: &FileOptions,
+                        progress: Option<FileHandler>,
+                        cancel: Option<&Cancel>,
+                        warnings: &mut Vec<Failure>)
+                        -> Result<u64> {
+    copy_file_phased(from, to, options, progress, cancel, warnings).map_err(|(_, err)| err)
+}
+
+fn copy_file_phased(from: &Path,
+                    to: &Path,
+                    options: &FileOptions,
+                    mut progress: Option<FileHandler>,
+                    cancel: Option<&Cancel>,
+                    warnings: &mut Vec<Failure>)
+                    -> Phased<u64> {
+    let preserve = options.preserve;
+    let options = &options.file;
+    check(cancel).map_err(at(Phase::Read, from))?;
+    if !from.exists() {
+        let message = format!("Path \"{}\" does not exist or you don't have access!",
+                              from.display());
+        return Err((Phase::Read, Error::new(ErrorKind::NotFound, &message).with_path(from)));
+    }
+    if !from.is_file() {
+        let message = format!("Path \"{}\" is not a file!", from.display());
+        return Err((Phase::Read, Error::new(ErrorKind::InvalidFile, &message).with_path(from)));
+    }
+    // Read before the copy, which updates the access time.
+    let source = from.metadata().map_err(at(Phase::Read, from))?;
+    if !options.overwrite && to.exists() {
+        if options.skip_exist {
+            return Ok(0);
+        }
+        let message = format!("Path \"{}\" exists", to.display());
+        return Err((Phase::Write, Error::new(ErrorKind::AlreadyExists, &message).with_path(to)));
+    }
+
+    if progress.is_none() && cancel.is_none() {
+        // `fs::copy` does not say which side failed.
+        let size = fs::copy(from, to).map_err(|err| match File::open(from) {
+            Ok(_) => (Phase::Write, Error::from(err).with_path(to)),
+            Err(_) => (Phase::Read, Error::from(err).with_path(from)),
+        })?;
+        preserve_metadata(&source, from, to, preserve, warnings).map_err(at(Phase::Write, to))?;
+        return Ok(size);
+    }
+    let mut file_from = File::open(from).map_err(at(Phase::Read, from))?;
+    let file_size = file_from.metadata().map_err(at(Phase::Read, from))?.len();
+    let mut file_to = File::create(to).map_err(at(Phase::Write, to))?;
+    // The mode of `from`, as `fs::copy` gives it whatever `preserve` says, and
+    // like it only to regular files.
+    if file_to.metadata().map_err(at(Phase::Write, to))?.is_file() {
+        file_to.set_permissions(source.permissions()).map_err(at(Phase::Write, to))?;
+    }
+    let mut buf = vec![0; options.buffer_size];
+    let mut copied_bytes = 0;
+    while !buf.is_empty() {
+        match file_from.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                file_to.write_all(&buf[..n]).map_err(at(Phase::Write, to))?;
+                copied_bytes += n as u64;
+                let aborted = match progress {
+                    Some(ref mut progress) => !progress(copied_bytes, file_size),
+                    None => false,
+                };
+                let stop = if aborted {
+                    Err(Error::new(ErrorKind::Interrupted,
+                                   &format!("Copy of \"{}\" aborted by the progress callback",
+                                            from.display())))
+                } else {
+                    check(cancel)
+                };
+                if let Err(err) = stop {
+                    drop(file_to);
+                    fs::remove_file(to).map_err(at(Phase::Remove, to))?;
+                    return Err((Phase::Write, err));
+                }
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err((Phase::Read, err.into())),
+        }
+    }
+    drop(file_to);
+    preserve_metadata(&source, from, to, preserve, warnings).map_err(at(Phase::Write, to))?;
+    Ok(file_size)
+}
+
+/// Gives `to` what `preserve` asks for of `from`, whose metadata is `source`.
+/// Changing the owner takes privileges, without them it is left as it is.
+fn preserve_metadata(source: &Metadata,
+                     from: &Path,
+                     to: &Path,
+                     preserve: Preserve,
+                     warnings: &mut Vec<Failure>)
+                     -> io::Result<()> {
+    if preserve.times {
+        let times = FileTimes::new()
+            .set_accessed(source.accessed()?)
+            .set_modified(source.modified()?);
+        open_for_times(to)?.set_times(times)?;
+    }
+    // Before the permissions, a change of owner clears the set-id bits.
+    if preserve.owner {
+        set_owner(source, to)?;
+    }
+    // Before the permissions too, which may take away the right to write them.
+    if preserve.xattrs {
+        with_owner_write(to, || copy_xattrs(from, to, warnings))?;
+    }
+    if preserve.permissions {
+        fs::set_permissions(to, source.permissions())?;
+    }
+    Ok(())
+}
+
+/// Copies the extended attributes of `from` to `to`. Only failing to list them
+/// fails the copy, an attribute that cannot be read or that the file system of
+/// `to` does not take is recorded in `warnings` and the rest are still copied.
+fn copy_xattrs(from: &Path, to: &Path, warnings: &mut Vec<Failure>) -> io::Result<()> {
+    let names = match xattr::list(from) {
+        Err(ref err) if xattr::is_unsupported(err) => return Ok(()),
+        names => names?,
+    };
+    for name in names {
+        let value = match xattr::get(from, &name) {
+            Ok(value) => value,
+            Err(err) => {
+                warnings.push(get_xattr_warning(from, Phase::Read, &name, err));
+                continue;
+            }
+        };
+        if let Err(err) = xattr::set(to, &name, &value) {
+            warnings.push(get_xattr_warning(to, Phase::Write, &name, err));
+        }
+    }
+    Ok(())
+}
+
+fn get_xattr_warning(path: &Path, phase: Phase, name: &CStr, err: io::Error) -> Failure {
+    let message = if xattr::is_unsupported(&err) {
+        format!("Extended attribute {:?} not supported: {}", name, err)
+    } else {
+        format!("Extended attribute {:?} not copied: {}", name, err)
+    };
+    let os_error = err.raw_os_error();
+    Failure {
+        path: path.to_path_buf(),
+        phase,
+        error: Error {
+            os_error,
+            ..Error::new(ErrorKind::Io(err), &message).with_path(path)
+        },
+    }
+}
+
+#[cfg(unix)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // FILE_WRITE_ATTRIBUTES, with FILE_FLAG_BACKUP_SEMANTICS for directories.
+    fs::OpenOptions::new().access_mode(0x100).custom_flags(0x0200_0000).open(path)
+}
+
+#[cfg(unix)]
+fn set_owner(source: &Metadata, to: &Path) -> io::Result<()> {
+    use std::os::unix::fs::{MetadataExt, chown};
+
+    match chown(to, Some(source.uid()), Some(source.gid())) {
+        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(windows)]
+fn set_owner(_: &Metadata, _: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Runs `write` with the owner allowed to write `path`, which a file created
+/// under a strict umask may not be, and puts the mode back after it.
+#[cfg(unix)]
+fn with_owner_write<F>(path: &Path, write: F) -> io::Result<()>
+    where F: FnOnce() -> io::Result<()>
+{
+    use std::os::unix::fs::PermissionsExt;
+
+    let permissions = fs::metadata(path)?.permissions();
+    if permissions.mode() & 0o200 != 0 {
+        return write();
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(permissions.mode() | 0o200))?;
+    let written = write();
+    fs::set_permissions(path, permissions)?;
+    written
+}
+
+#[cfg(windows)]
+fn with_owner_write<F>(_: &Path, write: F) -> io::Result<()>
+    where F: FnOnce() -> io::Result<()>
+{
+    write()
+}
+
+/// `copy_file` followed by removing `from`, which is kept when the copy was
+/// skipped.
+pub(crate) fn move_file(from: &Path,
+                        to: &Path,
+                        options: &FileOptions,
+                        progress: Option<FileHandler>,
+                        cancel: Option<&Cancel>,
+                        warnings: &mut Vec<Failure>)
+                        -> Result<u64> {
+    move_file_phased(from, to, options, progress, cancel, warnings).map_err(|(_, err)| err)
+}
+
+fn move_file_phased(from: &Path,
+                    to: &Path,
+                    options: &FileOptions,
+                    progress: Option<FileHandler>,
+                    cancel: Option<&Cancel>,
+                    warnings: &mut Vec<Failure>)
+                    -> Phased<u64> {
+    let skip = options.file.skip_exist && !options.file.overwrite && to.exists();
+    let result = copy_file_phased(from, to, options, progress, cancel, warnings)?;
+    if !skip {
+        remove_file(from).map_err(at(Phase::Remove, from))?;
+    }
+    Ok(result)
+}
+
+pub(crate) struct DirContent {
+    pub dir_size: u64,
+    pub files: Vec<PathBuf>,
+    pub directories: Vec<PathBuf>,
+    // Metadata of each of `directories`, read before walking it.
+    pub directory_metadata: Vec<Metadata>,
+    // Symlinks kept as links.
+    pub symlinks: Vec<PathBuf>,
+    // Symlinks followed, what is below them belongs to another tree.
+    pub followed: Vec<PathBuf>,
+    // Symlinks kept or followed that lead outside the walked directory.
+    pub outside: Vec<PathBuf>,
+    // Set when the filter or the symlink policy left anything out.
+    pub filtered: bool,
+}
+
+impl DirContent {
+    /// Whether `path` was reached through a followed symlink.
+    pub fn is_linked(&self, path: &Path) -> bool {
+        self.followed.iter().any(|link| path.starts_with(link))
+    }
+}
+
+/// Same walk as `dir::get_dir_content2`, a `depth` of 0 is unlimited. With
+/// `failures`, what cannot be read is recorded there and left out. The entries
+/// `filter` rejects are left out too, with everything below them, and the
+/// symlinks below `path` are handled as `symlinks` says. With `hard_links`,
+/// `dir_size` counts the data of hard-linked files once.
+pub(crate) fn get_dir_content(path: &Path,
+                              depth: u64,
+                              filter: Option<&Filter>,
+                              symlinks: Symlinks,
+                              hard_links: bool,
+                              cancel: Option<&Cancel>,
+                              failures: Option<&mut Vec<Failure>>)
+                              -> Result<DirContent> {
+    let mut content = DirContent {
+        dir_size: 0,
+        files: Vec::new(),
+        directories: Vec::new(),
+        directory_metadata: Vec::new(),
+        symlinks: Vec::new(),
+        followed: Vec::new(),
+        outside: Vec::new(),
+        filtered: false,
+    };
+    let depth = if depth != 0 { depth + 1 } else { 0 };
+    let mut walk = Walk::new(path, filter, symlinks, hard_links, cancel);
+    walk.visit(path, depth, &mut content, failures)?;
+    Ok(content)
+}
+
+/// What a walk found at a path below its root.
+enum Found {
+    /// A symlink the policy skips.
+    Skipped,
+    /// With the metadata of the link itself when links are kept, of its
+    /// target when they are followed.
+    Entry { metadata: Metadata, symlink: bool },
+}
+
+struct Walk<'w> {
+    root: &'w Path,
+    filter: Option<&'w Filter>,
+    symlinks: Symlinks,
+    cancel: Option<&'w Cancel<'w>>,
+    // Canonical paths of the directories from the root down to the one being
+    // walked, a followed symlink leading into one of them is a loop.
+    chain: Vec<PathBuf>,
+    // Hard-linked files counted so far, when they are counted once.
+    counted: Option<HashSet<LinkId>>,
+}
+
+impl<'w> Walk<'w> {
+    fn new(root: &'w Path,
+           filter: Option<&'w Filter>,
+           symlinks: Symlinks,
+           hard_links: bool,
+           cancel: Option<&'w Cancel<'w>>)
+           -> Walk<'w> {
+        Walk {
+            root,
+            filter,
+            symlinks,
+            cancel,
+            chain: Vec::new(),
+            counted: if hard_links { Some(HashSet::new()) } else { None },
+        }
+    }
+
+    /// Bytes of the file with `metadata`, 0 when its data is counted already.
+    fn count(&mut self, metadata: &Metadata) -> u64 {
+        if let (Some(counted), Some(id)) = (self.counted.as_mut(), get_link_id(metadata)) {
+            if !counted.insert(id) {
+                return 0;
+            }
+        }
+        metadata.len()
+    }
+
+    /// What is at `path`. The root itself is always followed.
+    fn find(&self, path: &Path) -> io::Result<Found> {
+        if self.chain.is_empty() {
+            return Ok(Found::Entry {
+                metadata: path.metadata()?,
+                symlink: false,
+            });
+        }
+        let metadata = path.symlink_metadata()?;
+        if !metadata.file_type().is_symlink() {
+            return Ok(Found::Entry {
+                metadata,
+                symlink: false,
+            });
+        }
+        let metadata = match self.symlinks {
+            Symlinks::Follow => path.metadata()?,
+            Symlinks::Keep => metadata,
+            Symlinks::Skip => return Ok(Found::Skipped),
+        };
+        Ok(Found::Entry {
+            metadata,
+            symlink: true,
+        })
+    }
+
+    /// Whether the symlink at `path` leads outside the root. A dangling one
+    /// leads nowhere.
+    fn is_outside(&self, path: &Path) -> bool {
+        match (fs::canonicalize(path), self.chain.first()) {
+            (Ok(target), Some(root)) => !target.starts_with(root),
+            _ => false,
+        }
+    }
+
+    /// Steps into the directory at `path`, failing when it is reached through a
+    /// symlink that leads back into the directories being walked.
+    fn enter(&mut self, path: &Path, symlink: bool) -> Result<()> {
+        let canonical = match self.chain.last() {
+            Some(parent) if !symlink => parent.join(path.file_name().unwrap_or_default()),
+            _ => fs::canonicalize(path)?,
+        };
+        if symlink && self.chain.contains(&canonical) {
+            err!(path: path,
+                 ErrorKind::Other,
+                 "Symlink \"{}\" loops back to \"{}\"",
+                 path.display(),
+                 canonical.display());
+        }
+        self.chain.push(canonical);
+        Ok(())
+    }
+
+    fn visit(&mut self,
+             path: &Path,
+             mut depth: u64,
+             content: &mut DirContent,
+             mut failures: Option<&mut Vec<Failure>>)
+             -> Result<()> {
+        check(self.cancel)?;
+        let found = self.find(path);
+        let (metadata, symlink) = match recover(found, failures.as_deref_mut(), path, Phase::Read)? {
+            Some(Found::Entry { metadata, symlink }) => (metadata, symlink),
+            Some(Found::Skipped) => {
+                content.filtered = true;
+                return Ok(());
+            }
+            None => return Ok(()),
+        };
+        if !is_accepted(self.filter, self.root, path, &metadata) {
+            content.filtered = true;
+            return Ok(());
+        }
+        if symlink {
+            if self.is_outside(path) {
+                content.outside.push(path.to_path_buf());
+            }
+            if self.symlinks == Symlinks::Follow {
+                content.followed.push(path.to_path_buf());
+            }
+        }
+        if !metadata.is_dir() {
+            content.dir_size += self.count(&metadata);
+            if symlink && self.symlinks == Symlinks::Keep {
+                content.symlinks.push(path.to_path_buf());
+            } else {
+                content.files.push(path.to_path_buf());
+            }
+            return Ok(());
+        }
+        let entered = self.enter(path, symlink);
+        if recover(entered, failures.as_deref_mut(), path, Phase::Read)?.is_none() {
+            return Ok(());
+        }
+        content.dir_size += metadata.len();
+        content.directories.push(path.to_path_buf());
+        content.directory_metadata.push(metadata);
+        if depth == 0 || depth > 1 {
+            if depth > 1 {
+                depth -= 1;
+            }
+            let entries = fs::read_dir(path);
+            if let Some(entries) = recover(entries, failures.as_deref_mut(), path, Phase::Read)? {
+                for entry in entries {
+                    if let Some(entry) = recover(entry, failures.as_deref_mut(), path, Phase::Read)? {
+                        self.visit(&entry.path(), depth, content, failures.as_deref_mut())?;
+                    }
+                }
+            }
+        }
+        self.chain.pop();
+        Ok(())
+    }
+
+    /// Adds the bytes of what is not a directory at and below `path`.
+    fn add_size(&mut self, path: &Path, size: &mut u64) -> Result<()> {
+        let (metadata, symlink) = match self.find(path)? {
+            Found::Entry { metadata, symlink } => (metadata, symlink),
+            Found::Skipped => return Ok(()),
+        };
+        if !is_accepted(self.filter, self.root, path, &metadata) {
+            return Ok(());
+        }
+        if !metadata.is_dir() {
+            *size += self.count(&metadata);
+            return Ok(());
+        }
+        self.enter(path, symlink)?;
+        for entry in fs::read_dir(path)? {
+            check(self.cancel)?;
+            self.add_size(&entry?.path(), size)?;
+        }
+        self.chain.pop();
+        Ok(())
+    }
+}
+
+/// Device and inode of a file, identical for its hard links.
+type LinkId = (u64, u64);
+
+/// Identity of the file with `metadata`. Windows has none in the stable std,
+/// its files are never taken for linked.
+#[cfg(unix)]
+fn get_file_id(metadata: &Metadata) -> Option<LinkId> {
+    use std::os::unix::fs::MetadataExt;
+
+    if metadata.is_file() {
+        Some((metadata.dev(), metadata.ino()))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn get_file_id(_: &Metadata) -> Option<LinkId> {
+    None
+}
+
+/// `get_file_id` of a file with other hard links.
+#[cfg(unix)]
+fn get_link_id(metadata: &Metadata) -> Option<LinkId> {
+    use std::os::unix::fs::MetadataExt;
+
+    get_file_id(metadata).filter(|_| metadata.nlink() > 1)
+}
+
+#[cfg(not(unix))]
+fn get_link_id(_: &Metadata) -> Option<LinkId> {
+    None
+}
+
+/// Whether `filter`, when given, keeps `path`, an entry below `root`.
+fn is_accepted(filter: Option<&Filter>, root: &Path, path: &Path, metadata: &Metadata) -> bool {
+    match filter {
+        Some(filter) if path != root => {
+            filter.accepts(path, path.strip_prefix(root).unwrap_or(path), metadata)
+        }
+        _ => true,
+    }
+}
+
+/// Whether `filter`, when given, keeps `item` of a batch, matched by its name.
+/// An item that cannot be read is kept for the transfer to report.
+fn is_item_accepted(filter: Option<&Filter>, item: &Path) -> bool {
+    match (filter, item.file_name(), item.metadata()) {
+        (Some(filter), Some(name), Ok(metadata)) => {
+            filter.accepts(item, Path::new(name), &metadata)
+        }
+        _ => true,
+    }
+}
+
+/// `result` as an option, going past its error by recording it in `failures`
+/// when given and failing with it otherwise.
+fn recover<T, E: Into<Error>>(result: std::result::Result<T, E>,
+                              failures: Option<&mut Vec<Failure>>,
+                              path: &Path,
+                              phase: Phase)
+                              -> Result<Option<T>> {
+    let error = match result {
+        Ok(value) => return Ok(Some(value)),
+        Err(err) => err.into().with_path(path),
+    };
+    match failures {
+        Some(failures) if !is_stop(&error) => {
+            failures.push(Failure {
+                path: path.to_path_buf(),
+                phase,
+                error,
+            });
+            Ok(None)
+        }
+        _ => Err(error),
+    }
+}
+
+fn stop<T>(cancel: Option<&Cancel>, result: Result<T>, done: u64) -> Result<T> {
+    match cancel {
+        Some(cancel) => cancel.stop(result, done),
+        None => result,
+    }
+}
+
+/// `dir::get_size`, only counting what is not a directory. What `filter`
+/// rejects below `path` is not counted, the symlinks below it are handled as
+/// `symlinks` says. With `hard_links`, hard-linked data is counted once.
+pub(crate) fn get_size(path: &Path,
+                       filter: Option<&Filter>,
+                       symlinks: Symlinks,
+                       hard_links: bool,
+                       cancel: Option<&Cancel>)
+                       -> Result<u64> {
+    let mut size = 0;
+    let mut walk = Walk::new(path, filter, symlinks, hard_links, cancel);
+    let result = walk.add_size(path, &mut size);
+    stop(cancel, result.map(|_| size), size)
+}
+
+/// `dir::remove`. A removal that can be cancelled or goes on past failures
+/// walks the tree itself, reporting the bytes of the files it removed.
+pub(crate) fn remove_dir(path: &Path, cancel: Option<&Cancel>, report: &mut Report) -> Result<()> {
+    let mut removed_bytes = 0;
+    let result = remove_one_dir(path, &mut removed_bytes, cancel, report.failures.as_mut());
+    stop(cancel, result, removed_bytes)
+}
+
+/// `remove_items`, see `remove_dir`. A path that does not exist is skipped.
+pub(crate) fn remove_items(paths: &[PathBuf],
+                           cancel: Option<&Cancel>,
+                           report: &mut Report)
+                           -> Result<()> {
+    let mut removed_bytes = 0;
+    let mut result = Ok(());
+    for path in paths {
+        let known_bytes = removed_bytes;
+        let known_failures = report.failure_count();
+        let skipped = !path.exists();
+        result = remove_item(path, &mut removed_bytes, cancel, report.failures.as_mut());
+        report.items.push(Outcome::new(&result,
+                                       skipped,
+                                       removed_bytes - known_bytes,
+                                       report.first_failure(known_failures)));
+        if result.is_err() {
+            break;
+        }
+    }
+    report.items.resize_with(paths.len(), Outcome::not_attempted);
+    stop(cancel, result, removed_bytes)
+}
+
+fn remove_item(path: &Path,
+               removed_bytes: &mut u64,
+               cancel: Option<&Cancel>,
+               failures: Option<&mut Vec<Failure>>)
+               -> Result<()> {
+    // Walked even without a token or failures, for the bytes of the outcome.
+    if path.is_dir() {
+        return remove_tree(path, removed_bytes, cancel, failures).map(|_| ());
+    }
+    check(cancel)?;
+    let size = path.metadata().map_or(0, |metadata| metadata.len());
+    if recover(remove_file(path), failures, path, Phase::Remove)?.is_some() {
+        *removed_bytes += size;
+    }
+    Ok(())
+}
+
+fn remove_one_dir(path: &Path,
+                  removed_bytes: &mut u64,
+                  cancel: Option<&Cancel>,
+                  failures: Option<&mut Vec<Failure>>)
+                  -> Result<()> {
+    if cancel.is_none() && failures.is_none() {
+        return remove_dir_all(path);
+    }
+    if path.exists() {
+        remove_tree(path, removed_bytes, cancel, failures)?;
+    }
+    Ok(())
+}
+
+/// Returns whether `path` is gone, which it is not after a failure below it.
+fn remove_tree(path: &Path,
+               removed_bytes: &mut u64,
+               cancel: Option<&Cancel>,
+               mut failures: Option<&mut Vec<Failure>>)
+               -> Result<bool> {
+    let entries = match recover(fs::read_dir(path), failures.as_deref_mut(), path, Phase::Read)? {
+        Some(entries) => entries,
+        None => return Ok(false),
+    };
+    let mut removed = true;
+    for entry in entries {
+        check(cancel)?;
+        let entry = match recover(entry, failures.as_deref_mut(), path, Phase::Read)? {
+            Some(entry) => entry,
+            None => {
+                removed = false;
+                continue;
+            }
+        };
+        let entry_path = entry.path();
+        let file_type = entry.file_type();
+        let is_dir = match recover(file_type, failures.as_deref_mut(), &entry_path, Phase::Read)? {
+            Some(file_type) => file_type.is_dir(),
+            None => {
+                removed = false;
+                continue;
+            }
+        };
+        if is_dir {
+            removed &= remove_tree(&entry_path, removed_bytes, cancel, failures.as_deref_mut())?;
+            continue;
+        }
+        let size = entry.metadata().map_or(0, |metadata| metadata.len());
+        let removal = fs::remove_file(&entry_path);
+        match recover(removal, failures.as_deref_mut(), &entry_path, Phase::Remove)? {
+            Some(()) => *removed_bytes += size,
+            None => removed = false,
+        }
+    }
+    if !removed {
+        return Ok(false);
+    }
+    Ok(recover(fs::remove_dir(path), failures, path, Phase::Remove)?.is_some())
+}
+
+/// `dir::copy`, `dir::move_dir` and their `_with_progress` versions. Without a
+/// handler an existing target fails the transfer, like the plain versions do.
+pub(crate) fn transfer_dir(from: &Path,
+                           to: &Path,
+                           options: &Options,
+                           action: Action,
+                           handler: Option<Handler>,
+                           cancel: Option<&Cancel>,
+                           report: &mut Report)
+                           -> Result<u64> {
+    let mut transfer = Transfer::new(options, action, handler, cancel, report);
+    let result = transfer.dir(from, to, true);
+    transfer.finish(result, report)
+}
+
+/// `copy_items`, `move_items` and their `_with_progress` versions.
+pub(crate) fn transfer_items(from: &[PathBuf],
+                             to: &Path,
+                             options: &Options,
+                             action: Action,
+                             handler: Option<Handler>,
+                             cancel: Option<&Cancel>,
+                             report: &mut Report)
+                             -> Result<u64> {
+    check_items_options(options, &action)?;
+    let mut transfer = Transfer::new(options, action, handler, cancel, report);
+    let result = transfer.items(from, to);
+    let result = transfer.finish(result, report);
+    report.items.resize_with(from.len(), Outcome::not_attempted);
+    result
+}
+
+fn check_items_options(options: &Options, action: &Action) -> Result<()> {
+    if options.dir.content_only {
+        let name = match *action {
+            Action::Copy => "copy_items",
+            Action::Move => "move_items",
+        };
+        err!(ErrorKind::Other, "Options 'content_only' not access for {} function", name);
+    }
+    Ok(())
+}
+
+struct Transfer<'h, 'c> {
+    options: Options,
+    action: Action,
+    handler: Option<Handler<'h>>,
+    cancel: Option<&'c Cancel<'c>>,
+    // Only kept in continue-on-error mode.
+    failures: Option<Vec<Failure>>,
+    // Outcomes of the paths of a batch, with failure indexes into `failures`.
+    items: Vec<Outcome>,
+    warnings: Vec<Failure>,
+    // Target of the first copy of each hard-linked file, when the links are
+    // recreated.
+    links: HashMap<LinkId, PathBuf>,
+    copied_bytes: u64,
+    total_bytes: u64,
+    done_files: u64,
+    skipped_files: u64,
+    // Set when a moved file stays in place, its directory is not removed then.
+    kept_source: bool,
+}
+
+impl<'h, 'c> Transfer<'h, 'c> {
+    fn new(options: &Options,
+           action: Action,
+           handler: Option<Handler<'h>>,
+           cancel: Option<&'c Cancel<'c>>,
+           report: &Report)
+           -> Transfer<'h, 'c> {
+        Transfer {
+            options: options.clone(),
+            action,
+            handler,
+            cancel,
+            failures: report.failures.as_ref().map(|_| Vec::new()),
+            items: Vec::new(),
+            warnings: Vec::new(),
+            links: HashMap::new(),
+            copied_bytes: 0,
+            total_bytes: 0,
+            done_files: 0,
+            skipped_files: 0,
+            kept_source: false,
+        }
+    }
+
+    /// Hands the failures and outcomes over to `report`, which may already
+    /// hold failures of its own.
+    fn finish(&mut self, result: Result<()>, report: &mut Report) -> Result<u64> {
+        let known_failures = report.failure_count();
+        if let (Some(failures), Some(found)) = (report.failures.as_mut(), self.failures.as_mut()) {
+            failures.append(found);
+        }
+        report.items.extend(self.items.drain(..).map(|outcome| {
+            Outcome {
+                failure: outcome.failure.map(|index| index + known_failures),
+                ..outcome
+            }
+        }));
+        report.warnings.append(&mut self.warnings);
+        stop(self.cancel, result.map(|_| self.copied_bytes), self.copied_bytes)
+    }
+
+    fn failure_count(&self) -> usize {
+        self.failures.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Goes past `err` on `path` in continue-on-error mode, keeping the source
+    /// of a move in place. Fails with `err` otherwise.
+    fn skip(&mut self, path: &Path, phase: Phase, err: Error) -> Result<()> {
+        recover(Err::<(), _>(err), self.failures.as_mut(), path, phase)?;
+        self.kept_source = true;
+        Ok(())
+    }
+
+    fn items(&mut self, from: &[PathBuf], to: &Path) -> Result<()> {
+        let filter = self.options.filter.clone();
+        if self.handler.is_some() {
+            for item in from.iter().filter(|item| is_item_accepted(filter.as_ref(), item)) {
+                let (symlinks, hard_links) = (self.options.symlinks, self.options.hard_links);
+                match get_size(item, filter.as_ref(), symlinks, hard_links, self.cancel) {
+                    Ok(size) => self.total_bytes += size,
+                    // Reported by the transfer of the item.
+                    Err(ref err) if self.failures.is_some() && !is_stop(err) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        for item in from {
+            if !is_item_accepted(filter.as_ref(), item) {
+                self.items.push(Outcome::new(&Ok(()), true, 0, None));
+                continue;
+            }
+            let copied_bytes = self.copied_bytes;
+            let known_failures = self.failure_count();
+            let done_files = self.done_files;
+            let skipped_files = self.skipped_files;
+            let result = if item.is_dir() {
+                self.dir(item, to, false)
+            } else {
+                match item.file_name() {
+                    Some(file_name) => self.file_or_link(item, &to.join(file_name)),
+                    None => Err(Error::new(ErrorKind::InvalidFileName, "Invalid file name")),
+                }
+            };
+            // The failures with a phase are recorded where they happened.
+            let result = result.or_else(|err| self.skip(item, Phase::Read, err));
+            let skipped = self.done_files == done_files && self.skipped_files > skipped_files;
+            let failure = if self.failure_count() > known_failures {
+                Some(known_failures)
+            } else {
+                None
+            };
+            self.items.push(Outcome::new(&result,
+                                         skipped,
+                                         self.copied_bytes - copied_bytes,
+                                         failure));
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Transfers the directory `from` into `to`. `standalone` is false inside a
+    /// batch, whose total already covers this directory.
+    fn dir(&mut self, from: &Path, to: &Path, standalone: bool) -> Result<()> {
+        if !from.exists() {
+            err!(path: from,
+                 ErrorKind::NotFound,
+                 "Path \"{}\" does not exist or you don't have access!",
+                 from.display());
+        }
+        if !from.is_dir() {
+            err!(path: from,
+                 ErrorKind::InvalidFolder,
+                 "Path \"{}\" is not a directory!",
+                 from.display());
+        }
+        let dir_name = match from.components().next_back() {
+            Some(val) => val.as_os_str(),
+            None => err!(ErrorKind::InvalidFolder, "Invalid folder from"),
+        };
+        let mut to = to.to_path_buf();
+        if (to.exists() || !self.options.dir.copy_inside) && !self.options.dir.content_only {
+            to.push(dir_name);
+        }
+        let kept_source = mem::replace(&mut self.kept_source, false);
+
+        // fs_extra does not limit the depth of moves, the rest of the tree would
+        // be removed with the source otherwise.
+        let depth = match self.action {
+            Action::Copy => self.options.dir.depth,
+            Action::Move => 0,
+        };
+        let known_failures = self.failure_count();
+        let filter = self.options.filter.as_ref();
+        let symlinks = self.options.symlinks;
+        let hard_links = self.options.hard_links;
+        let failures = self.failures.as_mut();
+        let content = get_dir_content(from,
+                                      depth,
+                                      filter,
+                                      symlinks,
+                                      hard_links,
+                                      self.cancel,
+                                      failures)?;
+        // Neither what failed nor what was filtered out goes with the source.
+        if self.failure_count() > known_failures || content.filtered {
+            self.kept_source = true;
+        }
+        for link in &content.outside {
+            let message = format!("Symlink \"{}\" leads outside of \"{}\"",
+                                  link.display(),
+                                  from.display());
+            self.warnings.push(Failure {
+                path: link.clone(),
+                phase: Phase::Read,
+                error: Error::new(ErrorKind::Other, &message),
+            });
+        }
+        if standalone {
+            self.total_bytes = content.dir_size;
+        }
+        for directory in &content.directories {
+            let dir = to.join(directory.strip_prefix(from)?);
+            if !dir.exists() {
+                let created = create_dir(&dir, false, self.options.dir.copy_inside);
+                match created {
+                    // Nothing below could be written without the top directory,
+                    // which only a batch goes past.
+                    Err(err) if directory == from && standalone => return Err(err),
+                    Err(err) if directory == from => {
+                        return self.skip(&dir, Phase::CreateDir, err);
+                    }
+                    Err(err) => self.skip(&dir, Phase::CreateDir, err)?,
+                    Ok(()) => {}
+                }
+            }
+        }
+
+        // A move takes the followed symlinks along, not what they lead to. They
+        // go first, before a move takes away what they lead to in the tree.
+        let (linked, files): (Vec<_>, Vec<_>) =
+            content.files.iter().partition(|file| content.is_linked(file));
+        for file in linked {
+            let action = mem::replace(&mut self.action, Action::Copy);
+            let result = self.file_or_link(file, &to.join(file.strip_prefix(from)?));
+            self.action = action;
+            result?;
+        }
+        for file in files {
+            self.file_or_link(file, &to.join(file.strip_prefix(from)?))?;
+        }
+        for link in &content.symlinks {
+            self.symlink(link, &to.join(link.strip_prefix(from)?))?;
+        }
+        // Deepest first and once their contents are written, which would change
+        // their times and may need the permissions they lose.
+        let directories = content.directories.iter().zip(&content.directory_metadata).rev();
+        for (directory, metadata) in directories {
+            if directory == from && self.options.dir.content_only {
+                continue;
+            }
+            let dir = to.join(directory.strip_prefix(from)?);
+            // Not there when its creation failed, which is reported already.
+            if dir.is_dir() {
+                let preserved = preserve_metadata(metadata,
+                                                  directory,
+                                                  &dir,
+                                                  self.options.preserve,
+                                                  &mut self.warnings);
+                if let Err(err) = preserved {
+                    self.skip(&dir, Phase::Write, err.into())?;
+                }
+            }
+        }
+        if let Action::Move = self.action {
+            if !self.kept_source {
+                if let Err(err) = remove_dir_all(from) {
+                    self.skip(from, Phase::Remove, err)?;
+                }
+            }
+        }
+        self.kept_source |= kept_source;
+        Ok(())
+    }
+
+    /// Recreates the symlink `from` at `to`. A conflict is not passed to the
+    /// handler, the options alone decide.
+    fn symlink(&mut self, from: &Path, to: &Path) -> Result<()> {
+        check(self.cancel)?;
+        let options = &self.options.dir;
+        if options.skip_exist && !options.overwrite && to.symlink_metadata().is_ok() {
+            self.kept_source = true;
+            self.skipped_files += 1;
+            return Ok(());
+        }
+        match copy_symlink(from, to, options.overwrite) {
+            Ok(size) => {
+                self.copied_bytes += size;
+                self.done_files += 1;
+            }
+            Err((Phase::Read, err)) => return self.skip(from, Phase::Read, err),
+            Err((phase, err)) => return self.skip(to, phase, err),
+        }
+        if let Action::Move = self.action {
+            if let Err(err) = fs::remove_file(from) {
+                self.skip(from, Phase::Remove, err.into())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Transfers `from` like `file`, or links `to` to the copy of a file it
+    /// shares its data with when the hard links are recreated.
+    fn file_or_link(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let metadata = match from.metadata() {
+            Ok(metadata) if self.options.hard_links => metadata,
+            // Reported by the transfer of the file.
+            _ => return self.file(from, to),
+        };
+        // Looked up by identity alone, a move may have removed the other links
+        // of the file already.
+        if let Some(first) = get_file_id(&metadata).and_then(|id| self.links.get(&id)).cloned() {
+            return self.hard_link(from, &first, to);
+        }
+        let id = match get_link_id(&metadata) {
+            Some(id) => id,
+            None => return self.file(from, to),
+        };
+        let done_files = self.done_files;
+        self.file(from, to)?;
+        if self.done_files > done_files {
+            self.links.insert(id, to.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Creates `to` as a hard link to `first`, for `from` that shares its data
+    /// with the source of `first`. A conflict is not passed to the handler, the
+    /// options alone decide.
+    fn hard_link(&mut self, from: &Path, first: &Path, to: &Path) -> Result<()> {
+        check(self.cancel)?;
+        let options = &self.options.dir;
+        if options.skip_exist && !options.overwrite && to.symlink_metadata().is_ok() {
+            self.kept_source = true;
+            self.skipped_files += 1;
+            return Ok(());
+        }
+        let linked = remove_existing(to, options.overwrite)
+            .and_then(|_| fs::hard_link(first, to).map_err(at(Phase::Write, to)));
+        if let Err((phase, err)) = linked {
+            return self.skip(to, phase, err);
+        }
+        self.done_files += 1;
+        if let Action::Move = self.action {
+            if let Err(err) = fs::remove_file(from) {
+                self.skip(from, Phase::Remove, err.into())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn file(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let mut file_options = FileOptions {
+            file: file::CopyOptions {
+                overwrite: self.options.dir.overwrite,
+                skip_exist: self.options.dir.skip_exist,
+                buffer_size: self.options.dir.buffer_size,
+            },
+            preserve: self.options.preserve,
+        };
+        let file_name = from.file_name().unwrap_or_default();
+        let file_total_bytes = match self.handler {
+            Some(_) => match from.metadata() {
+                Ok(metadata) => metadata.len(),
+                Err(err) => return self.skip(from, Phase::Read, err.into()),
+            },
+            None => 0,
+        };
+
+        check(self.cancel)?;
+        loop {
+            let skip = file_options.file.skip_exist && !file_options.file.overwrite && to.exists();
+            let result = match self.handler {
+                Some(ref mut handler) => {
+                    let copied_bytes = self.copied_bytes;
+                    let total_bytes = self.total_bytes;
+                    // Outside of a conflict only `Abort` counts, it stops the
+                    // transfer after this buffer.
+                    let mut progress = |file_bytes_copied, _| {
+                        let answer = handler(&Progress {
+                            copied_bytes: copied_bytes + file_bytes_copied,
+                            total_bytes,
+                            file_bytes_copied,
+                            file_total_bytes,
+                            file_name,
+                            state: TransitState::Normal,
+                        });
+                        !matches!(answer, TransitProcessResult::Abort)
+                    };
+                    transfer_file(&self.action,
+                                  from,
+                                  to,
+                                  &file_options,
+                                  Some(&mut progress),
+                                  self.cancel,
+                                  &mut self.warnings)
+                }
+                None => {
+                    transfer_file(&self.action,
+                                  from,
+                                  to,
+                                  &file_options,
+                                  None,
+                                  self.cancel,
+                                  &mut self.warnings)
+                }
+            };
+            let (phase, err) = match result {
+                Ok(size) => {
+                    self.copied_bytes += size;
+                    self.kept_source |= skip;
+                    if skip {
+                        self.skipped_files += 1;
+                    } else {
+                        self.done_files += 1;
+                    }
+                    return Ok(());
+                }
+                Err(failure) => failure,
+            };
+            let path = match phase {
+                Phase::Read | Phase::Remove => from,
+                Phase::Write | Phase::CreateDir => to,
+            };
+
+            let state = match err.kind {
+                ErrorKind::AlreadyExists => TransitState::Exists,
+                ErrorKind::PermissionDenied => TransitState::NoAccess,
+                _ => return self.skip(path, phase, err),
+            };
+            let handler = match self.handler {
+                Some(ref mut handler) => handler,
+                None => return self.skip(path, phase, err),
+            };
+            let decision = handler(&Progress {
+                copied_bytes: self.copied_bytes,
+                total_bytes: self.total_bytes,
+                file_bytes_copied: 0,
+                file_total_bytes,
+                file_name,
+                state: state.clone(),
+            });
+            match decision {
+                TransitProcessResult::Overwrite | TransitProcessResult::OverwriteAll
+                    if state == TransitState::NoAccess => {
+                    err!(ErrorKind::Other, "Overwrite denied for this situation!");
+                }
+                TransitProcessResult::Overwrite => file_options.file.overwrite = true,
+                TransitProcessResult::OverwriteAll => {
+                    file_options.file.overwrite = true;
+                    self.options.dir.overwrite = true;
+                }
+                TransitProcessResult::Skip => {
+                    self.kept_source = true;
+                    self.skipped_files += 1;
+                    return Ok(());
+                }
+                TransitProcessResult::SkipAll => {
+                    self.options.dir.skip_exist = true;
+                    self.kept_source = true;
+                    self.skipped_files += 1;
+                    return Ok(());
+                }
+                TransitProcessResult::Retry => {}
+                TransitProcessResult::ContinueOrAbort => return self.skip(path, phase, err),
+                TransitProcessResult::Abort => return Err(err),
+            }
+        }
+    }
+}
+
+/// Creates a symlink at `to` with the target of the one at `from`, see
+/// `remove_existing`. Returns the size of the link.
+fn copy_symlink(from: &Path, to: &Path, overwrite: bool) -> Phased<u64> {
+    let target = fs::read_link(from).map_err(at(Phase::Read, from))?;
+    let size = from.symlink_metadata().map_err(at(Phase::Read, from))?.len();
+    remove_existing(to, overwrite)?;
+    create_symlink(&target, to, from.is_dir()).map_err(at(Phase::Write, to))?;
+    Ok(size)
+}
+
+/// Makes room for a link at `to`, removing what is there with `overwrite`
+/// unless it is a directory.
+fn remove_existing(to: &Path, overwrite: bool) -> Phased<()> {
+    if let Ok(existing) = to.symlink_metadata() {
+        if !overwrite || existing.is_dir() {
+            let message = format!("Path \"{}\" exists", to.display());
+            let err = Error::new(ErrorKind::AlreadyExists, &message).with_path(to);
+            return Err((Phase::Write, err));
+        }
+        fs::remove_file(to).map_err(at(Phase::Write, to))?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path, _: bool) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+fn transfer_file(action: &Action,
+                 from: &Path,
+                 to: &Path,
+                 options: &FileOptions,
+                 progress: Option<FileHandler>,
+                 cancel: Option<&Cancel>,
+                 warnings: &mut Vec<Failure>)
+                 -> Phased<u64> {
+    match *action {
+        Action::Copy => copy_file_phased(from, to, options, progress, cancel, warnings),
+        Action::Move => move_file_phased(from, to, options, progress, cancel, warnings),
+    }
+}
+
+/// What a transfer would do with one path, see `plan_dir`.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum StepAction {
+    CreateDir,
+    CopyFile,
+    CopyLink,
+    HardLink,
+    Overwrite,
+    SkipExisting,
+    RemoveSource,
+}
+
+pub(crate) struct Step {
+    pub action: StepAction,
+    /// The target exists already, or is the target of an earlier step.
+    pub conflict: bool,
+    pub size: u64,
+    pub from: PathBuf,
+    /// Empty for `RemoveSource`.
+    pub to: PathBuf,
+}
+
+/// Steps `transfer_dir` would take, found without writing anything. The plan
+/// has no handler: a conflict neither overwritten nor skipped is planned as a
+/// `CopyFile`, which the transfer fails on. Failures other than conflicts are
+/// not foreseen.
+pub(crate) fn plan_dir(from: &Path,
+                       to: &Path,
+                       options: &Options,
+                       action: Action)
+                       -> Result<Vec<Step>> {
+    let mut plan = Plan::new(options, action);
+    plan.dir(from, to)?;
+    Ok(plan.steps)
+}
+
+/// Steps `transfer_items` would take, see `plan_dir`.
+pub(crate) fn plan_items(from: &[PathBuf],
+                         to: &Path,
+                         options: &Options,
+                         action: Action)
+                         -> Result<Vec<Step>> {
+    check_items_options(options, &action)?;
+    let mut plan = Plan::new(options, action);
+    for item in from {
+        if !is_item_accepted(options.filter.as_ref(), item) {
+            continue;
+        }
+        if item.is_dir() {
+            plan.dir(item, to)?;
+            continue;
+        }
+        match item.file_name() {
+            Some(file_name) => plan.file_or_link(item, &to.join(file_name))?,
+            None => err!(ErrorKind::InvalidFileName, "Invalid file name"),
+        }
+    }
+    Ok(plan.steps)
+}
+
+/// Steps `copy_file` or `move_file` would take, see `plan_dir`.
+pub(crate) fn plan_file(from: &Path,
+                        to: &Path,
+                        options: &file::CopyOptions,
+                        action: Action)
+                        -> Result<Vec<Step>> {
+    let options = Options {
+        dir: dir::CopyOptions {
+            overwrite: options.overwrite,
+            skip_exist: options.skip_exist,
+            buffer_size: options.buffer_size,
+            ..dir::CopyOptions::new()
+        },
+        filter: None,
+        preserve: Preserve::default(),
+        symlinks: Symlinks::Follow,
+        hard_links: false,
+    };
+    let mut plan = Plan::new(&options, action);
+    plan.file(from, to)?;
+    Ok(plan.steps)
+}
+
+/// Dry run of `Transfer`, following the same decisions.
+struct Plan {
+    options: Options,
+    action: Action,
+    steps: Vec<Step>,
+    // Targets of the steps so far, they exist by the time the later ones run.
+    targets: HashSet<PathBuf>,
+    // Hard-linked files with a planned copy, when the links are recreated.
+    links: HashSet<LinkId>,
+    kept_source: bool,
+}
+
+impl Plan {
+    fn new(options: &Options, action: Action) -> Plan {
+        Plan {
+            options: options.clone(),
+            action,
+            steps: Vec::new(),
+            targets: HashSet::new(),
+            links: HashSet::new(),
+            kept_source: false,
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.targets.contains(path) || path.exists()
+    }
+
+    fn push(&mut self, action: StepAction, conflict: bool, size: u64, from: &Path, to: &Path) {
+        // Drops the trailing separator `join` leaves for the top directory.
+        let to: PathBuf = to.components().collect();
+        if action != StepAction::RemoveSource {
+            self.targets.insert(to.clone());
+        }
+        self.steps.push(Step {
+            action,
+            conflict,
+            size,
+            from: from.to_path_buf(),
+            to,
+        });
+    }
+
+    fn dir(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if !from.exists() {
+            err!(path: from,
+                 ErrorKind::NotFound,
+                 "Path \"{}\" does not exist or you don't have access!",
+                 from.display());
+        }
+        if !from.is_dir() {
+            err!(path: from,
+                 ErrorKind::InvalidFolder,
+                 "Path \"{}\" is not a directory!",
+                 from.display());
+        }
+        let dir_name = match from.components().next_back() {
+            Some(val) => val.as_os_str(),
+            None => err!(ErrorKind::InvalidFolder, "Invalid folder from"),
+        };
+        let mut to = to.to_path_buf();
+        if (self.exists(&to) || !self.options.dir.copy_inside) && !self.options.dir.content_only {
+            to.push(dir_name);
+        }
+        let kept_source = mem::replace(&mut self.kept_source, false);
+
+        let depth = match self.action {
+            Action::Copy => self.options.dir.depth,
+            Action::Move => 0,
+        };
+        let filter = self.options.filter.as_ref();
+        let symlinks = self.options.symlinks;
+        let hard_links = self.options.hard_links;
+        let content = get_dir_content(from, depth, filter, symlinks, hard_links, None, None)?;
+        if content.filtered {
+            self.kept_source = true;
+        }
+        for directory in &content.directories {
+            let dir = to.join(directory.strip_prefix(from)?);
+            if !self.exists(&dir) {
+                self.push(StepAction::CreateDir, false, 0, directory, &dir);
+            }
+        }
+        for file in &content.files {
+            let to_file = to.join(file.strip_prefix(from)?);
+            if content.is_linked(file) {
+                let action = mem::replace(&mut self.action, Action::Copy);
+                let result = self.file_or_link(file, &to_file);
+                self.action = action;
+                result?;
+            } else {
+                self.file_or_link(file, &to_file)?;
+            }
+        }
+        for link in &content.symlinks {
+            let size = link.symlink_metadata()?.len();
+            self.copy(StepAction::CopyLink, size, link, &to.join(link.strip_prefix(from)?));
+        }
+        if let Action::Move = self.action {
+            if !self.kept_source {
+                self.push(StepAction::RemoveSource, false, 0, from, Path::new(""));
+            }
+        }
+        self.kept_source |= kept_source;
+        Ok(())
+    }
+
+    /// Plans `file`, or a hard link like `Transfer::file_or_link`.
+    fn file_or_link(&mut self, from: &Path, to: &Path) -> Result<()> {
+        let id = match from.metadata() {
+            Ok(ref metadata) if self.options.hard_links => get_link_id(metadata),
+            _ => None,
+        };
+        match id {
+            Some(id) if self.links.contains(&id) => {
+                let size = from.metadata()?.len();
+                self.copy(StepAction::HardLink, size, from, to);
+            }
+            Some(id) => {
+                if self.file(from, to)? {
+                    self.links.insert(id);
+                }
+            }
+            None => {
+                self.file(from, to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the file gets written, see `copy`.
+    fn file(&mut self, from: &Path, to: &Path) -> Result<bool> {
+        if !from.exists() {
+            err!(path: from,
+                 ErrorKind::NotFound,
+                 "Path \"{}\" does not exist or you don't have access!",
+                 from.display());
+        }
+        if !from.is_file() {
+            err!(path: from, ErrorKind::InvalidFile, "Path \"{}\" is not a file!", from.display());
+        }
+        let size = from.metadata()?.len();
+        Ok(self.copy(StepAction::CopyFile, size, from, to))
+    }
+
+    /// Plans `copy` of `from` to `to`, or what replaces it on a conflict.
+    /// Returns whether `to` gets written.
+    fn copy(&mut self, copy: StepAction, size: u64, from: &Path, to: &Path) -> bool {
+        let conflict = self.exists(to);
+        let replaced = conflict && self.options.dir.overwrite;
+        // A hard link replaces the target without copying anything.
+        let action = if !conflict || replaced && copy == StepAction::HardLink {
+            copy
+        } else if replaced {
+            StepAction::Overwrite
+        } else if self.options.dir.skip_exist {
+            StepAction::SkipExisting
+        } else {
+            copy
+        };
+        self.push(action, conflict, size, from, to);
+        if action == StepAction::SkipExisting || action == copy && conflict && !replaced {
+            self.kept_source = true;
+            return false;
+        }
+        if let Action::Move = self.action {
+            self.push(StepAction::RemoveSource, false, size, from, Path::new(""));
+        }
+        true
+    }
+}
+
+/// `dir::get_details_entry` filling an `Entry` with the OS bytes of the names.
+/// `path` itself is followed, the symlinks below it are not for the size.
+pub(crate) fn get_details_entry(path: &Path, config: &HashSet<DirEntryAttr>) -> Result<Entry> {
+    let metadata = path.metadata()?;
+    get_details_entry_with_meta(path, config, &metadata, Symlinks::Keep, None)
+}
+
+/// `metadata` is of the link itself for a symlink that is not followed. A size
+/// that cannot be taken is 0 and recorded in `failures` when given.
+fn get_details_entry_with_meta(path: &Path,
+                               config: &HashSet<DirEntryAttr>,
+                               metadata: &Metadata,
+                               symlinks: Symlinks,
+                               failures: Option<&mut Vec<Failure>>)
+                               -> Result<Entry> {
+    let mut entry = get_default_entry();
+    let set = |field: &mut *const c_char, value: Option<&OsStr>| {
+        let old = mem::replace(field, get_os_c_string(value.unwrap_or_default()).into_raw());
+        unsafe { free_c_string(old) }
+    };
+
+    if config.contains(&DirEntryAttr::Name) {
+        if metadata.is_dir() {
+            set(&mut entry.name, path.file_name());
+        } else {
+            set(&mut entry.name, path.file_stem());
+        }
+    }
+    if config.contains(&DirEntryAttr::Ext) {
+        set(&mut entry.ext, path.extension());
+    }
+    if config.contains(&DirEntryAttr::FullName) {
+        set(&mut entry.full_name, path.file_name());
+    }
+    if config.contains(&DirEntryAttr::Path) {
+        let full_path = get_full_path(path)?;
+        let full_path = full_path.as_os_str();
+        // Drop the `\\?\` verbatim prefix canonicalize adds on Windows.
+        let bytes = full_path.as_encoded_bytes();
+        let full_path = match bytes.strip_prefix(b"\\\\?\\") {
+            Some(rest) => unsafe { OsStr::from_encoded_bytes_unchecked(rest) },
+            None => full_path,
+        };
+        set(&mut entry.path, Some(full_path));
+    }
+    if config.contains(&DirEntryAttr::DosPath) {
+        set(&mut entry.dos_path, Some(get_full_path(path)?.as_os_str()));
+    }
+    if config.contains(&DirEntryAttr::Size) {
+        entry.size = if metadata.file_type().is_symlink() {
+            metadata.len()
+        } else {
+            let size = get_size(path, None, symlinks, false, None);
+            recover(size, failures, path, Phase::Read)?.unwrap_or(0)
+        };
+    }
+    if config.contains(&DirEntryAttr::FileSize) {
+        entry.file_size = metadata.len();
+    }
+    if config.contains(&DirEntryAttr::IsDir) {
+        entry.is_dir = metadata.is_dir();
+    }
+    if config.contains(&DirEntryAttr::IsFile) {
+        entry.is_file = metadata.is_file();
+    }
+    if config.contains(&DirEntryAttr::Modified) {
+        entry.modified = get_system_time(&metadata.modified()?);
+    }
+    if config.contains(&DirEntryAttr::Accessed) {
+        entry.accessed = get_system_time(&metadata.accessed()?);
+    }
+    if config.contains(&DirEntryAttr::Created) {
+        entry.created = get_system_time(&metadata.created()?);
+    }
+    if path.symlink_metadata()?.file_type().is_symlink() {
+        entry.is_symlink = true;
+        set(&mut entry.link_target, Some(fs::read_link(path)?.as_os_str()));
+    }
+    Ok(entry)
+}
+
+fn get_full_path(path: &Path) -> Result<PathBuf> {
+    if let Ok(full_path) = path.canonicalize() {
+        return Ok(full_path);
+    }
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => err!(ErrorKind::Other, "Error get parent path"),
+    };
+    match path.file_name() {
+        Some(name) => Ok(parent.canonicalize()?.join(name)),
+        None => err!(ErrorKind::Other, "Error get part name path"),
+    }
+}
+
+/// `dir::ls`, the base entry is only filled in with `DirEntryAttr::BaseInfo`.
+/// The symlinks in `path` are listed as `symlinks` says, a dangling one as the
+/// link even when they are followed. The sizes that cannot be taken, of a
+/// looping or unreadable directory, are 0 and come back as failures.
+pub(crate) fn ls(path: &Path,
+                 config: &HashSet<DirEntryAttr>,
+                 filter: Option<&Filter>,
+                 symlinks: Symlinks)
+                 -> Result<(Entry, Vec<Entry>, Vec<Failure>)> {
+    if !path.is_dir() {
+        err!(ErrorKind::InvalidFolder, "Path does not directory");
+    }
+    let mut items = Vec::new();
+    let mut failures = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let mut metadata = entry.metadata()?;
+        if metadata.file_type().is_symlink() {
+            match symlinks {
+                Symlinks::Follow => {
+                    if let Ok(target) = entry_path.metadata() {
+                        metadata = target;
+                    }
+                }
+                Symlinks::Keep => {}
+                Symlinks::Skip => continue,
+            }
+        }
+        if is_accepted(filter, path, &entry_path, &metadata) {
+            let failures = Some(&mut failures);
+            items.push(get_details_entry_with_meta(&entry_path, config, &metadata, symlinks,
+                                                   failures)?);
+        }
+    }
+    let base = if config.contains(&DirEntryAttr::BaseInfo) {
+        let metadata = path.metadata()?;
+        get_details_entry_with_meta(path, config, &metadata, symlinks, Some(&mut failures))?
+    } else {
+        get_default_entry()
+    };
+    Ok((base, items, failures))
+}
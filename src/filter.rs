@@ -0,0 +1,447 @@
+// Filters for the directory operations. A `Filter` is built up by the caller,
+// then attached to `CopyOptions.filter` or handed to `dir_get_size` and
+// `dir_ls`, and decides which entries of a tree the operation sees.
+
+use std::fs::Metadata;
+use std::os::raw::{c_char, c_void};
+use std::path::{Component, Path};
+use std::time::{Duration, UNIX_EPOCH};
+
+use super::{Error, SystemTime, U64Result, get_invalid_argument_error,
+            get_null_pointer_error, get_os_c_string, get_str, guard, guard_free};
+
+type Predicate = extern "C" fn(*const c_char, bool, u64, *mut c_void) -> bool;
+
+/// Filter rules, created with `filter_new` and released with `filter_free`. An
+/// entry is left out when it matches an exclude pattern; a file also when
+/// there are include patterns and it matches none of them, or when its size or
+/// modification time is out of range. Leaving out a directory leaves out
+/// everything below it.
+#[derive(Clone)]
+pub struct Filter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    min_size: u64,
+    max_size: u64,
+    modified_after: Option<Duration>,
+    modified_before: Option<Duration>,
+    predicate: Option<(Predicate, *mut c_void)>,
+}
+
+// The predicate runs on the thread of the operation, jobs included, which the
+// caller agrees to by setting it.
+unsafe impl Send for Filter {}
+
+impl Filter {
+    /// Whether the entry at `path` is kept. `relative` is its path below the
+    /// directory the operation walks, or its name for an item of a batch.
+    pub(crate) fn accepts(&self, path: &Path, relative: &Path, metadata: &Metadata) -> bool {
+        let relative = get_pattern_path(relative);
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        let is_dir = metadata.is_dir();
+        let matches = |pattern: &Pattern| {
+            (is_dir || !pattern.dir_only) &&
+            pattern.matches(if pattern.anchored { &relative } else { name })
+        };
+        if self.exclude.iter().any(&matches) {
+            return false;
+        }
+        if !is_dir {
+            if !self.include.is_empty() && !self.include.iter().any(&matches) {
+                return false;
+            }
+            if metadata.len() < self.min_size || metadata.len() > self.max_size {
+                return false;
+            }
+            if !self.accepts_modified(metadata) {
+                return false;
+            }
+        }
+        match self.predicate {
+            Some((predicate, user_data)) => {
+                let path = get_os_c_string(path.as_os_str());
+                predicate(path.as_ptr(), is_dir, metadata.len(), user_data)
+            }
+            None => true,
+        }
+    }
+
+    fn accepts_modified(&self, metadata: &Metadata) -> bool {
+        if self.modified_after.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+        let modified = match metadata.modified().map(|time| time.duration_since(UNIX_EPOCH)) {
+            Ok(Ok(modified)) => modified,
+            _ => return false,
+        };
+        self.modified_after.is_none_or(|after| modified >= after) &&
+        self.modified_before.is_none_or(|before| modified < before)
+    }
+}
+
+/// `relative` with `/` between the names, the form patterns are matched on.
+fn get_pattern_path(relative: &Path) -> String {
+    let names: Vec<_> = relative.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    names.join("/")
+}
+
+/// Glob pattern. `*` and `?` match within a name, `**` across names and
+/// `[...]` one character of a set, `[!...]` one outside it. A pattern with a
+/// `/` is matched against the whole relative path, one without against the
+/// name only, and one ending in `/` only matches directories.
+#[derive(Clone)]
+struct Pattern {
+    tokens: Vec<Token>,
+    anchored: bool,
+    dir_only: bool,
+}
+
+#[derive(Clone)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyName,
+    AnyPath,
+    // `**/`, which also matches no directory at all.
+    AnyDirs,
+    Class(bool, Vec<(char, char)>),
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Result<Pattern, Error> {
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() {
+            return Err(get_invalid_argument_error("Empty pattern"));
+        }
+        let anchored = pattern.contains('/');
+        let mut chars = pattern.trim_start_matches('/').chars().peekable();
+        let mut tokens = Vec::new();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::AnyChar,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::AnyDirs
+                    } else {
+                        Token::AnyPath
+                    }
+                }
+                '*' => Token::AnyName,
+                '[' => {
+                    let negated = chars.peek() == Some(&'!');
+                    if negated {
+                        chars.next();
+                    }
+                    let mut ranges = Vec::new();
+                    loop {
+                        let start = match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(start) => start,
+                            None => {
+                                let message = format!("Unclosed '[' in pattern \"{}\"", pattern);
+                                return Err(get_invalid_argument_error(&message));
+                            }
+                        };
+                        let mut end = start;
+                        if chars.peek() == Some(&'-') {
+                            chars.next();
+                            match chars.peek() {
+                                Some(&']') | None => ranges.push(('-', '-')),
+                                Some(&c) => {
+                                    chars.next();
+                                    end = c;
+                                }
+                            }
+                        }
+                        ranges.push((start, end));
+                    }
+                    Token::Class(negated, ranges)
+                }
+                c => Token::Char(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Pattern {
+            tokens,
+            anchored,
+            dir_only,
+        })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        match_tokens(&self.tokens, &text)
+    }
+}
+
+/// Walks the tokens one by one, keeping the positions in `text` they can reach
+/// so far. Unlike backtracking this takes tokens × characters steps whatever
+/// stars the pattern has.
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let mut reached = vec![false; text.len() + 1];
+    reached[0] = true;
+    for token in tokens {
+        let mut next = vec![false; text.len() + 1];
+        // Whether a star before this position can stretch up to it.
+        let mut stretching = false;
+        for (index, &at) in reached.iter().enumerate() {
+            let c = text.get(index).copied();
+            match *token {
+                Token::AnyName => {
+                    stretching |= at;
+                    next[index] = stretching;
+                    if c == Some('/') {
+                        stretching = false;
+                    }
+                }
+                Token::AnyPath => {
+                    stretching |= at;
+                    next[index] = stretching;
+                }
+                Token::AnyDirs => {
+                    stretching |= at;
+                    next[index] |= at;
+                    if stretching && c == Some('/') {
+                        next[index + 1] = true;
+                    }
+                }
+                ref token => {
+                    if at && c.is_some_and(|c| matches_char(token, c)) {
+                        next[index + 1] = true;
+                    }
+                }
+            }
+        }
+        if !next.contains(&true) {
+            return false;
+        }
+        reached = next;
+    }
+    reached[text.len()]
+}
+
+fn matches_char(token: &Token, c: char) -> bool {
+    match *token {
+        Token::Char(expected) => c == expected,
+        Token::AnyChar => c != '/',
+        Token::Class(negated, ref ranges) => {
+            c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != negated
+        }
+        Token::AnyName | Token::AnyPath | Token::AnyDirs => false,
+    }
+}
+
+fn get_duration(time: &SystemTime) -> Duration {
+    Duration::new(time.tv_sec, time.tv_nsec)
+}
+
+unsafe fn get_filter<'a>(filter: *mut Filter) -> Result<&'a mut Filter, Error> {
+    filter.as_mut().ok_or_else(|| get_null_pointer_error("Invalid filter"))
+}
+
+/// New filter that keeps everything.
+#[no_mangle]
+pub extern "C" fn filter_new() -> *mut Filter {
+    Box::into_raw(Box::new(Filter {
+        include: Vec::new(),
+        exclude: Vec::new(),
+        min_size: 0,
+        max_size: u64::MAX,
+        modified_after: None,
+        modified_before: None,
+        predicate: None,
+    }))
+}
+
+/// Adds an include pattern. Once there is one, only the files matching one of
+/// them are kept; directories are still walked.
+#[no_mangle]
+pub unsafe extern "C" fn filter_include(filter: *mut Filter,
+                                        pattern: *const c_char)
+                                        -> *mut U64Result {
+    guard(|| {
+        let filter = get_filter(filter)?;
+        let pattern = get_str(pattern, "Invalid pattern")?;
+        filter.include.push(Pattern::new(pattern)?);
+        Ok(0)
+    })
+}
+
+/// Adds an exclude pattern, which leaves out the files and directories it
+/// matches.
+#[no_mangle]
+pub unsafe extern "C" fn filter_exclude(filter: *mut Filter,
+                                        pattern: *const c_char)
+                                        -> *mut U64Result {
+    guard(|| {
+        let filter = get_filter(filter)?;
+        let pattern = get_str(pattern, "Invalid pattern")?;
+        filter.exclude.push(Pattern::new(pattern)?);
+        Ok(0)
+    })
+}
+
+/// Keeps the files of `min_size` to `max_size` bytes, both included.
+#[no_mangle]
+pub unsafe extern "C" fn filter_size(filter: *mut Filter,
+                                     min_size: u64,
+                                     max_size: u64)
+                                     -> *mut U64Result {
+    guard(|| {
+        let filter = get_filter(filter)?;
+        if min_size > max_size {
+            return Err(get_invalid_argument_error("Invalid size range: min_size > max_size"));
+        }
+        filter.min_size = min_size;
+        filter.max_size = max_size;
+        Ok(0)
+    })
+}
+
+/// Keeps the files modified at or after `after` and before `before`. A NULL
+/// bound is open.
+#[no_mangle]
+pub unsafe extern "C" fn filter_modified(filter: *mut Filter,
+                                         after: *const SystemTime,
+                                         before: *const SystemTime)
+                                         -> *mut U64Result {
+    guard(|| {
+        let filter = get_filter(filter)?;
+        let after = after.as_ref().map(get_duration);
+        let before = before.as_ref().map(get_duration);
+        if let (Some(after), Some(before)) = (after, before) {
+            if after > before {
+                return Err(get_invalid_argument_error("Invalid time range: after > before"));
+            }
+        }
+        filter.modified_after = after;
+        filter.modified_before = before;
+        Ok(0)
+    })
+}
+
+/// Asks `cb` about every entry the other rules keep, with its path as OS bytes,
+/// whether it is a directory and its size; returning false leaves it out. A
+/// NULL `cb` removes the predicate. `user_data` is passed back unchanged, from
+/// the thread of the operation.
+#[no_mangle]
+pub unsafe extern "C" fn filter_predicate(filter: *mut Filter,
+                                          cb: Option<extern "C" fn(*const c_char, bool, u64, *mut c_void) -> bool>,
+                                          user_data: *mut c_void)
+                                          -> *mut U64Result {
+    guard(|| {
+        let filter = get_filter(filter)?;
+        filter.predicate = cb.map(|cb| (cb, user_data));
+        Ok(0)
+    })
+}
+
+/// Releases `filter`, which must not be in use by a running blocking call. Jobs
+/// started with it keep a copy of their own.
+#[no_mangle]
+pub unsafe extern "C" fn filter_free(filter: *mut Filter) {
+    guard_free(|| {
+        if !filter.is_null() {
+            drop(Box::from_raw(filter));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::Pattern;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Pattern::new(pattern).ok().expect("valid pattern").matches(text)
+    }
+
+    #[test]
+    fn literal_and_any_char() {
+        assert!(matches("Cargo.toml", "Cargo.toml"));
+        assert!(!matches("Cargo.toml", "Cargo.tom"));
+        assert!(matches("file?.txt", "file1.txt"));
+        assert!(!matches("file?.txt", "file.txt"));
+        assert!(!matches("a?c", "a/c"));
+    }
+
+    #[test]
+    fn star_stays_within_a_name() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/engine/main.rs"));
+        assert!(matches("a*b*c", "aXbYc"));
+        assert!(!matches("a*b*c", "aXbY"));
+    }
+
+    #[test]
+    fn double_star_crosses_names() {
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/a/b/main.rs"));
+        assert!(!matches("src/**/*.rs", "lib/main.rs"));
+        assert!(matches("**/target", "target"));
+        assert!(matches("**/target", "a/b/target"));
+        assert!(!matches("**/target", "a/btarget"));
+        assert!(matches("docs/**", "docs/a/b.md"));
+        assert!(matches("a**z", "a/b/z"));
+    }
+
+    #[test]
+    fn stars_take_linear_steps() {
+        let name = "a".repeat(5000);
+        let path = vec!["name"; 2000].join("/") + "/y";
+        let start = Instant::now();
+        assert!(!matches("*a*a*a*a*b", &name));
+        assert!(matches("*a*a*a*a*", &name));
+        assert!(!matches("**/**/**/x", &path));
+        assert!(matches("**/**/**/y", &path));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("[!abc].txt", "d.txt"));
+        assert!(!matches("[!abc].txt", "a.txt"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[]]", "]"));
+        assert!(!matches("a[!x]b", "a/b"));
+    }
+
+    #[test]
+    fn anchoring_and_directories() {
+        let pattern = Pattern::new("target/").ok().unwrap();
+        assert!(pattern.dir_only);
+        assert!(!pattern.anchored);
+        assert!(pattern.matches("target"));
+
+        let pattern = Pattern::new("/build").ok().unwrap();
+        assert!(pattern.anchored);
+        assert!(pattern.matches("build"));
+
+        assert!(Pattern::new("src/main.rs").ok().unwrap().anchored);
+        assert!(!Pattern::new("main.rs").ok().unwrap().anchored);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Pattern::new("").is_err());
+        assert!(Pattern::new("/").is_err());
+        assert!(Pattern::new("[abc").is_err());
+        assert!(Pattern::new("[!").is_err());
+    }
+}
@@ -0,0 +1,621 @@
+// Background jobs. Each operation started here runs on its own thread and is
+// followed through an opaque `Job` handle: polled with `job_progress`, waited
+// for with `job_wait` and collected with `job_result`.
+
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use fs_extra::dir::TransitProcessResult;
+use libc::size_t;
+
+use cancel::{CancelToken, TokenState, get_token_state};
+use engine::{self, Action};
+use filter::Filter;
+use super::{CopyOptions, Error, FfiPath, FfiResult, OsPath, U64Result, get_continue_on_error,
+            get_file_copy_options, get_invalid_argument_error, get_null_pointer_error,
+            get_path_error, get_path_list, get_symlinks, get_transfer_options, guard,
+            guard_free, run_operation};
+
+pub const JOB_STATE_RUNNING: u8 = 0;
+pub const JOB_STATE_FINISHED: u8 = 1;
+pub const JOB_STATE_PAUSED: u8 = 2;
+
+/// `job_wait` timeout that never expires.
+pub const JOB_WAIT_FOREVER: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+
+/// Snapshot of a job returned by `job_progress`. The byte counts stay 0 for
+/// jobs that do not copy anything.
+#[repr(C)]
+pub struct JobProgress {
+    /// One of the `JOB_STATE_*` values.
+    pub state: u8,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+    pub file_bytes_copied: u64,
+    pub file_total_bytes: u64,
+}
+
+/// Handle of a background operation, released with `job_free`.
+pub struct Job {
+    shared: Arc<Shared>,
+}
+
+// State shared between a handle and the thread running the job.
+struct Shared {
+    state: AtomicU8,
+    copied_bytes: AtomicU64,
+    total_bytes: AtomicU64,
+    file_bytes_copied: AtomicU64,
+    file_total_bytes: AtomicU64,
+    pause: engine::Pause,
+    // Of the token the job was started with, a fresh one without.
+    token: Arc<TokenState>,
+    // Taken by `job_result`, so it is `None` both before the end and after.
+    result: Mutex<Option<U64Result>>,
+    finished: Condvar,
+}
+
+type ResultSlot<'a> = MutexGuard<'a, Option<U64Result>>;
+
+impl Shared {
+    fn new(token: Arc<TokenState>) -> Shared {
+        Shared {
+            state: AtomicU8::new(JOB_STATE_RUNNING),
+            copied_bytes: AtomicU64::new(0),
+            total_bytes: AtomicU64::new(0),
+            file_bytes_copied: AtomicU64::new(0),
+            file_total_bytes: AtomicU64::new(0),
+            pause: engine::Pause::new(),
+            token,
+            result: Mutex::new(None),
+            finished: Condvar::new(),
+        }
+    }
+
+    fn lock_result(&self) -> ResultSlot<'_> {
+        self.result.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn finish(&self, result: U64Result) {
+        let mut slot = self.lock_result();
+        *slot = Some(result);
+        self.state.store(JOB_STATE_FINISHED, Ordering::SeqCst);
+        self.finished.notify_all();
+    }
+
+    fn is_finished(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == JOB_STATE_FINISHED
+    }
+
+    /// Blocks until the job is finished or `timeout` passed, `None` waits for
+    /// as long as it takes.
+    fn wait(&self, timeout: Option<Duration>) -> ResultSlot<'_> {
+        let slot = self.lock_result();
+        let running = |_: &mut Option<U64Result>| !self.is_finished();
+        match timeout {
+            Some(timeout) => {
+                self.finished
+                    .wait_timeout_while(slot, timeout, running)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+            None => self.finished.wait_while(slot, running).unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    /// Progress handler of directory and batch transfers. Conflicts fail the
+    /// job, the same as without a handler.
+    fn on_dir_progress(&self, progress: &engine::Progress) -> TransitProcessResult {
+        self.copied_bytes.store(progress.copied_bytes, Ordering::Relaxed);
+        self.total_bytes.store(progress.total_bytes, Ordering::Relaxed);
+        self.file_bytes_copied.store(progress.file_bytes_copied, Ordering::Relaxed);
+        self.file_total_bytes.store(progress.file_total_bytes, Ordering::Relaxed);
+        TransitProcessResult::ContinueOrAbort
+    }
+
+    fn on_file_progress(&self, copied_bytes: u64, total_bytes: u64) -> bool {
+        self.copied_bytes.store(copied_bytes, Ordering::Relaxed);
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.file_bytes_copied.store(copied_bytes, Ordering::Relaxed);
+        self.file_total_bytes.store(total_bytes, Ordering::Relaxed);
+        true
+    }
+}
+
+impl Job {
+    /// Runs `work` on a new thread, stopping or pausing it through `token` when
+    /// given and going on past failed files with `continue_on_error`. A panic in it ends
+    /// the job with a `Panic` error.
+    fn spawn<F>(token: Option<Arc<TokenState>>, continue_on_error: bool, work: F) -> Job
+        where F: FnOnce(&Shared, Option<&engine::Cancel>, &mut engine::Report)
+                        -> Result<u64, Error> + Send + 'static
+    {
+        let token = token.unwrap_or_else(|| Arc::new(TokenState::new()));
+        let shared = Arc::new(Shared::new(token));
+        let worker = shared.clone();
+        thread::spawn(move || {
+            let cancel = engine::Cancel::new(&worker.token.flag,
+                                             vec![&worker.pause, &worker.token.pause]);
+            let result = run_operation(Some(&cancel), continue_on_error, |cancel, report| {
+                work(&worker, cancel, report)
+            });
+            worker.finish(result);
+        });
+        Job { shared }
+    }
+}
+
+// Nobody could resume a job paused past its handle.
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.shared.pause.set(false);
+    }
+}
+
+/// A job whose arguments are rejected is handed out already finished with the
+/// error.
+impl FfiResult for Job {
+    type Ok = Job;
+
+    fn new(result: Result<Job, Error>) -> Job {
+        match result {
+            Ok(job) => job,
+            Err(error) => {
+                let shared = Shared::new(Arc::new(TokenState::new()));
+                shared.finish(U64Result::new(Err(error)));
+                Job { shared: Arc::new(shared) }
+            }
+        }
+    }
+}
+
+unsafe fn do_job_dir_transfer<P: FfiPath>(from: P,
+                                          to: P,
+                                          options: *const CopyOptions,
+                                          action: Action,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    guard(|| {
+        let continue_on_error = get_continue_on_error(options);
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(Job::spawn(get_token_state(cancel), continue_on_error, move |shared, cancel, report| {
+            let mut handle = |progress: &engine::Progress| shared.on_dir_progress(progress);
+            let handler = Some(&mut handle as engine::Handler);
+            Ok(engine::transfer_dir(&from_path, &to_path, &options, action, handler, cancel, report)?)
+        }))
+    })
+}
+
+unsafe fn do_job_file_transfer<P: FfiPath>(from: P,
+                                           to: P,
+                                           options: *const CopyOptions,
+                                           action: Action,
+                                           cancel: *const CancelToken)
+                                           -> *mut Job {
+    guard(|| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(Job::spawn(get_token_state(cancel), false, move |shared, cancel, report| {
+            let mut handle = |copied_bytes, total_bytes| {
+                shared.on_file_progress(copied_bytes, total_bytes)
+            };
+            let handle = Some(&mut handle as engine::FileHandler);
+            let warnings = &mut report.warnings;
+            let result = match action {
+                Action::Copy => engine::copy_file(&from_path, &to_path, &options, handle, cancel, warnings),
+                Action::Move => engine::move_file(&from_path, &to_path, &options, handle, cancel, warnings),
+            };
+            Ok(result?)
+        }))
+    })
+}
+
+unsafe fn do_job_items_transfer<P: FfiPath>(from_list: *const P,
+                                            from_size: size_t,
+                                            to: P,
+                                            options: *const CopyOptions,
+                                            action: Action,
+                                            cancel: *const CancelToken)
+                                            -> *mut Job {
+    guard(|| {
+        let continue_on_error = get_continue_on_error(options);
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(Job::spawn(get_token_state(cancel), continue_on_error, move |shared, cancel, report| {
+            let mut handle = |progress: &engine::Progress| shared.on_dir_progress(progress);
+            let handler = Some(&mut handle as engine::Handler);
+            Ok(engine::transfer_items(&from_list, &to_path, &options, action, handler, cancel, report)?)
+        }))
+    })
+}
+
+/// Starts `dir_copy` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_copy(from: *const c_char,
+                                      to: *const c_char,
+                                      options: *const CopyOptions,
+                                      cancel: *const CancelToken)
+                                      -> *mut Job {
+    do_job_dir_transfer(from, to, options, Action::Copy, cancel)
+}
+
+/// `job_dir_copy` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_copy_os(from: OsPath,
+                                         to: OsPath,
+                                         options: *const CopyOptions,
+                                         cancel: *const CancelToken)
+                                         -> *mut Job {
+    do_job_dir_transfer(from, to, options, Action::Copy, cancel)
+}
+
+/// Starts `dir_move` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_move(from: *const c_char,
+                                      to: *const c_char,
+                                      options: *const CopyOptions,
+                                      cancel: *const CancelToken)
+                                      -> *mut Job {
+    do_job_dir_transfer(from, to, options, Action::Move, cancel)
+}
+
+/// `job_dir_move` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_move_os(from: OsPath,
+                                         to: OsPath,
+                                         options: *const CopyOptions,
+                                         cancel: *const CancelToken)
+                                         -> *mut Job {
+    do_job_dir_transfer(from, to, options, Action::Move, cancel)
+}
+
+/// Starts `file_copy` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_copy(from: *const c_char,
+                                       to: *const c_char,
+                                       options: *const CopyOptions,
+                                       cancel: *const CancelToken)
+                                       -> *mut Job {
+    do_job_file_transfer(from, to, options, Action::Copy, cancel)
+}
+
+/// `job_file_copy` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_copy_os(from: OsPath,
+                                          to: OsPath,
+                                          options: *const CopyOptions,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    do_job_file_transfer(from, to, options, Action::Copy, cancel)
+}
+
+/// Starts `file_move` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_move(from: *const c_char,
+                                       to: *const c_char,
+                                       options: *const CopyOptions,
+                                       cancel: *const CancelToken)
+                                       -> *mut Job {
+    do_job_file_transfer(from, to, options, Action::Move, cancel)
+}
+
+/// `job_file_move` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_move_os(from: OsPath,
+                                          to: OsPath,
+                                          options: *const CopyOptions,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    do_job_file_transfer(from, to, options, Action::Move, cancel)
+}
+
+/// Starts `copy_items` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_copy_items(from_list: *const *const c_char,
+                                        from_size: size_t,
+                                        to: *const c_char,
+                                        options: *const CopyOptions,
+                                        cancel: *const CancelToken)
+                                        -> *mut Job {
+    do_job_items_transfer(from_list, from_size, to, options, Action::Copy, cancel)
+}
+
+/// `job_copy_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_copy_items_os(from_list: *const OsPath,
+                                           from_size: size_t,
+                                           to: OsPath,
+                                           options: *const CopyOptions,
+                                           cancel: *const CancelToken)
+                                           -> *mut Job {
+    do_job_items_transfer(from_list, from_size, to, options, Action::Copy, cancel)
+}
+
+/// Starts `move_items` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_move_items(from_list: *const *const c_char,
+                                        from_size: size_t,
+                                        to: *const c_char,
+                                        options: *const CopyOptions,
+                                        cancel: *const CancelToken)
+                                        -> *mut Job {
+    do_job_items_transfer(from_list, from_size, to, options, Action::Move, cancel)
+}
+
+/// `job_move_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_move_items_os(from_list: *const OsPath,
+                                           from_size: size_t,
+                                           to: OsPath,
+                                           options: *const CopyOptions,
+                                           cancel: *const CancelToken)
+                                           -> *mut Job {
+    do_job_items_transfer(from_list, from_size, to, options, Action::Move, cancel)
+}
+
+unsafe fn do_job_file_remove<P: FfiPath>(path: P) -> *mut Job {
+    guard(|| {
+        let remove_path = path.get_path("Invalid from path")?;
+
+        Ok(Job::spawn(None, false, move |_, _, _| {
+            engine::remove_file(&remove_path)
+                .map_err(|err| get_path_error(err, &remove_path))?;
+            Ok(0)
+        }))
+    })
+}
+
+/// Starts `file_remove` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_remove(path: *const c_char) -> *mut Job {
+    do_job_file_remove(path)
+}
+
+/// `job_file_remove` with an `OsPath` argument.
+#[no_mangle]
+pub unsafe extern "C" fn job_file_remove_os(path: OsPath) -> *mut Job {
+    do_job_file_remove(path)
+}
+
+unsafe fn do_job_dir_remove<P: FfiPath>(path: P,
+                                        continue_on_error: bool,
+                                        cancel: *const CancelToken)
+                                        -> *mut Job {
+    guard(|| {
+        let remove_path = path.get_path("Invalid from path")?;
+
+        Ok(Job::spawn(get_token_state(cancel), continue_on_error, move |_, cancel, report| {
+            engine::remove_dir(&remove_path, cancel, report)
+                .map_err(|err| get_path_error(err, &remove_path))?;
+            Ok(0)
+        }))
+    })
+}
+
+/// Starts `dir_remove` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_remove(path: *const c_char,
+                                        continue_on_error: bool,
+                                        cancel: *const CancelToken)
+                                        -> *mut Job {
+    do_job_dir_remove(path, continue_on_error, cancel)
+}
+
+/// `job_dir_remove` with an `OsPath` argument.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_remove_os(path: OsPath,
+                                           continue_on_error: bool,
+                                           cancel: *const CancelToken)
+                                           -> *mut Job {
+    do_job_dir_remove(path, continue_on_error, cancel)
+}
+
+unsafe fn do_job_remove_items<P: FfiPath>(path_list: *const P,
+                                          path_size: size_t,
+                                          continue_on_error: bool,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    guard(|| {
+        let paths = get_path_list(path_list, path_size, "Invalid from path")?;
+
+        Ok(Job::spawn(get_token_state(cancel), continue_on_error, move |_, cancel, report| {
+            engine::remove_items(&paths, cancel, report)?;
+            Ok(0)
+        }))
+    })
+}
+
+/// Starts `remove_items` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_remove_items(path_list: *const *const c_char,
+                                          path_size: size_t,
+                                          continue_on_error: bool,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    do_job_remove_items(path_list, path_size, continue_on_error, cancel)
+}
+
+/// `job_remove_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn job_remove_items_os(path_list: *const OsPath,
+                                             path_size: size_t,
+                                             continue_on_error: bool,
+                                             cancel: *const CancelToken)
+                                             -> *mut Job {
+    do_job_remove_items(path_list, path_size, continue_on_error, cancel)
+}
+
+unsafe fn do_job_dir_get_size<P: FfiPath>(path: P,
+                                          filter: *const Filter,
+                                          symlinks: u8,
+                                          count_links_once: bool,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    guard(|| {
+        let symlinks = get_symlinks(symlinks)?;
+        let dir_path = path.get_path("Invalid from path")?;
+        let filter = filter.as_ref().cloned();
+
+        Ok(Job::spawn(get_token_state(cancel), false, move |_, cancel, _| {
+            engine::get_size(&dir_path, filter.as_ref(), symlinks, count_links_once, cancel)
+                .map_err(|err| get_path_error(err, &dir_path))
+        }))
+    })
+}
+
+/// Starts `dir_get_size` in the background.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_get_size(path: *const c_char,
+                                          filter: *const Filter,
+                                          symlinks: u8,
+                                          count_links_once: bool,
+                                          cancel: *const CancelToken)
+                                          -> *mut Job {
+    do_job_dir_get_size(path, filter, symlinks, count_links_once, cancel)
+}
+
+/// `job_dir_get_size` with an `OsPath` argument.
+#[no_mangle]
+pub unsafe extern "C" fn job_dir_get_size_os(path: OsPath,
+                                             filter: *const Filter,
+                                             symlinks: u8,
+                                             count_links_once: bool,
+                                             cancel: *const CancelToken)
+                                             -> *mut Job {
+    do_job_dir_get_size(path, filter, symlinks, count_links_once, cancel)
+}
+
+/// Current progress of `job`, a NULL `job` reads as finished.
+#[no_mangle]
+pub unsafe extern "C" fn job_progress(job: *const Job) -> JobProgress {
+    match job.as_ref() {
+        Some(job) => {
+            let shared = &job.shared;
+            let state = match shared.state.load(Ordering::SeqCst) {
+                JOB_STATE_RUNNING if shared.pause.is_paused() || shared.token.pause.is_paused() => {
+                    JOB_STATE_PAUSED
+                }
+                state => state,
+            };
+            JobProgress {
+                state,
+                copied_bytes: shared.copied_bytes.load(Ordering::Relaxed),
+                total_bytes: shared.total_bytes.load(Ordering::Relaxed),
+                file_bytes_copied: shared.file_bytes_copied.load(Ordering::Relaxed),
+                file_total_bytes: shared.file_total_bytes.load(Ordering::Relaxed),
+            }
+        }
+        None => {
+            JobProgress {
+                state: JOB_STATE_FINISHED,
+                copied_bytes: 0,
+                total_bytes: 0,
+                file_bytes_copied: 0,
+                file_total_bytes: 0,
+            }
+        }
+    }
+}
+
+/// Pauses `job` before its next buffer or file, where it waits without losing
+/// its place until `job_resume`. Cancelling a paused job still stops it.
+#[no_mangle]
+pub unsafe extern "C" fn job_pause(job: *const Job) {
+    if let Some(job) = job.as_ref() {
+        job.shared.pause.set(true);
+    }
+}
+
+/// Lets a job paused with `job_pause` continue.
+#[no_mangle]
+pub unsafe extern "C" fn job_resume(job: *const Job) {
+    if let Some(job) = job.as_ref() {
+        job.shared.pause.set(false);
+    }
+}
+
+/// Waits up to `timeout_ms` milliseconds (or forever with `JOB_WAIT_FOREVER`)
+/// for `job` to finish. Returns true once it has, a NULL `job` included.
+#[no_mangle]
+pub unsafe extern "C" fn job_wait(job: *const Job, timeout_ms: u64) -> bool {
+    match job.as_ref() {
+        Some(job) => {
+            let timeout = match timeout_ms {
+                JOB_WAIT_FOREVER => None,
+                timeout_ms => Some(Duration::from_millis(timeout_ms)),
+            };
+            drop(job.shared.wait(timeout));
+            job.shared.is_finished()
+        }
+        None => true,
+    }
+}
+
+/// Waits for `job` to finish and hands over its result, which is released with
+/// `u64_result_free`. The result can be collected once, later calls fail.
+#[no_mangle]
+pub unsafe extern "C" fn job_result(job: *const Job) -> *mut U64Result {
+    let result = match job.as_ref() {
+        Some(job) => job.shared.wait(None).take(),
+        None => Some(U64Result::new(Err(get_null_pointer_error("Invalid job")))),
+    };
+    let result = result.unwrap_or_else(|| {
+        U64Result::new(Err(get_invalid_argument_error("Job result was already collected")))
+    });
+    Box::into_raw(Box::new(result))
+}
+
+/// Releases `job`. A job that is still running, or paused, finishes in the
+/// background and its result is dropped.
+#[no_mangle]
+pub unsafe extern "C" fn job_free(job: *mut Job) {
+    guard_free(|| {
+        if !job.is_null() {
+            drop(Box::from_raw(job));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ERROR_CODE_PANIC, u64_result_free};
+
+    unsafe fn collect(job: Job) -> (bool, u32) {
+        let job = Box::into_raw(Box::new(job));
+        let result = job_result(job);
+        let collected = ((*result).is_error, (*result).error.code);
+        u64_result_free(result);
+        job_free(job);
+        collected
+    }
+
+    #[test]
+    fn panic_in_job_finishes_it_with_error() {
+        let job = Job::spawn(None, false, |_, _, _| panic!("panic in job"));
+        assert_eq!(unsafe { collect(job) }, (true, ERROR_CODE_PANIC));
+    }
+
+    #[test]
+    fn panic_while_building_job_result_finishes_it_with_error() {
+        let job = Job::spawn(None, true, |_, _, report| {
+            report.items.push(engine::Outcome {
+                status: engine::Status::Failed,
+                bytes: 0,
+                failure: Some(1),
+            });
+            Ok(0)
+        });
+        assert_eq!(unsafe { collect(job) }, (true, ERROR_CODE_PANIC));
+    }
+}
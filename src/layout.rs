@@ -0,0 +1,164 @@
+// Layout of every struct that crosses the C boundary. The numbers are what a C
+// compiler produces for the declarations in fs_extra_ffi.h, so any change here
+// is an ABI break and needs FS_EXTRA_FFI_ABI_VERSION bumped, except for fields
+// appended to the structs that carry their own size.
+//
+// Each number is given as [64-bit, 32-bit, 32-bit x86]: 32-bit x86 aligns a
+// `uint64_t` inside a struct to 4 bytes, the other 32-bit targets to 8.
+
+use std::mem;
+
+use super::*;
+
+#[cfg(target_pointer_width = "64")]
+const TARGET: usize = 0;
+#[cfg(all(target_pointer_width = "32", not(target_arch = "x86")))]
+const TARGET: usize = 1;
+#[cfg(all(target_pointer_width = "32", target_arch = "x86"))]
+const TARGET: usize = 2;
+
+macro_rules! assert_layout {
+    ($ty:ident, size: $size:expr, align: $align:expr, { $($field:ident: $offset:expr,)* }) => {
+        const _: () = {
+            assert!(mem::size_of::<$ty>() == $size[TARGET]);
+            assert!(mem::align_of::<$ty>() == $align[TARGET]);
+            $(assert!(mem::offset_of!($ty, $field) == $offset[TARGET]);)*
+        };
+    };
+}
+
+assert_layout!(SystemTime, size: [16, 16, 12], align: [8, 8, 4], {
+    tv_sec: [0, 0, 0],
+    tv_nsec: [8, 8, 8],
+});
+
+assert_layout!(FileTransitProcess, size: [16, 16, 16], align: [8, 8, 4], {
+    copied_bytes: [0, 0, 0],
+    total_bytes: [8, 8, 8],
+});
+
+assert_layout!(DirTransitProcess, size: [48, 40, 40], align: [8, 8, 4], {
+    copied_bytes: [0, 0, 0],
+    total_bytes: [8, 8, 8],
+    file_bytes_copied: [16, 16, 16],
+    file_total_bytes: [24, 24, 24],
+    file_name: [32, 32, 32],
+    state: [40, 36, 36],
+});
+
+assert_layout!(Entry, size: [120, 104, 80], align: [8, 8, 4], {
+    name: [0, 0, 0],
+    ext: [8, 4, 4],
+    full_name: [16, 8, 8],
+    path: [24, 12, 12],
+    dos_path: [32, 16, 16],
+    file_size: [40, 24, 20],
+    size: [48, 32, 28],
+    is_dir: [56, 40, 36],
+    is_file: [57, 41, 37],
+    is_symlink: [58, 42, 38],
+    modified: [64, 48, 40],
+    accessed: [80, 64, 52],
+    created: [96, 80, 64],
+    link_target: [112, 96, 76],
+});
+
+assert_layout!(Error, size: [32, 20, 20], align: [8, 4, 4], {
+    kind: [0, 0, 0],
+    message: [8, 4, 4],
+    code: [16, 8, 8],
+    os_error: [20, 12, 12],
+    path: [24, 16, 16],
+});
+
+assert_layout!(DetailsEntryResult, size: [160, 128, 104], align: [8, 8, 4], {
+    is_error: [0, 0, 0],
+    error: [8, 4, 4],
+    ok: [40, 24, 24],
+});
+
+assert_layout!(LsResult, size: [192, 144, 120], align: [8, 8, 4], {
+    is_error: [0, 0, 0],
+    error: [8, 4, 4],
+    base: [40, 24, 24],
+    size: [160, 128, 104],
+    items: [168, 132, 108],
+    warnings_size: [176, 136, 112],
+    warnings: [184, 140, 116],
+});
+
+assert_layout!(CopyOptions, size: [48, 40, 36], align: [8, 8, 4], {
+    size: [0, 0, 0],
+    overwrite: [8, 4, 4],
+    skip_exist: [9, 5, 5],
+    continue_on_error: [10, 6, 6],
+    copy_inside: [11, 7, 7],
+    content_only: [12, 8, 8],
+    buffer_size: [16, 12, 12],
+    depth: [24, 16, 16],
+    filter: [32, 24, 24],
+    preserve_permissions: [40, 28, 28],
+    preserve_times: [41, 29, 29],
+    preserve_owner: [42, 30, 30],
+    preserve_xattrs: [43, 31, 31],
+    symlinks: [44, 32, 32],
+    preserve_hard_links: [45, 33, 33],
+});
+
+assert_layout!(OsPath, size: [16, 8, 8], align: [8, 4, 4], {
+    data: [0, 0, 0],
+    size: [8, 4, 4],
+});
+
+assert_layout!(JobProgress, size: [40, 40, 36], align: [8, 8, 4], {
+    state: [0, 0, 0],
+    copied_bytes: [8, 8, 4],
+    total_bytes: [16, 16, 12],
+    file_bytes_copied: [24, 24, 20],
+    file_total_bytes: [32, 32, 28],
+});
+
+assert_layout!(FileError, size: [40, 24, 24], align: [8, 4, 4], {
+    phase: [0, 0, 0],
+    error: [8, 4, 4],
+});
+
+assert_layout!(ItemResult, size: [48, 40, 32], align: [8, 8, 4], {
+    status: [0, 0, 0],
+    bytes: [8, 8, 4],
+    error: [16, 16, 12],
+});
+
+assert_layout!(U64Result, size: [96, 56, 56], align: [8, 8, 4], {
+    is_error: [0, 0, 0],
+    error: [8, 4, 4],
+    ok: [40, 24, 24],
+    file_errors_size: [48, 32, 32],
+    file_errors: [56, 36, 36],
+    items_size: [64, 40, 40],
+    items: [72, 44, 44],
+    warnings_size: [80, 48, 48],
+    warnings: [88, 52, 52],
+});
+
+assert_layout!(PlanStep, size: [32, 24, 20], align: [8, 8, 4], {
+    action: [0, 0, 0],
+    conflict: [1, 1, 1],
+    size: [8, 8, 4],
+    from: [16, 16, 12],
+    to: [24, 20, 16],
+});
+
+assert_layout!(PlanResult, size: [64, 40, 40], align: [8, 8, 4], {
+    is_error: [0, 0, 0],
+    error: [8, 4, 4],
+    total_bytes: [40, 24, 24],
+    size: [48, 32, 32],
+    steps: [56, 36, 36],
+});
+
+assert_layout!(CStringResult, size: [48, 28, 28], align: [8, 4, 4], {
+    is_error: [0, 0, 0],
+    error: [8, 4, 4],
+    ok: [40, 24, 24],
+});
@@ -1,216 +1,490 @@
+// Every export takes raw pointers from C, the contract for them lives in the
+// generated header rather than in per-function `# Safety` sections.
+#![allow(clippy::missing_safety_doc)]
+
 extern crate fs_extra;
 extern crate libc;
 
-use libc::{uint64_t, uint32_t, uint8_t, size_t};
-use std::os::raw::c_char;
-use std::collections::{HashSet, HashMap};
-use std::ffi::{CStr, CString};
+use libc::size_t;
+use std::any::Any;
+use std::os::raw::{c_char, c_void};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use fs_extra::{file, dir};
 use std::time::UNIX_EPOCH;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::mem;
-
+use engine::Action;
+
+mod cancel;
+mod engine;
+mod filter;
+mod job;
+mod layout;
+mod plan;
+mod xattr;
+
+pub use cancel::{CancelToken, cancel_token_cancel, cancel_token_free, cancel_token_is_cancelled,
+                 cancel_token_is_paused, cancel_token_new, cancel_token_pause, cancel_token_resume};
+pub use filter::{Filter, filter_exclude, filter_free, filter_include, filter_modified,
+                 filter_new, filter_predicate, filter_size};
+use cancel::{get_cancel, set_cancelled_done};
+pub use job::{Job, JobProgress, JOB_STATE_FINISHED, JOB_STATE_PAUSED, JOB_STATE_RUNNING,
+              JOB_WAIT_FOREVER, job_copy_items, job_copy_items_os, job_dir_copy,
+              job_dir_copy_os, job_dir_get_size, job_dir_get_size_os, job_dir_move,
+              job_dir_move_os, job_dir_remove, job_dir_remove_os, job_file_copy,
+              job_file_copy_os, job_file_move, job_file_move_os, job_file_remove,
+              job_file_remove_os, job_free, job_move_items, job_move_items_os, job_pause,
+              job_progress, job_remove_items, job_remove_items_os, job_result, job_resume,
+              job_wait};
+pub use plan::{PlanResult, PlanStep, PLAN_ACTION_COPY_FILE, PLAN_ACTION_COPY_LINK,
+               PLAN_ACTION_CREATE_DIR, PLAN_ACTION_HARD_LINK, PLAN_ACTION_OVERWRITE,
+               PLAN_ACTION_REMOVE_SOURCE, PLAN_ACTION_SKIP_EXISTING, copy_items_plan,
+               copy_items_plan_os, dir_copy_plan, dir_copy_plan_os, dir_move_plan,
+               dir_move_plan_os, file_copy_plan, file_copy_plan_os, file_move_plan,
+               file_move_plan_os, move_items_plan, move_items_plan_os, plan_result_free,
+               plan_result_size, plan_result_step};
+
+#[repr(C)]
 pub struct SystemTime {
-    tv_sec: uint64_t,
-    tv_nsec: uint32_t,
+    pub tv_sec: u64,
+    pub tv_nsec: u32,
 }
 
+#[repr(C)]
 pub struct FileTransitProcess {
-    copied_bytes: uint64_t,
-    total_bytes: uint64_t,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
 }
 
+#[repr(C)]
 pub struct DirTransitProcess {
-    copied_bytes: uint64_t,
-    total_bytes: uint64_t,
-    file_bytes_copied: uint64_t,
-    file_total_bytes: uint64_t,
-    file_name: *const c_char,
-    state: uint8_t,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+    pub file_bytes_copied: u64,
+    pub file_total_bytes: u64,
+    /// Borrowed: valid only until the callback returns and freed by the
+    /// library. Copy it to keep it.
+    pub file_name: *const c_char,
+    pub state: u8,
 }
 
+#[repr(C)]
+pub struct Entry {
+    pub name: *const c_char,
+    pub ext: *const c_char,
+    pub full_name: *const c_char,
+    pub path: *const c_char,
+    pub dos_path: *const c_char,
+    pub file_size: u64,
+    pub size: u64,
+    pub is_dir: bool,
+    pub is_file: bool,
+    /// Filled in whatever the config, like `link_target`.
+    pub is_symlink: bool,
+    pub modified: SystemTime,
+    pub accessed: SystemTime,
+    pub created: SystemTime,
+    /// Where the symlink points as stored in the link, empty for other entries.
+    pub link_target: *const c_char,
+}
 
+/// Unit of an `OsPath`, a byte on Unix.
+#[cfg(unix)]
+pub type OsChar = u8;
+/// Unit of an `OsPath`, a UTF-16 code unit on Windows.
+#[cfg(windows)]
+pub type OsChar = u16;
+
+/// Path in the native encoding of the OS, not NUL terminated. Taken by the
+/// `_os` variants of the functions, which accept any name the file system does.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct OsPath {
+    pub data: *const OsChar,
+    /// Number of `OsChar` units in `data`.
+    pub size: size_t,
+}
 
-pub struct Entry {
-    name: *const c_char,
-    ext: *const c_char,
-    full_name: *const c_char,
-    path: *const c_char,
-    dos_path: *const c_char,
-    file_size: uint64_t,
-    size: uint64_t,
-    is_dir: bool,
-    is_file: bool,
-    modified: SystemTime,
-    accessed: SystemTime,
-    created: SystemTime,
-}
-
-pub fn get_enum_entry(num: uint8_t) -> fs_extra::dir::DirEntryAttr {
+/// Bumped on every incompatible change of the exported functions or structs.
+pub const FS_EXTRA_FFI_ABI_VERSION: u32 = 18;
+
+pub const DIR_ENTRY_ATTR_NAME: u8 = 0;
+pub const DIR_ENTRY_ATTR_EXT: u8 = 1;
+pub const DIR_ENTRY_ATTR_FULL_NAME: u8 = 2;
+pub const DIR_ENTRY_ATTR_PATH: u8 = 3;
+pub const DIR_ENTRY_ATTR_DOS_PATH: u8 = 4;
+pub const DIR_ENTRY_ATTR_FILE_SIZE: u8 = 5;
+pub const DIR_ENTRY_ATTR_SIZE: u8 = 6;
+pub const DIR_ENTRY_ATTR_IS_DIR: u8 = 7;
+pub const DIR_ENTRY_ATTR_IS_FILE: u8 = 8;
+pub const DIR_ENTRY_ATTR_MODIFIED: u8 = 9;
+pub const DIR_ENTRY_ATTR_ACCESSED: u8 = 10;
+pub const DIR_ENTRY_ATTR_CREATED: u8 = 11;
+pub const DIR_ENTRY_ATTR_BASE_INFO: u8 = 12;
+
+pub const TRANSIT_PROCESS_RESULT_OVERWRITE: u8 = 0;
+pub const TRANSIT_PROCESS_RESULT_OVERWRITE_ALL: u8 = 1;
+pub const TRANSIT_PROCESS_RESULT_SKIP: u8 = 2;
+pub const TRANSIT_PROCESS_RESULT_SKIP_ALL: u8 = 3;
+pub const TRANSIT_PROCESS_RESULT_RETRY: u8 = 4;
+pub const TRANSIT_PROCESS_RESULT_ABORT: u8 = 5;
+pub const TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT: u8 = 6;
+
+pub const TRANSIT_STATE_NORMAL: u8 = 0;
+pub const TRANSIT_STATE_EXISTS: u8 = 1;
+pub const TRANSIT_STATE_NO_ACCESS: u8 = 2;
+
+pub const ERROR_CODE_NONE: u32 = 0;
+// fs_extra::error::ErrorKind
+pub const ERROR_CODE_NOT_FOUND: u32 = 1;
+pub const ERROR_CODE_PERMISSION_DENIED: u32 = 2;
+pub const ERROR_CODE_ALREADY_EXISTS: u32 = 3;
+pub const ERROR_CODE_INTERRUPTED: u32 = 4;
+pub const ERROR_CODE_INVALID_FOLDER: u32 = 5;
+pub const ERROR_CODE_INVALID_FILE: u32 = 6;
+pub const ERROR_CODE_INVALID_FILE_NAME: u32 = 7;
+pub const ERROR_CODE_INVALID_PATH: u32 = 8;
+pub const ERROR_CODE_IO: u32 = 9;
+pub const ERROR_CODE_STRIP_PREFIX: u32 = 10;
+pub const ERROR_CODE_OS_STRING: u32 = 11;
+pub const ERROR_CODE_OTHER: u32 = 12;
+// Failures of the FFI layer itself
+pub const ERROR_CODE_INVALID_UTF8: u32 = 100;
+pub const ERROR_CODE_NULL_POINTER: u32 = 101;
+pub const ERROR_CODE_INVALID_ARGUMENT: u32 = 102;
+pub const ERROR_CODE_PANIC: u32 = 103;
+/// The operation was stopped through its `CancelToken`.
+pub const ERROR_CODE_CANCELLED: u32 = 104;
+
+pub const FILE_ERROR_PHASE_READ: u8 = 0;
+pub const FILE_ERROR_PHASE_WRITE: u8 = 1;
+pub const FILE_ERROR_PHASE_CREATE_DIR: u8 = 2;
+pub const FILE_ERROR_PHASE_REMOVE: u8 = 3;
+
+pub const ITEM_STATUS_DONE: u8 = 0;
+pub const ITEM_STATUS_SKIPPED: u8 = 1;
+pub const ITEM_STATUS_FAILED: u8 = 2;
+pub const ITEM_STATUS_NOT_ATTEMPTED: u8 = 3;
+
+// What happens with the symlinks below the paths an operation is given, which
+// are always followed. Kept ones are copied, listed and counted as links.
+pub const SYMLINK_POLICY_FOLLOW: u8 = 0;
+pub const SYMLINK_POLICY_KEEP: u8 = 1;
+pub const SYMLINK_POLICY_SKIP: u8 = 2;
+
+pub fn get_enum_entry(num: u8) -> Option<fs_extra::dir::DirEntryAttr> {
+    let attr = match num {
+        DIR_ENTRY_ATTR_NAME => dir::DirEntryAttr::Name,
+        DIR_ENTRY_ATTR_EXT => dir::DirEntryAttr::Ext,
+        DIR_ENTRY_ATTR_FULL_NAME => dir::DirEntryAttr::FullName,
+        DIR_ENTRY_ATTR_PATH => dir::DirEntryAttr::Path,
+        DIR_ENTRY_ATTR_DOS_PATH => dir::DirEntryAttr::DosPath,
+        DIR_ENTRY_ATTR_FILE_SIZE => dir::DirEntryAttr::FileSize,
+        DIR_ENTRY_ATTR_SIZE => dir::DirEntryAttr::Size,
+        DIR_ENTRY_ATTR_IS_DIR => dir::DirEntryAttr::IsDir,
+        DIR_ENTRY_ATTR_IS_FILE => dir::DirEntryAttr::IsFile,
+        DIR_ENTRY_ATTR_MODIFIED => dir::DirEntryAttr::Modified,
+        DIR_ENTRY_ATTR_ACCESSED => dir::DirEntryAttr::Accessed,
+        DIR_ENTRY_ATTR_CREATED => dir::DirEntryAttr::Created,
+        DIR_ENTRY_ATTR_BASE_INFO => dir::DirEntryAttr::BaseInfo,
+        _ => return None,
+    };
+    Some(attr)
+}
+
+pub(crate) fn get_symlinks(num: u8) -> Result<engine::Symlinks, Error> {
     match num {
-        0 => dir::DirEntryAttr::Name,
-        1 => dir::DirEntryAttr::Ext,
-        2 => dir::DirEntryAttr::FullName,
-        3 => dir::DirEntryAttr::Path,
-        4 => dir::DirEntryAttr::DosPath,
-        5 => dir::DirEntryAttr::FileSize,
-        6 => dir::DirEntryAttr::Size,
-        7 => dir::DirEntryAttr::IsDir,
-        8 => dir::DirEntryAttr::IsFile,
-        9 => dir::DirEntryAttr::Modified,
-        10 => dir::DirEntryAttr::Accessed,
-        11 => dir::DirEntryAttr::Created,
-        12 => dir::DirEntryAttr::BaseInfo,
-        _ => panic!("unknown index for DirEntryAttr!"),
+        SYMLINK_POLICY_FOLLOW => Ok(engine::Symlinks::Follow),
+        SYMLINK_POLICY_KEEP => Ok(engine::Symlinks::Keep),
+        SYMLINK_POLICY_SKIP => Ok(engine::Symlinks::Skip),
+        _ => Err(get_invalid_argument_error(&format!("Invalid symlink policy: {}", num))),
     }
 }
 
-pub fn get_enum_transit_result(num: uint8_t) -> dir::TransitProcessResult {
-    match num {
-        0 => dir::TransitProcessResult::Overwrite,
-        1 => dir::TransitProcessResult::OverwriteAll,
-        2 => dir::TransitProcessResult::Skip,
-        3 => dir::TransitProcessResult::SkipAll,
-        4 => dir::TransitProcessResult::Retry,
-        5 => dir::TransitProcessResult::Abort,
-        6 => dir::TransitProcessResult::ContinueOrAbort,
-        _ => panic!("unknown index for TransitProcessResult!"),
+pub fn get_enum_transit_result(num: u8) -> Option<dir::TransitProcessResult> {
+    let result = match num {
+        TRANSIT_PROCESS_RESULT_OVERWRITE => dir::TransitProcessResult::Overwrite,
+        TRANSIT_PROCESS_RESULT_OVERWRITE_ALL => dir::TransitProcessResult::OverwriteAll,
+        TRANSIT_PROCESS_RESULT_SKIP => dir::TransitProcessResult::Skip,
+        TRANSIT_PROCESS_RESULT_SKIP_ALL => dir::TransitProcessResult::SkipAll,
+        TRANSIT_PROCESS_RESULT_RETRY => dir::TransitProcessResult::Retry,
+        TRANSIT_PROCESS_RESULT_ABORT => dir::TransitProcessResult::Abort,
+        TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT => dir::TransitProcessResult::ContinueOrAbort,
+        _ => return None,
+    };
+    Some(result)
+}
+
+pub fn get_enum_transit_state(num: u8) -> Option<dir::TransitState> {
+    let state = match num {
+        TRANSIT_STATE_NORMAL => dir::TransitState::Normal,
+        TRANSIT_STATE_EXISTS => dir::TransitState::Exists,
+        TRANSIT_STATE_NO_ACCESS => dir::TransitState::NoAccess,
+        _ => return None,
+    };
+    Some(state)
+}
+
+pub fn get_error_code(kind: &fs_extra::error::ErrorKind) -> u32 {
+    use fs_extra::error::ErrorKind;
+
+    match *kind {
+        ErrorKind::NotFound => ERROR_CODE_NOT_FOUND,
+        ErrorKind::PermissionDenied => ERROR_CODE_PERMISSION_DENIED,
+        ErrorKind::AlreadyExists => ERROR_CODE_ALREADY_EXISTS,
+        ErrorKind::Interrupted => ERROR_CODE_INTERRUPTED,
+        ErrorKind::InvalidFolder => ERROR_CODE_INVALID_FOLDER,
+        ErrorKind::InvalidFile => ERROR_CODE_INVALID_FILE,
+        ErrorKind::InvalidFileName => ERROR_CODE_INVALID_FILE_NAME,
+        ErrorKind::InvalidPath => ERROR_CODE_INVALID_PATH,
+        ErrorKind::Io(_) if engine::is_cancelled(kind) => ERROR_CODE_CANCELLED,
+        ErrorKind::Io(_) => ERROR_CODE_IO,
+        ErrorKind::StripPrefix(_) => ERROR_CODE_STRIP_PREFIX,
+        ErrorKind::OsString(_) => ERROR_CODE_OS_STRING,
+        ErrorKind::Other => ERROR_CODE_OTHER,
     }
 }
 
-pub fn get_enum_transit_state(num: uint8_t) -> dir::TransitState {
-    match num {
-        0 => dir::TransitState::Normal,
-        1 => dir::TransitState::Exists,
-        2 => dir::TransitState::NoAccess,
-        _ => panic!("unknown index for TransitState!"),
+pub fn get_error_code_name(code: u32) -> &'static [u8] {
+    match code {
+        ERROR_CODE_NONE => b"None\0",
+        ERROR_CODE_NOT_FOUND => b"NotFound\0",
+        ERROR_CODE_PERMISSION_DENIED => b"PermissionDenied\0",
+        ERROR_CODE_ALREADY_EXISTS => b"AlreadyExists\0",
+        ERROR_CODE_INTERRUPTED => b"Interrupted\0",
+        ERROR_CODE_INVALID_FOLDER => b"InvalidFolder\0",
+        ERROR_CODE_INVALID_FILE => b"InvalidFile\0",
+        ERROR_CODE_INVALID_FILE_NAME => b"InvalidFileName\0",
+        ERROR_CODE_INVALID_PATH => b"InvalidPath\0",
+        ERROR_CODE_IO => b"Io\0",
+        ERROR_CODE_STRIP_PREFIX => b"StripPrefix\0",
+        ERROR_CODE_OS_STRING => b"OsString\0",
+        ERROR_CODE_OTHER => b"Other\0",
+        ERROR_CODE_INVALID_UTF8 => b"InvalidUtf8\0",
+        ERROR_CODE_NULL_POINTER => b"NullPointer\0",
+        ERROR_CODE_INVALID_ARGUMENT => b"InvalidArgument\0",
+        ERROR_CODE_PANIC => b"Panic\0",
+        ERROR_CODE_CANCELLED => b"Cancelled\0",
+        _ => b"Unknown\0",
+    }
+}
+
+/// Name of an `ERROR_CODE_*` value. The string is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn fs_extra_error_code_name(code: u32) -> *const c_char {
+    get_error_code_name(code).as_ptr() as *const c_char
+}
+
+pub fn get_entry_attrs(config: &[u8]) -> Result<HashSet<dir::DirEntryAttr>, Error> {
+    let mut attrs = HashSet::new();
+    for &option in config {
+        match get_enum_entry(option) {
+            Some(attr) => {
+                attrs.insert(attr);
+            }
+            None => {
+                let message = format!("Unknown DirEntryAttr value {}", option);
+                return Err(get_invalid_argument_error(message.as_str()));
+            }
+        }
+    }
+    Ok(attrs)
+}
+
+/// Decodes a progress callback answer. An unknown value is taken for `Abort`,
+/// which stops the operation at this call, and is remembered in `invalid` so
+/// the caller can report it instead of the interruption.
+pub fn get_callback_transit_result(num: u8,
+                                   invalid: &Cell<Option<u8>>)
+                                   -> dir::TransitProcessResult {
+    match get_enum_transit_result(num) {
+        Some(result) => result,
+        None => {
+            if invalid.get().is_none() {
+                invalid.set(Some(num));
+            }
+            dir::TransitProcessResult::Abort
+        }
     }
 }
 
-pub fn get_int_transit_state(state: dir::TransitState) -> uint8_t {
+pub fn get_invalid_argument_error(message: &str) -> Error {
+    Error::new(ERROR_CODE_INVALID_ARGUMENT, "InvalidArgument", message)
+}
+
+pub fn get_invalid_transit_result_error(num: u8) -> Error {
+    let message = format!("Unknown TransitProcessResult value {} returned from callback", num);
+    get_invalid_argument_error(message.as_str())
+}
+
+pub fn get_int_transit_state(state: dir::TransitState) -> u8 {
     match state {
-        dir::TransitState::Normal => 0,
-        dir::TransitState::Exists => 1,
-        dir::TransitState::NoAccess => 2,
+        dir::TransitState::Normal => TRANSIT_STATE_NORMAL,
+        dir::TransitState::Exists => TRANSIT_STATE_EXISTS,
+        dir::TransitState::NoAccess => TRANSIT_STATE_NO_ACCESS,
     }
 
 }
 
+/// ABI version the library was built with, compare with `FS_EXTRA_FFI_ABI_VERSION`.
+#[no_mangle]
+pub extern "C" fn fs_extra_ffi_version() -> u32 {
+    FS_EXTRA_FFI_ABI_VERSION
+}
+
 pub fn get_c_string(val: &str) -> CString {
-    unsafe { CString::from_vec_unchecked(val.as_bytes().to_vec()) }
+    get_c_string_from_bytes(val.as_bytes())
 }
 
-pub fn parse_entry(item: &HashMap<dir::DirEntryAttr, dir::DirEntryValue>) -> Entry {
+/// Keeps the bytes of `val` as the OS gave them, so names that are not valid
+/// UTF-8 reach C unchanged.
+pub fn get_os_c_string(val: &OsStr) -> CString {
+    get_c_string_from_bytes(val.as_encoded_bytes())
+}
 
-    let get_str_val = |field: dir::DirEntryAttr| -> *const c_char {
-        let result: *const c_char;
-        match item.get(&field) {
-            Some(val) => {
-                if let &dir::DirEntryValue::String(ref val) = val {
-                    result = get_c_string(val).into_raw();
-                } else {
-                    result = get_c_string("").into_raw();
-                }
+/// Cuts `bytes` at the first NUL, where C would stop reading anyway, so the
+/// string freed by `free_c_string` has the length it was allocated with.
+fn get_c_string_from_bytes(bytes: &[u8]) -> CString {
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    CString::new(&bytes[..end]).unwrap_or_default()
+}
+
+/// Releases a string created with `get_c_string(..).into_raw()`.
+pub unsafe fn free_c_string(val: *const c_char) {
+    if !val.is_null() {
+        drop(CString::from_raw(val as *mut c_char));
+    }
+}
+
+pub fn get_system_time(val: &std::time::SystemTime) -> SystemTime {
+    match val.duration_since(UNIX_EPOCH) {
+        Ok(val) => {
+            SystemTime {
+                tv_sec: val.as_secs(),
+                tv_nsec: val.subsec_nanos(),
             }
-            _ => result = get_c_string("").into_raw(),
         }
-        result
-    };
+        Err(_) => get_default_system_time(),
+    }
+}
 
-    let get_u64_val = |field: dir::DirEntryAttr| -> u64 {
-        let mut result = 0;
-        match item.get(&field) {
-            Some(val) => {
-                if let &dir::DirEntryValue::U64(val) = val {
-                    result = val;
-                }
-            }
-            _ => {}
+pub fn get_default_system_time() -> SystemTime {
+    SystemTime {
+        tv_sec: 0,
+        tv_nsec: 0,
+    }
+}
+
+#[repr(C)]
+pub struct Error {
+    pub kind: *const c_char,
+    pub message: *const c_char,
+    /// One of the `ERROR_CODE_*` values.
+    pub code: u32,
+    /// Raw OS error number when the failure came from an I/O call, 0 otherwise.
+    pub os_error: i32,
+    /// Path the operation failed on, empty when it is not known.
+    pub path: *const c_char,
+}
+
+impl Error {
+    pub fn new(code: u32, kind: &str, message: &str) -> Error {
+        Error {
+            kind: get_c_string(kind).into_raw(),
+            message: get_c_string(message).into_raw(),
+            code,
+            os_error: 0,
+            path: get_c_string("").into_raw(),
         }
-        result
-    };
+    }
 
-    let get_bool_val = |field: dir::DirEntryAttr| -> bool {
-        let mut result = false;
-        match item.get(&field) {
-            Some(val) => {
-                if let &dir::DirEntryValue::Boolean(val) = val {
-                    result = val;
-                }
+    pub fn empty() -> Error {
+        Error::new(ERROR_CODE_NONE, "", "")
+    }
+}
+
+// An `Error` owns its strings, nothing else points to them.
+unsafe impl Send for Error {}
+
+impl Clone for Error {
+    fn clone(&self) -> Error {
+        unsafe {
+            Error {
+                kind: CStr::from_ptr(self.kind).to_owned().into_raw(),
+                message: CStr::from_ptr(self.message).to_owned().into_raw(),
+                code: self.code,
+                os_error: self.os_error,
+                path: CStr::from_ptr(self.path).to_owned().into_raw(),
             }
-            _ => {}
         }
-        result
-    };
+    }
+}
 
-    let get_system_time_val = |field: dir::DirEntryAttr| -> SystemTime {
-        let mut result = SystemTime {
-            tv_sec: 0,
-            tv_nsec: 0,
-        };
-        match item.get(&field) {
-            Some(val) => {
-                if let &dir::DirEntryValue::SystemTime(val) = val {
-                    let tv_sec: u64;
-                    let tv_nsec: u32;
-                    match val.duration_since(UNIX_EPOCH) {
-                        Ok(val) => tv_sec = val.as_secs(),
-                        _ => tv_sec = 0,
-                    }
-                    match val.duration_since(UNIX_EPOCH) {
-                        Ok(val) => tv_nsec = val.subsec_nanos(),
-                        _ => tv_nsec = 0,
-                    }
-
-                    result = SystemTime {
-                        tv_sec: tv_sec,
-                        tv_nsec: tv_nsec,
-                    };
-                }
-            }
-            _ => {}
+impl Drop for Error {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.kind);
+            free_c_string(self.message);
+            free_c_string(self.path);
         }
-        result
-    };
+    }
+}
 
+impl From<engine::Error> for Error {
+    fn from(err: engine::Error) -> Error {
+        get_path_error(err, Path::new(""))
+    }
+}
 
-    let name = get_str_val(dir::DirEntryAttr::Name);
-    let ext = get_str_val(dir::DirEntryAttr::Ext);
-    let full_name = get_str_val(dir::DirEntryAttr::FullName);
-    let path = get_str_val(dir::DirEntryAttr::Path);
-    let dos_path = get_str_val(dir::DirEntryAttr::DosPath);
-    let file_size = get_u64_val(dir::DirEntryAttr::FileSize);
-    let size = get_u64_val(dir::DirEntryAttr::Size);
-    let is_dir = get_bool_val(dir::DirEntryAttr::IsDir);
-    let is_file = get_bool_val(dir::DirEntryAttr::IsFile);
-    let modified = get_system_time_val(dir::DirEntryAttr::Modified);
-    let accessed = get_system_time_val(dir::DirEntryAttr::Accessed);
-    let created = get_system_time_val(dir::DirEntryAttr::Created);
-    Entry {
-        name: name,
-        ext: ext,
-        full_name: full_name,
-        path: path,
-        dos_path: dos_path,
-        file_size: file_size,
-        size: size,
-        is_dir: is_dir,
-        is_file: is_file,
-        modified: modified,
-        accessed: accessed,
-        created: created,
+impl From<fs_extra::error::Error> for Error {
+    fn from(err: fs_extra::error::Error) -> Error {
+        engine::Error::from(err).into()
     }
+}
 
+/// Builds an error from an engine failure. `path` is used when the failure does
+/// not name the failed path itself.
+pub(crate) fn get_fs_error(kind: &str, err: &engine::Error, path: &Path) -> Error {
+    Error {
+        kind: get_c_string(kind).into_raw(),
+        message: get_c_string(err.message.as_str()).into_raw(),
+        code: get_error_code(&err.kind),
+        os_error: err.os_error.unwrap_or(0),
+        path: get_os_c_string(err.path.as_deref().unwrap_or(path).as_os_str()).into_raw(),
+    }
 }
-pub struct Error {
-    kind: *const c_char,
-    message: *const c_char,
+
+pub(crate) fn get_path_error(err: engine::Error, path: &Path) -> Error {
+    if engine::is_cancelled(&err.kind) {
+        return get_fs_error("Cancelled", &err, path);
+    }
+    get_fs_error(format!("{:?}", err.kind).as_str(), &err, path)
 }
+
+#[repr(C)]
 pub struct DetailsEntryResult {
-    is_error: bool,
-    error: Error,
-    ok: Entry,
+    pub is_error: bool,
+    pub error: Error,
+    pub ok: Entry,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.name);
+            free_c_string(self.ext);
+            free_c_string(self.full_name);
+            free_c_string(self.path);
+            free_c_string(self.dos_path);
+            free_c_string(self.link_target);
+        }
+    }
 }
+
 pub fn get_default_entry() -> Entry {
     Entry {
         name: get_c_string("").into_raw(),
@@ -222,1512 +496,1765 @@ pub fn get_default_entry() -> Entry {
         size: 0,
         is_dir: false,
         is_file: false,
-        modified: SystemTime {
-            tv_sec: 0,
-            tv_nsec: 0,
-        },
-        accessed: SystemTime {
-            tv_sec: 0,
-            tv_nsec: 0,
-        },
-        created: SystemTime {
-            tv_sec: 0,
-            tv_nsec: 0,
-        },
+        is_symlink: false,
+        modified: get_default_system_time(),
+        accessed: get_default_system_time(),
+        created: get_default_system_time(),
+        link_target: get_c_string("").into_raw(),
     }
 
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn dir_get_details_entry(path: *const c_char,
-                                               config: *const uint8_t,
-                                               size: size_t)
-                                               -> *mut DetailsEntryResult {
-    let config = std::slice::from_raw_parts(config as *const uint8_t, size);
-    let mut options = HashSet::new();
-    let path = CStr::from_ptr(path);
-    for option in config {
-        let option = get_enum_entry(option.clone());
-        options.insert(option);
-    }
+/// Result structs handed out by the exported functions.
+pub trait FfiResult {
+    type Ok;
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut entry = get_default_entry();
-    let item_path: &str;
-
-    match path.to_str() {
-        Ok(val) => item_path = val,
-        Err(msg) => {
-            item_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid path").into_raw(),
-                message: get_c_string("Invalid path").into_raw(),
-            };
-        }
+    fn new(result: Result<Self::Ok, Error>) -> Self;
+}
 
-    }
+impl FfiResult for DetailsEntryResult {
+    type Ok = Entry;
 
-    if !is_error {
-        match dir::get_details_entry(item_path, &options) {
-            Ok(item) => entry = parse_entry(&item),
-            Err(err_item) => {
-                is_error = true;
-                match err_item.kind {
-                    fs_extra::error::ErrorKind::NotFound => {
-                        err = Error {
-                            kind: get_c_string("Path not found").into_raw(),
-                            message: get_c_string(format!("{}", err_item.to_string()).as_str())
-                                .into_raw(),
-                        }
-                    }
-                    _ => {
-                        err = Error {
-                            kind: get_c_string("Other error").into_raw(),
-                            message: get_c_string(format!("{}", err_item.to_string()).as_str())
-                                .into_raw(),
-                        }
-                    }
+    fn new(result: Result<Entry, Error>) -> DetailsEntryResult {
+        match result {
+            Ok(ok) => {
+                DetailsEntryResult {
+                    is_error: false,
+                    error: Error::empty(),
+                    ok,
+                }
+            }
+            Err(error) => {
+                DetailsEntryResult {
+                    is_error: true,
+                    error,
+                    ok: get_default_entry(),
                 }
-
             }
         }
     }
-    let result: DetailsEntryResult;
-    result = DetailsEntryResult {
-        is_error: is_error,
-        error: err,
-        ok: entry,
-    };
-
-    Box::into_raw(Box::new(result))
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn dir_get_details_entry_free(value: *mut DetailsEntryResult) {
-    Box::from_raw(value);
 }
 
+impl FfiResult for LsResult {
+    type Ok = (Entry, Vec<Entry>, Vec<FileError>);
 
-pub struct LsResult {
-    is_error: bool,
-    error: Error,
-    base: Entry,
-    size: size_t,
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn dir_ls(path: *const c_char,
-                                config: *const uint8_t,
-                                config_size: size_t,
-                                out_items: *mut *mut Entry)
-                                -> *mut LsResult {
-    let config = std::slice::from_raw_parts(config as *const uint8_t, config_size);
-    let mut options = HashSet::new();
-    let path = CStr::from_ptr(path);
-    for option in config {
-        let option = get_enum_entry(option.clone());
-        options.insert(option);
+    fn new(result: Result<(Entry, Vec<Entry>, Vec<FileError>), Error>) -> LsResult {
+        match result {
+            Ok((base, items, warnings)) => {
+                let (warnings_size, warnings) = if warnings.is_empty() {
+                    (0, ptr::null())
+                } else {
+                    (warnings.len(), Box::into_raw(warnings.into_boxed_slice()) as *const FileError)
+                };
+                LsResult {
+                    is_error: false,
+                    error: Error::empty(),
+                    base,
+                    size: items.len(),
+                    items: Box::into_raw(items.into_boxed_slice()) as *const Entry,
+                    warnings_size,
+                    warnings,
+                }
+            }
+            Err(error) => {
+                LsResult {
+                    is_error: true,
+                    error,
+                    base: get_default_entry(),
+                    size: 0,
+                    items: ptr::null(),
+                    warnings_size: 0,
+                    warnings: ptr::null(),
+                }
+            }
+        }
     }
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut entry_items_size = 0;
-    let item_path: &str;
-    let mut entry_items = Vec::new();
-    let mut base_item = get_default_entry();
-
-    match path.to_str() {
-        Ok(val) => item_path = val,
-        Err(msg) => {
-            item_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid path").into_raw(),
-                message: get_c_string("Invalid path").into_raw(),
-            };
+impl FfiResult for U64Result {
+    type Ok = u64;
+
+    fn new(result: Result<u64, Error>) -> U64Result {
+        match result {
+            Ok(ok) => {
+                U64Result {
+                    is_error: false,
+                    error: Error::empty(),
+                    ok,
+                    file_errors_size: 0,
+                    file_errors: ptr::null(),
+                    items_size: 0,
+                    items: ptr::null(),
+                    warnings_size: 0,
+                    warnings: ptr::null(),
+                }
+            }
+            Err(error) => {
+                U64Result {
+                    is_error: true,
+                    error,
+                    ok: 0,
+                    file_errors_size: 0,
+                    file_errors: ptr::null(),
+                    items_size: 0,
+                    items: ptr::null(),
+                    warnings_size: 0,
+                    warnings: ptr::null(),
+                }
+            }
         }
     }
+}
+
+impl FfiResult for CStringResult {
+    type Ok = String;
 
-    if !is_error {
-        match dir::ls(item_path, &options) {
-            Ok(result) => {
-                for item in result.items {
-                    let item = parse_entry(&item);
-                    entry_items.push(item);
+    fn new(result: Result<String, Error>) -> CStringResult {
+        match result {
+            Ok(ok) => {
+                CStringResult {
+                    is_error: false,
+                    error: Error::empty(),
+                    ok: get_c_string(ok.as_str()).into_raw(),
                 }
-                base_item = parse_entry(&result.base);
             }
-            Err(err_item) => {
-                is_error = true;
-                match err_item.kind {
-                    fs_extra::error::ErrorKind::NotFound => {
-                        err = Error {
-                            kind: get_c_string("Path not found").into_raw(),
-                            message: get_c_string(format!("{}", err_item.to_string()).as_str())
-                                .into_raw(),
-                        }
-                    }
-                    _ => {
-                        err = Error {
-                            kind: get_c_string("Other error").into_raw(),
-                            message: get_c_string(format!("{}", err_item.to_string()).as_str())
-                                .into_raw(),
-                        }
-                    }
+            Err(error) => {
+                CStringResult {
+                    is_error: true,
+                    error,
+                    ok: get_c_string("").into_raw(),
                 }
             }
         }
-        *out_items = entry_items.as_mut_ptr();
-        entry_items_size = entry_items.len();
-        mem::forget(entry_items);
-    };
+    }
+}
+
+/// Runs the body of an exported function. A panic inside it (from fs_extra, a
+/// callback trampoline or this crate) is turned into an error with the `Panic`
+/// kind instead of unwinding into the caller.
+pub fn guard<R, F>(body: F) -> *mut R
+    where R: FfiResult,
+          F: FnOnce() -> Result<R::Ok, Error>
+{
+    let result = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => Err(get_panic_error(payload.as_ref())),
+    };
+    Box::into_raw(Box::new(R::new(result)))
+}
+
+/// Same as `guard` for the functions that release results and return nothing.
+pub fn guard_free<F: FnOnce()>(body: F) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(body));
+}
 
-    let result = LsResult {
-        is_error: is_error,
-        error: err,
-        base: base_item,
-        size: entry_items_size,
+pub fn get_panic_error(payload: &(dyn Any + Send)) -> Error {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Unknown panic"
     };
-    Box::into_raw(Box::new(result))
+    Error::new(ERROR_CODE_PANIC, "Panic", message)
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn dir_ls_free(entry_items: *mut Entry,
-                                     size: size_t,
-                                     result: *mut DetailsEntryResult) {
-    Box::from_raw(result);
-    if !entry_items.is_null() {
-        Vec::from_raw_parts(entry_items, size, size);
+pub fn get_null_pointer_error(kind: &str) -> Error {
+    Error::new(ERROR_CODE_NULL_POINTER, kind, format!("{}: NULL pointer", kind).as_str())
+}
+
+pub unsafe fn get_str<'a>(value: *const c_char, kind: &str) -> Result<&'a str, Error> {
+    if value.is_null() {
+        return Err(get_null_pointer_error(kind));
+    }
+    match CStr::from_ptr(value).to_str() {
+        Ok(val) => Ok(val),
+        Err(_) => Err(Error::new(ERROR_CODE_INVALID_UTF8, kind, kind)),
     }
 }
 
-pub struct CopyOptions {
-    overwrite: bool,
-    skip_exist: bool,
-    buffer_size: size_t,
+/// A NULL `data` is only accepted together with a zero `size`.
+pub unsafe fn get_slice<'a, T>(data: *const T, size: size_t, kind: &str) -> Result<&'a [T], Error> {
+    if size == 0 {
+        Ok(&[])
+    } else if data.is_null() {
+        Err(get_null_pointer_error(kind))
+    } else {
+        Ok(std::slice::from_raw_parts(data, size))
+    }
 }
 
-pub struct U64Result {
-    is_error: bool,
-    error: Error,
-    ok: uint64_t,
+#[cfg(unix)]
+pub unsafe fn get_os_path(value: OsPath, kind: &str) -> Result<PathBuf, Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(PathBuf::from(OsStr::from_bytes(get_slice(value.data, value.size, kind)?)))
 }
 
-pub struct CStringResult {
-    is_error: bool,
-    error: Error,
-    ok: *const c_char,
+#[cfg(windows)]
+pub unsafe fn get_os_path(value: OsPath, kind: &str) -> Result<PathBuf, Error> {
+    use std::os::windows::ffi::OsStringExt;
+
+    Ok(PathBuf::from(std::ffi::OsString::from_wide(get_slice(value.data, value.size, kind)?)))
 }
 
+/// Path argument of an export, a UTF-8 C string or an `OsPath`.
+pub trait FfiPath: Copy {
+    unsafe fn get_path(self, kind: &str) -> Result<PathBuf, Error>;
+}
 
-#[no_mangle]
-pub unsafe extern "C" fn dir_copy(from: *const c_char,
-                                  to: *const c_char,
-                                  options: *mut CopyOptions)
-                                  -> *mut U64Result {
-    let options = &mut *options;
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+impl FfiPath for *const c_char {
+    unsafe fn get_path(self, kind: &str) -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(get_str(self, kind)?))
+    }
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
+impl FfiPath for OsPath {
+    unsafe fn get_path(self, kind: &str) -> Result<PathBuf, Error> {
+        get_os_path(self, kind)
     }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+}
+
+pub unsafe fn get_path_list<P: FfiPath>(list: *const P,
+                                        size: size_t,
+                                        kind: &str)
+                                        -> Result<Vec<PathBuf>, Error> {
+    let mut result = Vec::new();
+    for path in get_slice(list, size, kind)? {
+        result.push(path.get_path(kind)?);
     }
+    Ok(result)
+}
 
+/// A NULL `options` keeps the default of stopping at the first error.
+pub unsafe fn get_continue_on_error(options: *const CopyOptions) -> bool {
+    get_copy_options(options).is_ok_and(|options| options.continue_on_error)
+}
 
-    match dir::copy(from_path, to_path, &options) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
-        }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+/// `get_dir_copy_options` with the additions of this crate.
+pub(crate) unsafe fn get_transfer_options(options: *const CopyOptions)
+                                          -> Result<engine::Options, Error> {
+    let options = get_copy_options(options)?;
+    Ok(engine::Options {
+        filter: options.filter.as_ref().cloned(),
+        dir: get_dir_copy_options(&options),
+        preserve: get_preserve(&options),
+        symlinks: get_symlinks(options.symlinks)?,
+        hard_links: options.preserve_hard_links,
+    })
 }
 
+/// `get_copy_options` for the single file copies and moves.
+pub(crate) unsafe fn get_file_copy_options(options: *const CopyOptions)
+                                           -> Result<engine::FileOptions, Error> {
+    let options = get_copy_options(options)?;
+    Ok(engine::FileOptions {
+        file: file::CopyOptions {
+            overwrite: options.overwrite,
+            skip_exist: options.skip_exist,
+            buffer_size: options.buffer_size,
+        },
+        preserve: get_preserve(&options),
+    })
+}
+
+/// The fields of `options` its `size` covers, the defaults for the rest. A NULL
+/// `options` stands for the defaults.
+pub unsafe fn get_copy_options(options: *const CopyOptions) -> Result<CopyOptions, Error> {
+    let mut result = get_default_copy_options();
+    let size = match options.as_ref() {
+        Some(options) => options.size,
+        None => return Ok(result),
+    };
+    if !is_released_copy_options_size(size) {
+        let message = format!("Invalid options size: {}", size);
+        return Err(get_invalid_argument_error(&message));
+    }
+    ptr::copy_nonoverlapping(options as *const u8,
+                             &mut result as *mut CopyOptions as *mut u8,
+                             size.min(mem::size_of::<CopyOptions>()));
+    result.size = mem::size_of::<CopyOptions>();
+    if result.buffer_size == 0 {
+        return Err(get_invalid_argument_error("Invalid buffer_size: 0"));
+    }
+    Ok(result)
+}
 
+pub fn get_callback<T>(cb: Option<T>) -> Result<T, Error> {
+    cb.ok_or_else(|| get_null_pointer_error("Invalid callback"))
+}
 
+pub(crate) fn get_preserve(options: &CopyOptions) -> engine::Preserve {
+    engine::Preserve {
+        permissions: options.preserve_permissions,
+        times: options.preserve_times,
+        owner: options.preserve_owner,
+        xattrs: options.preserve_xattrs,
+    }
+}
 
-#[no_mangle]
-pub unsafe extern "C" fn dir_move(from: *const c_char,
-                                  to: *const c_char,
-                                  options: *mut CopyOptions)
-                                  -> *mut U64Result {
-    let options = &mut *options;
-    let options = dir::CopyOptions {
+pub fn get_dir_copy_options(options: &CopyOptions) -> dir::CopyOptions {
+    dir::CopyOptions {
         overwrite: options.overwrite,
         skip_exist: options.skip_exist,
         buffer_size: options.buffer_size,
-    };
+        copy_inside: options.copy_inside,
+        content_only: options.content_only,
+        depth: options.depth,
+    }
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
+pub fn get_dir_transit_process(copied_bytes: u64,
+                               total_bytes: u64,
+                               file_bytes_copied: u64,
+                               file_total_bytes: u64,
+                               file_name: &CStr,
+                               state: dir::TransitState)
+                               -> DirTransitProcess {
+    DirTransitProcess {
+        copied_bytes,
+        total_bytes,
+        file_bytes_copied,
+        file_total_bytes,
+        file_name: file_name.as_ptr(),
+        state: get_int_transit_state(state),
     }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+}
+
+pub(crate) fn get_details_entry_error(err: engine::Error, path: &Path) -> Error {
+    match err.kind {
+        fs_extra::error::ErrorKind::NotFound => get_fs_error("Path not found", &err, path),
+        _ => get_fs_error("Other error", &err, path),
     }
+}
 
+/// Runs a single file transfer with its progress forwarded to `cb`. Answering
+/// `TRANSIT_PROCESS_RESULT_ABORT` stops it, any other valid answer continues.
+fn run_with_file_callback<F>(cb: extern "C" fn(FileTransitProcess, *mut c_void) -> u8,
+                             user_data: *mut c_void,
+                             transfer: F)
+                             -> Result<u64, Error>
+    where F: FnOnce(engine::FileHandler) -> engine::Result<u64>
+{
+    let invalid_result = Cell::new(None);
+    let mut handle = |copied_bytes, total_bytes| {
+        let p_info = FileTransitProcess {
+            copied_bytes,
+            total_bytes,
+        };
+        let result = get_callback_transit_result(cb(p_info, user_data), &invalid_result);
+        !matches!(result, dir::TransitProcessResult::Abort)
+    };
 
-    match dir::move_dir(from_path, to_path, &options) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
-        }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
+    let result = transfer(&mut handle);
+    if let Some(val) = invalid_result.get() {
+        return Err(get_invalid_transit_result_error(val));
     }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+    Ok(result?)
 }
 
-
-#[no_mangle]
-pub unsafe extern "C" fn move_result_free(value: *mut U64Result) {
-    Box::from_raw(value);
+/// Runs a directory transfer with its progress forwarded to `cb`.
+fn run_with_dir_callback<F>(cb: extern "C" fn(DirTransitProcess, *mut c_void) -> u8,
+                            user_data: *mut c_void,
+                            transfer: F)
+                            -> Result<u64, Error>
+    where F: FnOnce(engine::Handler) -> engine::Result<u64>
+{
+    let invalid_result = Cell::new(None);
+    // The C copy of the name is lent to `cb`, and only rebuilt when the file
+    // changes.
+    let mut file_name = (OsString::new(), CString::default());
+    let mut handle = |process_info: &engine::Progress| {
+        if file_name.0 != process_info.file_name {
+            file_name = (process_info.file_name.to_os_string(),
+                         get_os_c_string(process_info.file_name));
+        }
+        let p_info = get_dir_transit_process(process_info.copied_bytes,
+                                             process_info.total_bytes,
+                                             process_info.file_bytes_copied,
+                                             process_info.file_total_bytes,
+                                             &file_name.1,
+                                             process_info.state.clone());
+        get_callback_transit_result(cb(p_info, user_data), &invalid_result)
+    };
+
+    let result = transfer(&mut handle);
+    if let Some(val) = invalid_result.get() {
+        return Err(get_invalid_transit_result_error(val));
+    }
+    Ok(result?)
 }
 
+unsafe fn do_dir_get_details_entry<P: FfiPath>(path: P,
+                                               config: *const u8,
+                                               size: size_t)
+                                               -> *mut DetailsEntryResult {
+    guard(|| {
+        let config = get_slice(config, size, "Invalid config")?;
+        let options = get_entry_attrs(config)?;
+        let item_path = path.get_path("Invalid path")?;
+
+        engine::get_details_entry(&item_path, &options)
+            .map_err(|err_item| get_details_entry_error(err_item, &item_path))
+    })
+}
 
 #[no_mangle]
-pub unsafe extern "C" fn file_copy(from: *const c_char,
-                                   to: *const c_char,
-                                   options: *mut CopyOptions)
-                                   -> *mut U64Result {
-    let options = &mut *options;
-    let options = file::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
-
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
+pub unsafe extern "C" fn dir_get_details_entry(path: *const c_char,
+                                               config: *const u8,
+                                               size: size_t)
+                                               -> *mut DetailsEntryResult {
+    do_dir_get_details_entry(path, config, size)
+}
 
+/// `dir_get_details_entry` for an `OsPath`. The strings of the entry hold the
+/// bytes of the names as the OS returned them.
+#[no_mangle]
+pub unsafe extern "C" fn dir_get_details_entry_os(path: OsPath,
+                                                  config: *const u8,
+                                                  size: size_t)
+                                                  -> *mut DetailsEntryResult {
+    do_dir_get_details_entry(path, config, size)
+}
 
-    match file::copy(from_path, to_path, &options) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
-        }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
+/// Releases a result of `dir_get_details_entry` together with its strings.
+#[no_mangle]
+pub unsafe extern "C" fn details_entry_result_free(value: *mut DetailsEntryResult) {
+    guard_free(|| {
+        if !value.is_null() {
+            drop(Box::from_raw(value));
         }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+    })
 }
 
+/// Same as `details_entry_result_free`.
 #[no_mangle]
-pub unsafe extern "C" fn dir_move_with_progress(from: *const c_char,
-                                                to: *const c_char,
-                                                options: *mut CopyOptions,
-                                                cb: extern "C" fn(DirTransitProcess) -> uint8_t)
-                                                -> *mut U64Result {
-    let options = &mut *options;
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+pub unsafe extern "C" fn dir_get_details_entry_free(value: *mut DetailsEntryResult) {
+    details_entry_result_free(value)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
-    let handle = |process_info: dir::TransitProcess| {
-        let p_info = DirTransitProcess {
-            copied_bytes: process_info.copied_bytes,
-            total_bytes: process_info.total_bytes,
-            file_bytes_copied: process_info.file_bytes_copied,
-            file_total_bytes: process_info.file_total_bytes,
-            file_name: get_c_string(process_info.file_name.as_str()).into_raw(),
-            state: get_int_transit_state(process_info.state),
-        };
 
-        get_enum_transit_result(cb(p_info))
-    };
+/// Owns the listed entries, read them with `ls_result_item` and release all of
+/// it with `ls_result_free`.
+#[repr(C)]
+pub struct LsResult {
+    pub is_error: bool,
+    pub error: Error,
+    pub base: Entry,
+    pub size: size_t,
+    pub items: *const Entry,
+    /// Sizes that could not be taken, left at 0 in their entries. Owned by the
+    /// result.
+    pub warnings_size: size_t,
+    pub warnings: *const FileError,
+}
 
-    match dir::move_dir_with_progress(from_path, to_path, &options, handle) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
+impl Drop for LsResult {
+    fn drop(&mut self) {
+        if !self.items.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.items as *mut Entry, self.size, self.size));
+            }
         }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
+        if !self.warnings.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.warnings as *mut FileError,
+                                         self.warnings_size,
+                                         self.warnings_size));
             }
         }
     }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
 }
 
+unsafe fn do_dir_ls<P: FfiPath>(path: P,
+                                config: *const u8,
+                                config_size: size_t,
+                                filter: *const Filter,
+                                symlinks: u8)
+                                -> *mut LsResult {
+    guard(|| {
+        let config = get_slice(config, config_size, "Invalid config")?;
+        let options = get_entry_attrs(config)?;
+        let symlinks = get_symlinks(symlinks)?;
+        let item_path = path.get_path("Invalid path")?;
+
+        let (base, items, failures) = engine::ls(&item_path, &options, filter.as_ref(), symlinks)
+            .map_err(|err_item| get_details_entry_error(err_item, &item_path))?;
+        Ok((base, items, failures.into_iter().map(get_file_error).collect()))
+    })
+}
+
+/// `symlinks` is one of the `SYMLINK_POLICY_*` values.
 #[no_mangle]
-pub unsafe extern "C" fn dir_copy_with_progress(from: *const c_char,
-                                                to: *const c_char,
-                                                options: *mut CopyOptions,
-                                                cb: extern "C" fn(DirTransitProcess) -> uint8_t)
-                                                -> *mut U64Result {
-    let options = &mut *options;
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+pub unsafe extern "C" fn dir_ls(path: *const c_char,
+                                config: *const u8,
+                                config_size: size_t,
+                                filter: *const Filter,
+                                symlinks: u8)
+                                -> *mut LsResult {
+    do_dir_ls(path, config, config_size, filter, symlinks)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
+/// `dir_ls` for an `OsPath`. The strings of the entries hold the bytes of the
+/// names as the OS returned them.
+#[no_mangle]
+pub unsafe extern "C" fn dir_ls_os(path: OsPath,
+                                   config: *const u8,
+                                   config_size: size_t,
+                                   filter: *const Filter,
+                                   symlinks: u8)
+                                   -> *mut LsResult {
+    do_dir_ls(path, config, config_size, filter, symlinks)
+}
+
+/// Number of entries in a `dir_ls` result.
+#[no_mangle]
+pub unsafe extern "C" fn ls_result_size(result: *const LsResult) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.size,
+        None => 0,
     }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+}
+
+/// Entry `index` of a `dir_ls` result, NULL when out of range. The entry stays
+/// owned by the result.
+#[no_mangle]
+pub unsafe extern "C" fn ls_result_item(result: *const LsResult, index: size_t) -> *const Entry {
+    match result.as_ref() {
+        Some(result) if index < result.size => result.items.add(index),
+        _ => ptr::null(),
     }
-    let handle = |process_info: dir::TransitProcess| {
-        let p_info = DirTransitProcess {
-            copied_bytes: process_info.copied_bytes,
-            total_bytes: process_info.total_bytes,
-            file_bytes_copied: process_info.file_bytes_copied,
-            file_total_bytes: process_info.file_total_bytes,
-            file_name: get_c_string(process_info.file_name.as_str()).into_raw(),
-            state: get_int_transit_state(process_info.state),
-        };
+}
 
-        get_enum_transit_result(cb(p_info))
-    };
+/// Number of entries in `warnings` of a `dir_ls` result.
+#[no_mangle]
+pub unsafe extern "C" fn ls_result_warnings_size(result: *const LsResult) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.warnings_size,
+        None => 0,
+    }
+}
 
-    match dir::copy_with_progress(from_path, to_path, &options, handle) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
-        }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
+/// Warning `index` of a `dir_ls` result, NULL when out of range. The warning
+/// stays owned by the result.
+#[no_mangle]
+pub unsafe extern "C" fn ls_result_warning(result: *const LsResult,
+                                           index: size_t)
+                                           -> *const FileError {
+    match result.as_ref() {
+        Some(result) if index < result.warnings_size => result.warnings.add(index),
+        _ => ptr::null(),
     }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
 }
 
+/// Releases a result of `dir_ls` with all of its entries and warnings.
+#[no_mangle]
+pub unsafe extern "C" fn ls_result_free(result: *mut LsResult) {
+    guard_free(|| {
+        if !result.is_null() {
+            drop(Box::from_raw(result));
+        }
+    })
+}
 
+/// Options of the copies and moves. Start from `copy_options_init` and set the
+/// fields to change; later versions only add fields at the end, and the library
+/// takes the defaults for those past `size`.
+#[repr(C)]
+pub struct CopyOptions {
+    /// `sizeof(CopyOptions)` as the caller was built with it.
+    pub size: size_t,
+    pub overwrite: bool,
+    pub skip_exist: bool,
+    /// Go on past the files that fail and report them in `file_errors` of the
+    /// result. Used by the directory and batch copies and moves.
+    pub continue_on_error: bool,
+    /// Copy a directory into a target that does not exist yet as the target
+    /// itself rather than into a new directory below it.
+    pub copy_inside: bool,
+    /// Copy only what is inside the source directory, not the directory.
+    pub content_only: bool,
+    /// Must not be 0.
+    pub buffer_size: size_t,
+    /// How many levels below the source directory a copy goes, 0 for all of
+    /// them. Moves always take the whole tree.
+    pub depth: u64,
+    /// Entries the directory and batch copies and moves leave out, NULL for
+    /// none. Only read during the call, jobs keep a copy of their own.
+    pub filter: *const Filter,
+    /// Give the copied files and directories the mode bits of their sources.
+    pub preserve_permissions: bool,
+    /// Give the copied files and directories the access and modification
+    /// times of their sources. Directories get theirs after their contents.
+    pub preserve_times: bool,
+    /// Give the copied files and directories the owner and group of their
+    /// sources, where the process is allowed to; ignored on Windows.
+    pub preserve_owner: bool,
+    /// Copy the extended attributes along with the data, POSIX ACLs included.
+    /// Those the target file system does not take end up in `warnings` of the
+    /// result. Linux and macOS only.
+    pub preserve_xattrs: bool,
+    /// One of the `SYMLINK_POLICY_*` values for the directory and batch copies
+    /// and moves. A followed symlink that loops back fails like an unreadable
+    /// file, one leading outside the source directory ends up in `warnings`
+    /// of the result. A move leaves what a followed symlink leads to in place.
+    pub symlinks: u8,
+    /// Recreate the hard links between the files of the directory and batch
+    /// copies and moves instead of copying each link in full. Unix only.
+    pub preserve_hard_links: bool,
+}
 
-#[no_mangle]
-pub unsafe extern "C" fn file_move_with_progress(from: *const c_char,
-                                                 to: *const c_char,
-                                                 options: *mut CopyOptions,
-                                                 cb: extern "C" fn(file::TransitProcess))
-                                                 -> *mut U64Result {
-    let options = &mut *options;
-    let options = file::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+/// Where `CopyOptions` ended in each released version, from the first one with
+/// a `size` field. The comments name the fields each version added.
+const COPY_OPTIONS_ENDS: [usize; 5] = [
+    // size, copy_inside, content_only and depth
+    mem::offset_of!(CopyOptions, preserve_permissions),
+    // preserve_permissions, preserve_times and preserve_owner
+    mem::offset_of!(CopyOptions, preserve_xattrs),
+    // preserve_xattrs
+    mem::offset_of!(CopyOptions, symlinks),
+    // symlinks
+    mem::offset_of!(CopyOptions, preserve_hard_links),
+    // preserve_hard_links
+    mem::size_of::<CopyOptions>(),
+];
+
+/// Whether `size` is `sizeof(CopyOptions)` of a released version, its end
+/// padded to the alignment of the struct as C does.
+fn is_released_copy_options_size(size: usize) -> bool {
+    let align = mem::align_of::<CopyOptions>();
+    COPY_OPTIONS_ENDS.iter().any(|end| end.next_multiple_of(align) == size)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+fn get_default_copy_options() -> CopyOptions {
+    let defaults = dir::CopyOptions::new();
+    CopyOptions {
+        size: mem::size_of::<CopyOptions>(),
+        overwrite: defaults.overwrite,
+        skip_exist: defaults.skip_exist,
+        continue_on_error: false,
+        copy_inside: defaults.copy_inside,
+        content_only: defaults.content_only,
+        buffer_size: defaults.buffer_size,
+        depth: defaults.depth,
+        filter: ptr::null(),
+        preserve_permissions: false,
+        preserve_times: false,
+        preserve_owner: false,
+        preserve_xattrs: false,
+        symlinks: SYMLINK_POLICY_FOLLOW,
+        preserve_hard_links: false,
     }
-    let handle = |process_info: file::TransitProcess| cb(process_info);
+}
+
+/// Fills `options` with the fs_extra defaults and no filter. Pass
+/// `sizeof(CopyOptions)`: only that many bytes are written, at most those of
+/// this version, and `size` is set to their number. Nothing is written to a
+/// NULL `options` or one too small for `size`.
+#[no_mangle]
+pub unsafe extern "C" fn copy_options_init(options: *mut CopyOptions, size: size_t) {
+    if options.is_null() || size < mem::size_of::<size_t>() {
+        return;
+    }
+    let size = size.min(mem::size_of::<CopyOptions>());
+    let defaults = get_default_copy_options();
+    ptr::copy_nonoverlapping(&defaults as *const CopyOptions as *const u8, options as *mut u8, size);
+    ptr::write(options as *mut size_t, size);
+}
+
+/// A file a continue-on-error operation went past. `error.path` holds the bytes
+/// of the path as the OS returned them.
+#[repr(C)]
+pub struct FileError {
+    /// One of the `FILE_ERROR_PHASE_*` values.
+    pub phase: u8,
+    pub error: Error,
+}
+
+/// Outcome of one path of `copy_items`, `move_items` or `remove_items`, at the
+/// index of the path in the call.
+#[repr(C)]
+pub struct ItemResult {
+    /// One of the `ITEM_STATUS_*` values.
+    pub status: u8,
+    /// Bytes copied, moved or removed for the path.
+    pub bytes: u64,
+    /// Why the path failed, empty unless `status` is `ITEM_STATUS_FAILED`.
+    pub error: Error,
+}
+
+#[repr(C)]
+pub struct U64Result {
+    pub is_error: bool,
+    pub error: Error,
+    /// On `ERROR_CODE_CANCELLED`, the bytes handled before the stop.
+    pub ok: u64,
+    /// Failures skipped by a continue-on-error operation, owned by the result.
+    pub file_errors_size: size_t,
+    pub file_errors: *const FileError,
+    /// Outcome of every path of a batch, owned by the result.
+    pub items_size: size_t,
+    pub items: *const ItemResult,
+    /// What a copy left out without failing, owned by the result. Collected
+    /// whether or not the operation continues on errors.
+    pub warnings_size: size_t,
+    pub warnings: *const FileError,
+}
 
-    match file::move_file_with_progress(from_path, to_path, &options, handle) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
+// A `U64Result` owns its file errors, items and warnings, nothing else points
+// to them.
+unsafe impl Send for U64Result {}
+
+impl Drop for U64Result {
+    fn drop(&mut self) {
+        if !self.file_errors.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.file_errors as *mut FileError,
+                                         self.file_errors_size,
+                                         self.file_errors_size));
+            }
+        }
+        if !self.items.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.items as *mut ItemResult,
+                                         self.items_size,
+                                         self.items_size));
+            }
         }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
+        if !self.warnings.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.warnings as *mut FileError,
+                                         self.warnings_size,
+                                         self.warnings_size));
             }
         }
     }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
 }
 
-
-#[no_mangle]
-pub unsafe extern "C" fn file_copy_with_progress(from: *const c_char,
-                                                 to: *const c_char,
-                                                 options: *mut CopyOptions,
-                                                 cb: extern "C" fn(file::TransitProcess))
-                                                 -> *mut U64Result {
-    let options = &mut *options;
-    let options = file::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
+pub(crate) fn get_file_error(failure: engine::Failure) -> FileError {
+    let phase = match failure.phase {
+        engine::Phase::Read => FILE_ERROR_PHASE_READ,
+        engine::Phase::Write => FILE_ERROR_PHASE_WRITE,
+        engine::Phase::CreateDir => FILE_ERROR_PHASE_CREATE_DIR,
+        engine::Phase::Remove => FILE_ERROR_PHASE_REMOVE,
     };
+    let mut error = failure.error;
+    error.path = Some(failure.path);
+    FileError {
+        phase,
+        error: get_path_error(error, Path::new("")),
+    }
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
+/// A failed item carries its first file error, or the error that ended the
+/// batch when it was not gone past.
+pub(crate) fn get_item_result(outcome: &engine::Outcome,
+                              file_errors: &[FileError],
+                              error: &Error)
+                              -> ItemResult {
+    let status = match outcome.status {
+        engine::Status::Done => ITEM_STATUS_DONE,
+        engine::Status::Skipped => ITEM_STATUS_SKIPPED,
+        engine::Status::Failed => ITEM_STATUS_FAILED,
+        engine::Status::NotAttempted => ITEM_STATUS_NOT_ATTEMPTED,
+    };
+    let error = match (outcome.status, outcome.failure) {
+        (engine::Status::Failed, Some(index)) => file_errors[index].error.clone(),
+        (engine::Status::Failed, None) => error.clone(),
+        _ => Error::empty(),
+    };
+    ItemResult {
+        status,
+        bytes: outcome.bytes,
+        error,
     }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+}
+
+/// Hands the failures and batch outcomes of an operation over to its result.
+pub(crate) fn set_report(result: &mut U64Result, report: engine::Report) {
+    let file_errors: Vec<FileError> = report.failures
+        .unwrap_or_default()
+        .into_iter()
+        .map(get_file_error)
+        .collect();
+    let items: Vec<ItemResult> = report.items
+        .iter()
+        .map(|outcome| get_item_result(outcome, &file_errors, &result.error))
+        .collect();
+    if !file_errors.is_empty() {
+        result.file_errors_size = file_errors.len();
+        result.file_errors = Box::into_raw(file_errors.into_boxed_slice()) as *const FileError;
+    }
+    if !items.is_empty() {
+        result.items_size = items.len();
+        result.items = Box::into_raw(items.into_boxed_slice()) as *const ItemResult;
+    }
+    let warnings: Vec<FileError> = report.warnings.into_iter().map(get_file_error).collect();
+    if !warnings.is_empty() {
+        result.warnings_size = warnings.len();
+        result.warnings = Box::into_raw(warnings.into_boxed_slice()) as *const FileError;
     }
-    let handle = |process_info: file::TransitProcess| cb(process_info);
+}
 
-    match file::copy_with_progress(from_path, to_path, &options, handle) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
-        }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
+/// `guard` for the operations taking a `CancelToken`, which may be NULL, and
+/// for those that can go on past failed files.
+pub(crate) unsafe fn guard_operation<F>(token: *const CancelToken,
+                                        continue_on_error: bool,
+                                        body: F)
+                                        -> *mut U64Result
+    where F: FnOnce(Option<&engine::Cancel>, &mut engine::Report) -> Result<u64, Error>
+{
+    let cancel = get_cancel(token);
+    Box::into_raw(Box::new(run_operation(cancel.as_ref(), continue_on_error, body)))
+}
+
+/// Runs `body` with a fresh report and builds its result. A panic in either is
+/// turned into a `Panic` error like `guard` does.
+pub(crate) fn run_operation<F>(cancel: Option<&engine::Cancel>,
+                               continue_on_error: bool,
+                               body: F)
+                               -> U64Result
+    where F: FnOnce(Option<&engine::Cancel>, &mut engine::Report) -> Result<u64, Error>
+{
+    let run = AssertUnwindSafe(|| {
+        let mut report = engine::Report::new(continue_on_error);
+        let mut result = U64Result::new(body(cancel, &mut report));
+        set_cancelled_done(&mut result, cancel);
+        set_report(&mut result, report);
+        result
+    });
+    panic::catch_unwind(run)
+        .unwrap_or_else(|payload| U64Result::new(Err(get_panic_error(payload.as_ref()))))
+}
+
+#[repr(C)]
+pub struct CStringResult {
+    pub is_error: bool,
+    pub error: Error,
+    pub ok: *const c_char,
+}
+
+impl Drop for CStringResult {
+    fn drop(&mut self) {
+        unsafe { free_c_string(self.ok) }
     }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
 }
 
+/// Number of entries in `file_errors` of a result.
+#[no_mangle]
+pub unsafe extern "C" fn u64_result_file_errors_size(result: *const U64Result) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.file_errors_size,
+        None => 0,
+    }
+}
 
+/// File error `index` of a result, NULL when out of range. The error stays
+/// owned by the result.
 #[no_mangle]
-pub unsafe extern "C" fn file_copy_with_progress_free(value: *mut DetailsEntryResult) {
-    Box::from_raw(value);
+pub unsafe extern "C" fn u64_result_file_error(result: *const U64Result,
+                                               index: size_t)
+                                               -> *const FileError {
+    match result.as_ref() {
+        Some(result) if index < result.file_errors_size => result.file_errors.add(index),
+        _ => ptr::null(),
+    }
 }
 
+/// Number of entries in `items` of a result.
 #[no_mangle]
-pub unsafe extern "C" fn file_move(from: *const c_char,
-                                   to: *const c_char,
-                                   options: *mut CopyOptions)
-                                   -> *mut U64Result {
-    let options = &mut *options;
-    let options = file::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+pub unsafe extern "C" fn u64_result_items_size(result: *const U64Result) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.items_size,
+        None => 0,
+    }
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let from_path: &str;
-    let to_path: &str;
-
-    match CStr::from_ptr(from).to_str() {
-        Ok(val) => from_path = val,
-        Err(msg) => {
-            from_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
+/// Outcome of path `index` of a batch, NULL when out of range. The outcome
+/// stays owned by the result.
+#[no_mangle]
+pub unsafe extern "C" fn u64_result_item(result: *const U64Result,
+                                         index: size_t)
+                                         -> *const ItemResult {
+    match result.as_ref() {
+        Some(result) if index < result.items_size => result.items.add(index),
+        _ => ptr::null(),
     }
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
+}
+
+/// Number of entries in `warnings` of a result.
+#[no_mangle]
+pub unsafe extern "C" fn u64_result_warnings_size(result: *const U64Result) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.warnings_size,
+        None => 0,
     }
+}
 
+/// Warning `index` of a result, NULL when out of range. The warning stays owned
+/// by the result.
+#[no_mangle]
+pub unsafe extern "C" fn u64_result_warning(result: *const U64Result,
+                                            index: size_t)
+                                            -> *const FileError {
+    match result.as_ref() {
+        Some(result) if index < result.warnings_size => result.warnings.add(index),
+        _ => ptr::null(),
+    }
+}
 
-    match file::move_file(from_path, to_path, &options) {
-        Ok(copied_bytes) => {
-            result = copied_bytes;
+/// Releases a `U64Result` returned by any of the copy, move, remove, create,
+/// write or size functions.
+#[no_mangle]
+pub unsafe extern "C" fn u64_result_free(value: *mut U64Result) {
+    guard_free(|| {
+        if !value.is_null() {
+            drop(Box::from_raw(value));
         }
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
+    })
+}
+
+/// Releases a result of `file_read_to_string` together with the read content.
+#[no_mangle]
+pub unsafe extern "C" fn cstring_result_free(value: *mut CStringResult) {
+    guard_free(|| {
+        if !value.is_null() {
+            drop(Box::from_raw(value));
         }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+    })
+}
+
+
+unsafe fn do_dir_copy<P: FfiPath>(from: P,
+                                  to: P,
+                                  options: *const CopyOptions,
+                                  cancel: *const CancelToken)
+                                  -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(engine::transfer_dir(&from_path, &to_path, &options, Action::Copy, None, cancel, report)?)
+    })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn copy_items(from_list: *const *const c_char,
-                                    from_size: size_t,
-                                    to: *const c_char,
-                                    options: *mut CopyOptions)
-                                    -> *mut U64Result {
-    let options = &mut *options;
-    let from = std::slice::from_raw_parts(from_list, from_size);
-    let mut from_list = Vec::new();
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn dir_copy(from: *const c_char,
+                                  to: *const c_char,
+                                  options: *const CopyOptions,
+                                  cancel: *const CancelToken)
+                                  -> *mut U64Result {
+    do_dir_copy(from, to, options, cancel)
+}
 
-    for path in from {
-        if let Some(path) = path.as_ref() {
-            match CStr::from_ptr(path).to_str() {
-                Ok(val) => from_list.push(val),
-                Err(msg) => {
-                    is_error = true;
-                    break;
-                }
-            }
-        } else {
-            is_error = true;
-            break;
+/// `dir_copy` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_copy_os(from: OsPath,
+                                     to: OsPath,
+                                     options: *const CopyOptions,
+                                     cancel: *const CancelToken)
+                                     -> *mut U64Result {
+    do_dir_copy(from, to, options, cancel)
+}
 
-        }
-    }
 
-    if is_error {
-        err = Error {
-            kind: get_c_string("Invalid from path").into_raw(),
-            message: get_c_string("Invalid from path").into_raw(),
-        };
 
-    };
 
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+unsafe fn do_dir_move<P: FfiPath>(from: P,
+                                  to: P,
+                                  options: *const CopyOptions,
+                                  cancel: *const CancelToken)
+                                  -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let to_path: &str;
-
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
+        Ok(engine::transfer_dir(&from_path, &to_path, &options, Action::Move, None, cancel, report)?)
+    })
+}
 
+#[no_mangle]
+pub unsafe extern "C" fn dir_move(from: *const c_char,
+                                  to: *const c_char,
+                                  options: *const CopyOptions,
+                                  cancel: *const CancelToken)
+                                  -> *mut U64Result {
+    do_dir_move(from, to, options, cancel)
+}
 
-    if !is_error {
-        match fs_extra::copy_items(&from_list, to_path, &options) {
-            Ok(copied_bytes) => {
-                result = copied_bytes;
-            }
-            Err(err_item) => {
-                is_error = true;
-                err = Error {
-                    kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                    message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-                }
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+/// `dir_move` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_os(from: OsPath,
+                                     to: OsPath,
+                                     options: *const CopyOptions,
+                                     cancel: *const CancelToken)
+                                     -> *mut U64Result {
+    do_dir_move(from, to, options, cancel)
 }
 
 
+/// Same as `u64_result_free`.
 #[no_mangle]
-pub unsafe extern "C" fn move_items(from_list: *const *const c_char,
-                                    from_size: size_t,
-                                    to: *const c_char,
-                                    options: *mut CopyOptions)
-                                    -> *mut U64Result {
-    let options = &mut *options;
-    let from = std::slice::from_raw_parts(from_list, from_size);
-    let mut from_list = Vec::new();
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn move_result_free(value: *mut U64Result) {
+    u64_result_free(value)
+}
 
-    for path in from {
-        if let Some(path) = path.as_ref() {
-            match CStr::from_ptr(path).to_str() {
-                Ok(val) => from_list.push(val),
-                Err(msg) => {
-                    is_error = true;
-                    break;
-                }
-            }
-        } else {
-            is_error = true;
-            break;
 
-        }
-    }
+unsafe fn do_file_copy<P: FfiPath>(from: P,
+                                   to: P,
+                                   options: *const CopyOptions,
+                                   cancel: *const CancelToken)
+                                   -> *mut U64Result {
+    guard_operation(cancel, false, |cancel, report| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let warnings = &mut report.warnings;
+
+        Ok(engine::copy_file(&from_path, &to_path, &options, None, cancel, warnings)?)
+    })
+}
 
-    if is_error {
-        err = Error {
-            kind: get_c_string("Invalid from path").into_raw(),
-            message: get_c_string("Invalid from path").into_raw(),
-        };
+#[no_mangle]
+pub unsafe extern "C" fn file_copy(from: *const c_char,
+                                   to: *const c_char,
+                                   options: *const CopyOptions,
+                                   cancel: *const CancelToken)
+                                   -> *mut U64Result {
+    do_file_copy(from, to, options, cancel)
+}
 
-    };
+/// `file_copy` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_os(from: OsPath,
+                                      to: OsPath,
+                                      options: *const CopyOptions,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    do_file_copy(from, to, options, cancel)
+}
 
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+unsafe fn do_dir_move_with_progress<P: FfiPath>(from: P,
+                                                to: P,
+                                                options: *const CopyOptions,
+                                                cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                user_data: *mut c_void,
+                                                cancel: *const CancelToken)
+                                                -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+
+        run_with_dir_callback(cb, user_data, |handle| {
+            engine::transfer_dir(&from_path,
+                                 &to_path,
+                                 &options,
+                                 Action::Move,
+                                 Some(handle),
+                                 cancel,
+                                 report)
+        })
+    })
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let to_path: &str;
-
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
+/// `user_data` is passed back unchanged to every call of `cb`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_with_progress(from: *const c_char,
+                                                to: *const c_char,
+                                                options: *const CopyOptions,
+                                                cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                user_data: *mut c_void,
+                                                cancel: *const CancelToken)
+                                                -> *mut U64Result {
+    do_dir_move_with_progress(from, to, options, cb, user_data, cancel)
+}
 
+/// `dir_move_with_progress` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_with_progress_os(from: OsPath,
+                                                   to: OsPath,
+                                                   options: *const CopyOptions,
+                                                   cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                   user_data: *mut c_void,
+                                                   cancel: *const CancelToken)
+                                                   -> *mut U64Result {
+    do_dir_move_with_progress(from, to, options, cb, user_data, cancel)
+}
 
-    if !is_error {
-        match fs_extra::move_items(&from_list, to_path, &options) {
-            Ok(copied_bytes) => {
-                result = copied_bytes;
-            }
-            Err(err_item) => {
-                is_error = true;
-                err = Error {
-                    kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                    message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-                }
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+unsafe fn do_dir_copy_with_progress<P: FfiPath>(from: P,
+                                                to: P,
+                                                options: *const CopyOptions,
+                                                cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                user_data: *mut c_void,
+                                                cancel: *const CancelToken)
+                                                -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+
+        run_with_dir_callback(cb, user_data, |handle| {
+            engine::transfer_dir(&from_path,
+                                 &to_path,
+                                 &options,
+                                 Action::Copy,
+                                 Some(handle),
+                                 cancel,
+                                 report)
+        })
+    })
 }
 
+/// `user_data` is passed back unchanged to every call of `cb`.
+#[no_mangle]
+pub unsafe extern "C" fn dir_copy_with_progress(from: *const c_char,
+                                                to: *const c_char,
+                                                options: *const CopyOptions,
+                                                cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                user_data: *mut c_void,
+                                                cancel: *const CancelToken)
+                                                -> *mut U64Result {
+    do_dir_copy_with_progress(from, to, options, cb, user_data, cancel)
+}
 
+/// `dir_copy_with_progress` with `OsPath` arguments.
 #[no_mangle]
-pub unsafe extern "C" fn copy_items_with_progress(from_list: *const *const c_char,
-                                                  from_size: size_t,
-                                                  to: *const c_char,
-                                                  options: *mut CopyOptions,
-                                                  cb: extern "C" fn(DirTransitProcess) -> uint8_t)
-                                                  -> *mut U64Result {
-    let options = &mut *options;
-    let from = std::slice::from_raw_parts(from_list, from_size);
-    let mut from_list = Vec::new();
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn dir_copy_with_progress_os(from: OsPath,
+                                                   to: OsPath,
+                                                   options: *const CopyOptions,
+                                                   cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                   user_data: *mut c_void,
+                                                   cancel: *const CancelToken)
+                                                   -> *mut U64Result {
+    do_dir_copy_with_progress(from, to, options, cb, user_data, cancel)
+}
 
-    for path in from {
-        if let Some(path) = path.as_ref() {
-            match CStr::from_ptr(path).to_str() {
-                Ok(val) => from_list.push(val),
-                Err(msg) => {
-                    is_error = true;
-                    break;
-                }
-            }
-        } else {
-            is_error = true;
-            break;
 
-        }
-    }
 
-    if is_error {
-        err = Error {
-            kind: get_c_string("Invalid from path").into_raw(),
-            message: get_c_string("Invalid from path").into_raw(),
-        };
+unsafe fn do_file_move_with_progress<P: FfiPath>(from: P,
+                                                 to: P,
+                                                 options: *const CopyOptions,
+                                                 cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                 user_data: *mut c_void,
+                                                 cancel: *const CancelToken)
+                                                 -> *mut U64Result {
+    guard_operation(cancel, false, |cancel, report| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+        let warnings = &mut report.warnings;
+
+        run_with_file_callback(cb, user_data, |handle| {
+            engine::move_file(&from_path, &to_path, &options, Some(handle), cancel, warnings)
+        })
+    })
+}
 
-    };
+/// `user_data` is passed back unchanged to every call of `cb`. Answering
+/// `TRANSIT_PROCESS_RESULT_ABORT` stops the copy, removes the partly written
+/// target and fails with `ERROR_CODE_INTERRUPTED`.
+#[no_mangle]
+pub unsafe extern "C" fn file_move_with_progress(from: *const c_char,
+                                                 to: *const c_char,
+                                                 options: *const CopyOptions,
+                                                 cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                 user_data: *mut c_void,
+                                                 cancel: *const CancelToken)
+                                                 -> *mut U64Result {
+    do_file_move_with_progress(from, to, options, cb, user_data, cancel)
+}
 
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+/// `file_move_with_progress` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_move_with_progress_os(from: OsPath,
+                                                    to: OsPath,
+                                                    options: *const CopyOptions,
+                                                    cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                    user_data: *mut c_void,
+                                                    cancel: *const CancelToken)
+                                                    -> *mut U64Result {
+    do_file_move_with_progress(from, to, options, cb, user_data, cancel)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let to_path: &str;
-
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
 
+unsafe fn do_file_copy_with_progress<P: FfiPath>(from: P,
+                                                 to: P,
+                                                 options: *const CopyOptions,
+                                                 cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                 user_data: *mut c_void,
+                                                 cancel: *const CancelToken)
+                                                 -> *mut U64Result {
+    guard_operation(cancel, false, |cancel, report| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+        let warnings = &mut report.warnings;
+
+        run_with_file_callback(cb, user_data, |handle| {
+            engine::copy_file(&from_path, &to_path, &options, Some(handle), cancel, warnings)
+        })
+    })
+}
 
-    if !is_error {
-        let handle = |process_info: fs_extra::TransitProcess| {
-            let p_info = DirTransitProcess {
-                copied_bytes: process_info.copied_bytes,
-                total_bytes: process_info.total_bytes,
-                file_bytes_copied: process_info.file_bytes_copied,
-                file_total_bytes: process_info.file_total_bytes,
-                file_name: get_c_string(process_info.file_name.as_str()).into_raw(),
-                state: get_int_transit_state(process_info.state),
-            };
+/// `user_data` is passed back unchanged to every call of `cb`. Answering
+/// `TRANSIT_PROCESS_RESULT_ABORT` stops the copy, removes the partly written
+/// target and fails with `ERROR_CODE_INTERRUPTED`.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_with_progress(from: *const c_char,
+                                                 to: *const c_char,
+                                                 options: *const CopyOptions,
+                                                 cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                 user_data: *mut c_void,
+                                                 cancel: *const CancelToken)
+                                                 -> *mut U64Result {
+    do_file_copy_with_progress(from, to, options, cb, user_data, cancel)
+}
 
-            get_enum_transit_result(cb(p_info))
-        };
+/// `file_copy_with_progress` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_with_progress_os(from: OsPath,
+                                                    to: OsPath,
+                                                    options: *const CopyOptions,
+                                                    cb: Option<extern "C" fn(FileTransitProcess, *mut c_void) -> u8>,
+                                                    user_data: *mut c_void,
+                                                    cancel: *const CancelToken)
+                                                    -> *mut U64Result {
+    do_file_copy_with_progress(from, to, options, cb, user_data, cancel)
+}
 
 
-        match fs_extra::copy_items_with_progress(&from_list, to_path, &options, handle) {
-            Ok(copied_bytes) => {
-                result = copied_bytes;
-            }
-            Err(err_item) => {
-                is_error = true;
-                err = Error {
-                    kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                    message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-                }
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+/// Same as `u64_result_free`.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_with_progress_free(value: *mut U64Result) {
+    u64_result_free(value)
 }
 
+unsafe fn do_file_move<P: FfiPath>(from: P,
+                                   to: P,
+                                   options: *const CopyOptions,
+                                   cancel: *const CancelToken)
+                                   -> *mut U64Result {
+    guard_operation(cancel, false, |cancel, report| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let warnings = &mut report.warnings;
+
+        Ok(engine::move_file(&from_path, &to_path, &options, None, cancel, warnings)?)
+    })
+}
 
 #[no_mangle]
-pub unsafe extern "C" fn move_items_with_progress(from_list: *const *const c_char,
-                                                  from_size: size_t,
-                                                  to: *const c_char,
-                                                  options: *mut CopyOptions,
-                                                  cb: extern "C" fn(DirTransitProcess) -> uint8_t)
-                                                  -> *mut U64Result {
-    let options = &mut *options;
-    let from = std::slice::from_raw_parts(from_list, from_size);
-    let mut from_list = Vec::new();
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-
-    for path in from {
-        if let Some(path) = path.as_ref() {
-            match CStr::from_ptr(path).to_str() {
-                Ok(val) => from_list.push(val),
-                Err(msg) => {
-                    is_error = true;
-                    break;
-                }
-            }
-        } else {
-            is_error = true;
-            break;
+pub unsafe extern "C" fn file_move(from: *const c_char,
+                                   to: *const c_char,
+                                   options: *const CopyOptions,
+                                   cancel: *const CancelToken)
+                                   -> *mut U64Result {
+    do_file_move(from, to, options, cancel)
+}
 
-        }
-    }
+/// `file_move` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_move_os(from: OsPath,
+                                      to: OsPath,
+                                      options: *const CopyOptions,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    do_file_move(from, to, options, cancel)
+}
 
-    if is_error {
-        err = Error {
-            kind: get_c_string("Invalid from path").into_raw(),
-            message: get_c_string("Invalid from path").into_raw(),
-        };
+unsafe fn do_copy_items<P: FfiPath>(from_list: *const P,
+                                    from_size: size_t,
+                                    to: P,
+                                    options: *const CopyOptions,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
 
-    };
+        Ok(engine::transfer_items(&from_list, &to_path, &options, Action::Copy, None, cancel, report)?)
+    })
+}
 
-    let options = dir::CopyOptions {
-        overwrite: options.overwrite,
-        skip_exist: options.skip_exist,
-        buffer_size: options.buffer_size,
-    };
+#[no_mangle]
+pub unsafe extern "C" fn copy_items(from_list: *const *const c_char,
+                                    from_size: size_t,
+                                    to: *const c_char,
+                                    options: *const CopyOptions,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    do_copy_items(from_list, from_size, to, options, cancel)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-    let mut result = 0;
-    let to_path: &str;
-
-    match CStr::from_ptr(to).to_str() {
-        Ok(val) => to_path = val,
-        Err(msg) => {
-            to_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid to path").into_raw(),
-                message: get_c_string("Invalid to path").into_raw(),
-            };
-        }
-    }
+/// `copy_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn copy_items_os(from_list: *const OsPath,
+                                       from_size: size_t,
+                                       to: OsPath,
+                                       options: *const CopyOptions,
+                                       cancel: *const CancelToken)
+                                       -> *mut U64Result {
+    do_copy_items(from_list, from_size, to, options, cancel)
+}
 
 
-    if !is_error {
-        let handle = |process_info: fs_extra::TransitProcess| {
-            let p_info = DirTransitProcess {
-                copied_bytes: process_info.copied_bytes,
-                total_bytes: process_info.total_bytes,
-                file_bytes_copied: process_info.file_bytes_copied,
-                file_total_bytes: process_info.file_total_bytes,
-                file_name: get_c_string(process_info.file_name.as_str()).into_raw(),
-                state: get_int_transit_state(process_info.state),
-            };
+unsafe fn do_move_items<P: FfiPath>(from_list: *const P,
+                                    from_size: size_t,
+                                    to: P,
+                                    options: *const CopyOptions,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
 
-            get_enum_transit_result(cb(p_info))
-        };
+        Ok(engine::transfer_items(&from_list, &to_path, &options, Action::Move, None, cancel, report)?)
+    })
+}
 
+#[no_mangle]
+pub unsafe extern "C" fn move_items(from_list: *const *const c_char,
+                                    from_size: size_t,
+                                    to: *const c_char,
+                                    options: *const CopyOptions,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    do_move_items(from_list, from_size, to, options, cancel)
+}
 
-        match fs_extra::move_items_with_progress(&from_list, to_path, &options, handle) {
-            Ok(copied_bytes) => {
-                result = copied_bytes;
-            }
-            Err(err_item) => {
-                is_error = true;
-                err = Error {
-                    kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                    message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-                }
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+/// `move_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn move_items_os(from_list: *const OsPath,
+                                       from_size: size_t,
+                                       to: OsPath,
+                                       options: *const CopyOptions,
+                                       cancel: *const CancelToken)
+                                       -> *mut U64Result {
+    do_move_items(from_list, from_size, to, options, cancel)
 }
 
 
+unsafe fn do_copy_items_with_progress<P: FfiPath>(from_list: *const P,
+                                                  from_size: size_t,
+                                                  to: P,
+                                                  options: *const CopyOptions,
+                                                  cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                  user_data: *mut c_void,
+                                                  cancel: *const CancelToken)
+                                                  -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+
+        run_with_dir_callback(cb, user_data, |handle| {
+            engine::transfer_items(&from_list,
+                                   &to_path,
+                                   &options,
+                                   Action::Copy,
+                                   Some(handle),
+                                   cancel,
+                                   report)
+        })
+    })
+}
+
+/// `user_data` is passed back unchanged to every call of `cb`.
 #[no_mangle]
-pub unsafe extern "C" fn file_remove(path: *const c_char) -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn copy_items_with_progress(from_list: *const *const c_char,
+                                                  from_size: size_t,
+                                                  to: *const c_char,
+                                                  options: *const CopyOptions,
+                                                  cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                  user_data: *mut c_void,
+                                                  cancel: *const CancelToken)
+                                                  -> *mut U64Result {
+    do_copy_items_with_progress(from_list, from_size, to, options, cb, user_data, cancel)
+}
 
-    let remove_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => remove_path = val,
-        Err(msg) => {
-            remove_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
+/// `copy_items_with_progress` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn copy_items_with_progress_os(from_list: *const OsPath,
+                                                     from_size: size_t,
+                                                     to: OsPath,
+                                                     options: *const CopyOptions,
+                                                     cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                     user_data: *mut c_void,
+                                                     cancel: *const CancelToken)
+                                                     -> *mut U64Result {
+    do_copy_items_with_progress(from_list, from_size, to, options, cb, user_data, cancel)
+}
 
 
-    match file::remove(remove_path) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+unsafe fn do_move_items_with_progress<P: FfiPath>(from_list: *const P,
+                                                  from_size: size_t,
+                                                  to: P,
+                                                  options: *const CopyOptions,
+                                                  cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                  user_data: *mut c_void,
+                                                  cancel: *const CancelToken)
+                                                  -> *mut U64Result {
+    guard_operation(cancel, get_continue_on_error(options), |cancel, report| {
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+        let cb = get_callback(cb)?;
+
+        run_with_dir_callback(cb, user_data, |handle| {
+            engine::transfer_items(&from_list,
+                                   &to_path,
+                                   &options,
+                                   Action::Move,
+                                   Some(handle),
+                                   cancel,
+                                   report)
+        })
+    })
 }
 
+/// `user_data` is passed back unchanged to every call of `cb`.
+#[no_mangle]
+pub unsafe extern "C" fn move_items_with_progress(from_list: *const *const c_char,
+                                                  from_size: size_t,
+                                                  to: *const c_char,
+                                                  options: *const CopyOptions,
+                                                  cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                  user_data: *mut c_void,
+                                                  cancel: *const CancelToken)
+                                                  -> *mut U64Result {
+    do_move_items_with_progress(from_list, from_size, to, options, cb, user_data, cancel)
+}
 
+/// `move_items_with_progress` with `OsPath` arguments.
 #[no_mangle]
-pub unsafe extern "C" fn dir_remove(path: *const c_char) -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn move_items_with_progress_os(from_list: *const OsPath,
+                                                     from_size: size_t,
+                                                     to: OsPath,
+                                                     options: *const CopyOptions,
+                                                     cb: Option<extern "C" fn(DirTransitProcess, *mut c_void) -> u8>,
+                                                     user_data: *mut c_void,
+                                                     cancel: *const CancelToken)
+                                                     -> *mut U64Result {
+    do_move_items_with_progress(from_list, from_size, to, options, cb, user_data, cancel)
+}
 
-    let remove_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => remove_path = val,
-        Err(msg) => {
-            remove_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
 
+unsafe fn do_file_remove<P: FfiPath>(path: P)
+                                     -> *mut U64Result {
+    guard(|| {
+        let remove_path = path.get_path("Invalid from path")?;
 
-    match dir::remove(remove_path) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+        engine::remove_file(&remove_path).map_err(|err| get_path_error(err, &remove_path))?;
+        Ok(0)
+    })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn file_remove(path: *const c_char)
+                                     -> *mut U64Result {
+    do_file_remove(path)
+}
 
+/// `file_remove` with `OsPath` arguments.
 #[no_mangle]
-pub unsafe extern "C" fn remove_items(path_list: *const *const c_char,
-                                      path_size: size_t)
-                                      -> *mut U64Result {
-    let path_list = std::slice::from_raw_parts(path_list, path_size);
+pub unsafe extern "C" fn file_remove_os(path: OsPath)
+                                        -> *mut U64Result {
+    do_file_remove(path)
+}
 
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
 
-    let remove_path: &str;
-    let mut paths = Vec::new();
-    for path in path_list {
-        if let Some(path) = path.as_ref() {
-            match CStr::from_ptr(path).to_str() {
-                Ok(val) => paths.push(val),
-                Err(msg) => {
-                    is_error = true;
-                    break;
-                }
-            }
-        } else {
-            is_error = true;
-            break;
+unsafe fn do_dir_remove<P: FfiPath>(path: P,
+                                    continue_on_error: bool,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    guard_operation(cancel, continue_on_error, |cancel, report| {
+        let remove_path = path.get_path("Invalid from path")?;
 
-        }
-    }
+        engine::remove_dir(&remove_path, cancel, report)
+            .map_err(|err| get_path_error(err, &remove_path))?;
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dir_remove(path: *const c_char,
+                                    continue_on_error: bool,
+                                    cancel: *const CancelToken)
+                                    -> *mut U64Result {
+    do_dir_remove(path, continue_on_error, cancel)
+}
 
+/// `dir_remove` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_remove_os(path: OsPath,
+                                       continue_on_error: bool,
+                                       cancel: *const CancelToken)
+                                       -> *mut U64Result {
+    do_dir_remove(path, continue_on_error, cancel)
+}
 
-    match fs_extra::remove_items(&paths) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+
+unsafe fn do_remove_items<P: FfiPath>(path_list: *const P,
+                                      path_size: size_t,
+                                      continue_on_error: bool,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    guard_operation(cancel, continue_on_error, |cancel, report| {
+        let paths = get_path_list(path_list, path_size, "Invalid from path")?;
+
+        engine::remove_items(&paths, cancel, report)?;
+        Ok(0)
+    })
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn dir_create(path: *const c_char, erase: bool) -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn remove_items(path_list: *const *const c_char,
+                                      path_size: size_t,
+                                      continue_on_error: bool,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    do_remove_items(path_list, path_size, continue_on_error, cancel)
+}
 
-    let create_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => create_path = val,
-        Err(msg) => {
-            create_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
+/// `remove_items` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn remove_items_os(path_list: *const OsPath,
+                                         path_size: size_t,
+                                         continue_on_error: bool,
+                                         cancel: *const CancelToken)
+                                         -> *mut U64Result {
+    do_remove_items(path_list, path_size, continue_on_error, cancel)
+}
 
+unsafe fn do_dir_create<P: FfiPath>(path: P,
+                                    erase: bool)
+                                    -> *mut U64Result {
+    guard(|| {
+        let create_path = path.get_path("Invalid from path")?;
 
-    match dir::create(create_path, erase) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+        engine::create_dir(&create_path, erase, false)
+            .map_err(|err| get_path_error(err, &create_path))?;
+        Ok(0)
+    })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn dir_create(path: *const c_char,
+                                    erase: bool)
+                                    -> *mut U64Result {
+    do_dir_create(path, erase)
+}
 
+/// `dir_create` with `OsPath` arguments.
 #[no_mangle]
-pub unsafe extern "C" fn dir_create_all(path: *const c_char, erase: bool) -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn dir_create_os(path: OsPath,
+                                       erase: bool)
+                                       -> *mut U64Result {
+    do_dir_create(path, erase)
+}
 
-    let create_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => create_path = val,
-        Err(msg) => {
-            create_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
 
+unsafe fn do_dir_create_all<P: FfiPath>(path: P,
+                                        erase: bool)
+                                        -> *mut U64Result {
+    guard(|| {
+        let create_path = path.get_path("Invalid from path")?;
 
-    match dir::create_all(create_path, erase) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+        engine::create_dir(&create_path, erase, true)
+            .map_err(|err| get_path_error(err, &create_path))?;
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn dir_create_all(path: *const c_char,
+                                        erase: bool)
+                                        -> *mut U64Result {
+    do_dir_create_all(path, erase)
+}
+
+/// `dir_create_all` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_create_all_os(path: OsPath,
+                                           erase: bool)
+                                           -> *mut U64Result {
+    do_dir_create_all(path, erase)
 }
 
 
+unsafe fn do_file_write_all<P: FfiPath>(path: P,
+                                        content: *const c_char)
+                                        -> *mut U64Result {
+    guard(|| {
+        let create_path = path.get_path("Invalid from path")?;
+        let file_content = get_str(content, "Invalid content")?;
+
+        engine::write_all(&create_path, file_content)
+            .map_err(|err| get_path_error(err, &create_path))?;
+        Ok(0)
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn file_write_all(path: *const c_char,
                                         content: *const c_char)
                                         -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+    do_file_write_all(path, content)
+}
 
-    let create_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => create_path = val,
-        Err(msg) => {
-            create_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
-    let file_content: &str;
-    match CStr::from_ptr(content).to_str() {
-        Ok(val) => file_content = val,
-        Err(msg) => {
-            file_content = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
+/// `file_write_all` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_write_all_os(path: OsPath,
+                                           content: *const c_char)
+                                           -> *mut U64Result {
+    do_file_write_all(path, content)
+}
 
 
-    match file::write_all(create_path, file_content) {
-        Ok(()) => {}
-        Err(err_item) => {
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: 0,
-    };
-    Box::into_raw(Box::new(result))
+unsafe fn do_dir_get_size<P: FfiPath>(path: P,
+                                      filter: *const Filter,
+                                      symlinks: u8,
+                                      count_links_once: bool,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    guard_operation(cancel, false, |cancel, _| {
+        let symlinks = get_symlinks(symlinks)?;
+        let dir_path = path.get_path("Invalid from path")?;
+
+        engine::get_size(&dir_path, filter.as_ref(), symlinks, count_links_once, cancel)
+            .map_err(|err| get_path_error(err, &dir_path))
+    })
 }
 
+/// `symlinks` is one of the `SYMLINK_POLICY_*` values, a followed symlink that
+/// loops back fails the call. With `count_links_once`, the data of hard-linked
+/// files is counted once, on Unix.
+#[no_mangle]
+pub unsafe extern "C" fn dir_get_size(path: *const c_char,
+                                      filter: *const Filter,
+                                      symlinks: u8,
+                                      count_links_once: bool,
+                                      cancel: *const CancelToken)
+                                      -> *mut U64Result {
+    do_dir_get_size(path, filter, symlinks, count_links_once, cancel)
+}
 
+/// `dir_get_size` with `OsPath` arguments.
 #[no_mangle]
-pub unsafe extern "C" fn dir_get_size(path: *const c_char) -> *mut U64Result {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
+pub unsafe extern "C" fn dir_get_size_os(path: OsPath,
+                                         filter: *const Filter,
+                                         symlinks: u8,
+                                         count_links_once: bool,
+                                         cancel: *const CancelToken)
+                                         -> *mut U64Result {
+    do_dir_get_size(path, filter, symlinks, count_links_once, cancel)
+}
 
-    let dir_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => dir_path = val,
-        Err(msg) => {
-            dir_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
 
+unsafe fn do_file_read_to_string<P: FfiPath>(path: P)
+                                             -> *mut CStringResult {
+    guard(|| {
+        let file_path = path.get_path("Invalid from path")?;
 
-    let result: u64;
-    match dir::get_size(dir_path) {
-        Ok(val) => result = val,
-        Err(err_item) => {
-            result = 0;
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
-        }
-    }
-    let result = U64Result {
-        is_error: is_error,
-        error: err,
-        ok: result,
-    };
-    Box::into_raw(Box::new(result))
+        engine::read_to_string(&file_path)
+            .map_err(|err| get_path_error(err, &file_path))
+    })
 }
 
-
+/// Reads a whole file, its content is cut at the first NUL byte.
 #[no_mangle]
-pub unsafe extern "C" fn file_read_to_string(path: *const c_char) -> *mut CStringResult {
-    let mut is_error = false;
-    let mut err = Error {
-        kind: get_c_string("").into_raw(),
-        message: get_c_string("").into_raw(),
-    };
-
-    let file_path: &str;
-    match CStr::from_ptr(path).to_str() {
-        Ok(val) => file_path = val,
-        Err(msg) => {
-            file_path = "";
-            is_error = true;
-            err = Error {
-                kind: get_c_string("Invalid from path").into_raw(),
-                message: get_c_string("Invalid from path").into_raw(),
-            };
-        }
-    }
+pub unsafe extern "C" fn file_read_to_string(path: *const c_char)
+                                             -> *mut CStringResult {
+    do_file_read_to_string(path)
+}
 
+/// `file_read_to_string` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_read_to_string_os(path: OsPath)
+                                                -> *mut CStringResult {
+    do_file_read_to_string(path)
+}
 
-    let result: CString;
-    match file::read_to_string(file_path) {
-        Ok(val) => result = get_c_string(val.as_str()),
-        Err(err_item) => {
-            result = get_c_string("");
-            is_error = true;
-            err = Error {
-                kind: get_c_string(format!("{:?}", err_item.kind).as_str()).into_raw(),
-                message: get_c_string(format!("{}", err_item.to_string()).as_str()).into_raw(),
-            }
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::ptr;
+
+    use fs_extra::dir::TransitProcessResult;
+
+    use engine::{self, Action};
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fs_extra_ffi_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("dst")).unwrap();
+        fs::write(dir.join("src").join("file"), vec![b'x'; 1000]).unwrap();
+        dir
+    }
+
+    unsafe fn assert_panic(result: *mut U64Result, message: &str) {
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_PANIC);
+        assert_eq!(CStr::from_ptr((*result).error.message).to_str().unwrap(), message);
+        u64_result_free(result);
+    }
+
+    #[test]
+    fn panic_in_progress_handler_becomes_error() {
+        let dir = test_dir("panic_handler");
+        let options = unsafe { get_transfer_options(ptr::null()) }.ok().unwrap();
+        unsafe {
+            let result = guard_operation(ptr::null(), false, |cancel, report| {
+                // What `run_with_dir_callback` hands the engine, with a
+                // trampoline that panics instead of calling C.
+                let mut handle = |_: &engine::Progress| -> TransitProcessResult {
+                    panic!("panic in trampoline")
+                };
+                let handler = Some(&mut handle as engine::Handler);
+                Ok(engine::transfer_dir(&dir.join("src"),
+                                        &dir.join("dst"),
+                                        &options,
+                                        Action::Copy,
+                                        handler,
+                                        cancel,
+                                        report)?)
+            });
+            assert_panic(result, "panic in trampoline");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn panic_while_building_result_becomes_error() {
+        unsafe {
+            let result = guard_operation(ptr::null(), true, |_, report| {
+                // Points past the failures, which `set_report` indexes.
+                report.items.push(engine::Outcome {
+                    status: engine::Status::Failed,
+                    bytes: 0,
+                    failure: Some(1),
+                });
+                Ok(0)
+            });
+            assert!((*result).is_error);
+            assert_eq!((*result).error.code, ERROR_CODE_PANIC);
+            assert_eq!((*result).items_size, 0);
+            u64_result_free(result);
         }
     }
-    let result = CStringResult {
-        is_error: is_error,
-        error: err,
-        ok: result.into_raw(),
-    };
-    Box::into_raw(Box::new(result))
 }
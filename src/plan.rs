@@ -0,0 +1,316 @@
+// Dry runs of the copies and moves. A plan lists what the operation would do
+// with the same options, step by step, without writing anything.
+
+use std::os::raw::c_char;
+use std::ptr;
+
+use libc::size_t;
+
+use engine::{self, Action};
+use super::{CopyOptions, Error, FfiPath, FfiResult, OsPath, free_c_string, get_file_copy_options,
+            get_os_c_string, get_path_list, get_transfer_options, guard, guard_free};
+
+pub const PLAN_ACTION_CREATE_DIR: u8 = 0;
+pub const PLAN_ACTION_COPY_FILE: u8 = 1;
+pub const PLAN_ACTION_OVERWRITE: u8 = 2;
+pub const PLAN_ACTION_SKIP_EXISTING: u8 = 3;
+pub const PLAN_ACTION_REMOVE_SOURCE: u8 = 4;
+/// A symlink recreated as a link, `SYMLINK_POLICY_KEEP` only.
+pub const PLAN_ACTION_COPY_LINK: u8 = 5;
+/// A hard link to the copy of a file planned before, `preserve_hard_links`
+/// only. Its bytes are not copied again.
+pub const PLAN_ACTION_HARD_LINK: u8 = 6;
+
+/// One step of a plan. The paths hold the bytes of the names as the OS returned
+/// them.
+#[repr(C)]
+pub struct PlanStep {
+    /// One of the `PLAN_ACTION_*` values.
+    pub action: u8,
+    /// The target exists, or is created by an earlier step. A
+    /// `PLAN_ACTION_COPY_FILE` step with a conflict fails the operation unless
+    /// a progress callback resolves it; a `PLAN_ACTION_COPY_LINK` one always
+    /// does. A `PLAN_ACTION_HARD_LINK` one replaces the target with
+    /// `overwrite` and fails without it.
+    pub conflict: bool,
+    /// Bytes of the file or of the link itself, 0 for directories.
+    pub size: u64,
+    pub from: *const c_char,
+    /// Empty for `PLAN_ACTION_REMOVE_SOURCE`.
+    pub to: *const c_char,
+}
+
+impl Drop for PlanStep {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.from);
+            free_c_string(self.to);
+        }
+    }
+}
+
+/// Owns the steps of a plan, read them with `plan_result_step` and release all
+/// of it with `plan_result_free`.
+#[repr(C)]
+pub struct PlanResult {
+    pub is_error: bool,
+    pub error: Error,
+    /// Bytes the copied and overwritten files add up to, conflicts left out.
+    pub total_bytes: u64,
+    pub size: size_t,
+    pub steps: *const PlanStep,
+}
+
+impl Drop for PlanResult {
+    fn drop(&mut self) {
+        if !self.steps.is_null() {
+            unsafe {
+                drop(Vec::from_raw_parts(self.steps as *mut PlanStep, self.size, self.size));
+            }
+        }
+    }
+}
+
+impl FfiResult for PlanResult {
+    type Ok = Vec<PlanStep>;
+
+    fn new(result: Result<Vec<PlanStep>, Error>) -> PlanResult {
+        match result {
+            Ok(steps) => {
+                let total_bytes = steps.iter()
+                    .filter(|step| match step.action {
+                        PLAN_ACTION_COPY_FILE | PLAN_ACTION_COPY_LINK => !step.conflict,
+                        PLAN_ACTION_OVERWRITE => true,
+                        _ => false,
+                    })
+                    .map(|step| step.size)
+                    .sum();
+                PlanResult {
+                    is_error: false,
+                    error: Error::empty(),
+                    total_bytes,
+                    size: steps.len(),
+                    steps: Box::into_raw(steps.into_boxed_slice()) as *const PlanStep,
+                }
+            }
+            Err(error) => {
+                PlanResult {
+                    is_error: true,
+                    error,
+                    total_bytes: 0,
+                    size: 0,
+                    steps: ptr::null(),
+                }
+            }
+        }
+    }
+}
+
+fn get_plan_step(step: engine::Step) -> PlanStep {
+    let action = match step.action {
+        engine::StepAction::CreateDir => PLAN_ACTION_CREATE_DIR,
+        engine::StepAction::CopyFile => PLAN_ACTION_COPY_FILE,
+        engine::StepAction::CopyLink => PLAN_ACTION_COPY_LINK,
+        engine::StepAction::HardLink => PLAN_ACTION_HARD_LINK,
+        engine::StepAction::Overwrite => PLAN_ACTION_OVERWRITE,
+        engine::StepAction::SkipExisting => PLAN_ACTION_SKIP_EXISTING,
+        engine::StepAction::RemoveSource => PLAN_ACTION_REMOVE_SOURCE,
+    };
+    PlanStep {
+        action,
+        conflict: step.conflict,
+        size: step.size,
+        from: get_os_c_string(step.from.as_os_str()).into_raw(),
+        to: get_os_c_string(step.to.as_os_str()).into_raw(),
+    }
+}
+
+fn get_plan_steps(steps: Vec<engine::Step>) -> Vec<PlanStep> {
+    steps.into_iter().map(get_plan_step).collect()
+}
+
+unsafe fn do_dir_plan<P: FfiPath>(from: P,
+                                  to: P,
+                                  options: *const CopyOptions,
+                                  action: Action)
+                                  -> *mut PlanResult {
+    guard(|| {
+        let options = get_transfer_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(get_plan_steps(engine::plan_dir(&from_path, &to_path, &options, action)?))
+    })
+}
+
+unsafe fn do_file_plan<P: FfiPath>(from: P,
+                                   to: P,
+                                   options: *const CopyOptions,
+                                   action: Action)
+                                   -> *mut PlanResult {
+    guard(|| {
+        let options = get_file_copy_options(options)?;
+        let from_path = from.get_path("Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(get_plan_steps(engine::plan_file(&from_path, &to_path, &options.file, action)?))
+    })
+}
+
+unsafe fn do_items_plan<P: FfiPath>(from_list: *const P,
+                                    from_size: size_t,
+                                    to: P,
+                                    options: *const CopyOptions,
+                                    action: Action)
+                                    -> *mut PlanResult {
+    guard(|| {
+        let options = get_transfer_options(options)?;
+        let from_list = get_path_list(from_list, from_size, "Invalid from path")?;
+        let to_path = to.get_path("Invalid to path")?;
+
+        Ok(get_plan_steps(engine::plan_items(&from_list, &to_path, &options, action)?))
+    })
+}
+
+/// Plans `dir_copy` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn dir_copy_plan(from: *const c_char,
+                                       to: *const c_char,
+                                       options: *const CopyOptions)
+                                       -> *mut PlanResult {
+    do_dir_plan(from, to, options, Action::Copy)
+}
+
+/// `dir_copy_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_copy_plan_os(from: OsPath,
+                                          to: OsPath,
+                                          options: *const CopyOptions)
+                                          -> *mut PlanResult {
+    do_dir_plan(from, to, options, Action::Copy)
+}
+
+/// Plans `dir_move` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_plan(from: *const c_char,
+                                       to: *const c_char,
+                                       options: *const CopyOptions)
+                                       -> *mut PlanResult {
+    do_dir_plan(from, to, options, Action::Move)
+}
+
+/// `dir_move_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn dir_move_plan_os(from: OsPath,
+                                          to: OsPath,
+                                          options: *const CopyOptions)
+                                          -> *mut PlanResult {
+    do_dir_plan(from, to, options, Action::Move)
+}
+
+/// Plans `file_copy` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_plan(from: *const c_char,
+                                        to: *const c_char,
+                                        options: *const CopyOptions)
+                                        -> *mut PlanResult {
+    do_file_plan(from, to, options, Action::Copy)
+}
+
+/// `file_copy_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_copy_plan_os(from: OsPath,
+                                           to: OsPath,
+                                           options: *const CopyOptions)
+                                           -> *mut PlanResult {
+    do_file_plan(from, to, options, Action::Copy)
+}
+
+/// Plans `file_move` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn file_move_plan(from: *const c_char,
+                                        to: *const c_char,
+                                        options: *const CopyOptions)
+                                        -> *mut PlanResult {
+    do_file_plan(from, to, options, Action::Move)
+}
+
+/// `file_move_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn file_move_plan_os(from: OsPath,
+                                           to: OsPath,
+                                           options: *const CopyOptions)
+                                           -> *mut PlanResult {
+    do_file_plan(from, to, options, Action::Move)
+}
+
+/// Plans `copy_items` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn copy_items_plan(from_list: *const *const c_char,
+                                         from_size: size_t,
+                                         to: *const c_char,
+                                         options: *const CopyOptions)
+                                         -> *mut PlanResult {
+    do_items_plan(from_list, from_size, to, options, Action::Copy)
+}
+
+/// `copy_items_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn copy_items_plan_os(from_list: *const OsPath,
+                                            from_size: size_t,
+                                            to: OsPath,
+                                            options: *const CopyOptions)
+                                            -> *mut PlanResult {
+    do_items_plan(from_list, from_size, to, options, Action::Copy)
+}
+
+/// Plans `move_items` without running it.
+#[no_mangle]
+pub unsafe extern "C" fn move_items_plan(from_list: *const *const c_char,
+                                         from_size: size_t,
+                                         to: *const c_char,
+                                         options: *const CopyOptions)
+                                         -> *mut PlanResult {
+    do_items_plan(from_list, from_size, to, options, Action::Move)
+}
+
+/// `move_items_plan` with `OsPath` arguments.
+#[no_mangle]
+pub unsafe extern "C" fn move_items_plan_os(from_list: *const OsPath,
+                                            from_size: size_t,
+                                            to: OsPath,
+                                            options: *const CopyOptions)
+                                            -> *mut PlanResult {
+    do_items_plan(from_list, from_size, to, options, Action::Move)
+}
+
+/// Number of steps in a plan.
+#[no_mangle]
+pub unsafe extern "C" fn plan_result_size(result: *const PlanResult) -> size_t {
+    match result.as_ref() {
+        Some(result) => result.size,
+        None => 0,
+    }
+}
+
+/// Step `index` of a plan, NULL when out of range. The step stays owned by the
+/// result.
+#[no_mangle]
+pub unsafe extern "C" fn plan_result_step(result: *const PlanResult,
+                                          index: size_t)
+                                          -> *const PlanStep {
+    match result.as_ref() {
+        Some(result) if index < result.size => result.steps.add(index),
+        _ => ptr::null(),
+    }
+}
+
+/// Releases a plan with all of its steps.
+#[no_mangle]
+pub unsafe extern "C" fn plan_result_free(result: *mut PlanResult) {
+    guard_free(|| {
+        if !result.is_null() {
+            drop(Box::from_raw(result));
+        }
+    })
+}
@@ -0,0 +1,130 @@
+// Extended attributes of files and directories, POSIX ACLs included since Linux
+// stores them as `system.posix_acl_*` attributes. Read through symlinks, like
+// the copies read their sources. Only Linux and macOS have them here, elsewhere
+// every path has none.
+
+use std::ffi::CString;
+use std::io;
+use std::path::Path;
+
+/// Whether `err` says the file system has no extended attributes, or not this
+/// one.
+pub(crate) fn is_unsupported(err: &io::Error) -> bool {
+    match err.raw_os_error() {
+        Some(code) => code == libc::ENOTSUP || code == libc::EOPNOTSUPP,
+        None => false,
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn get_path(path: &Path) -> io::Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+/// Reads into a buffer of the size `read` asks for with an empty one, again
+/// when the value grew in between.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+fn read_sized<F: Fn(*mut libc::c_void, usize) -> libc::ssize_t>(read: F) -> io::Result<Vec<u8>> {
+    loop {
+        let size = read(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let size = read(buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+        if size >= 0 {
+            buf.truncate(size as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+/// Names of the extended attributes of `path`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub(crate) fn list(path: &Path) -> io::Result<Vec<CString>> {
+    let path = get_path(path)?;
+    let names = read_sized(|buf, size| unsafe { sys::listxattr(path.as_ptr(), buf as *mut _, size) })?;
+    Ok(names.split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| CString::new(name).unwrap_or_default())
+        .collect())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub(crate) fn get(path: &Path, name: &CString) -> io::Result<Vec<u8>> {
+    let path = get_path(path)?;
+    read_sized(|buf, size| unsafe { sys::getxattr(path.as_ptr(), name.as_ptr(), buf, size) })
+}
+
+/// Creates or replaces the attribute `name` of `path`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub(crate) fn set(path: &Path, name: &CString, value: &[u8]) -> io::Result<()> {
+    let path = get_path(path)?;
+    let value_ptr = value.as_ptr() as *const libc::c_void;
+    match unsafe { sys::setxattr(path.as_ptr(), name.as_ptr(), value_ptr, value.len()) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    pub use libc::{getxattr, listxattr};
+
+    pub unsafe fn setxattr(path: *const libc::c_char,
+                           name: *const libc::c_char,
+                           value: *const libc::c_void,
+                           size: usize)
+                           -> libc::c_int {
+        libc::setxattr(path, name, value, size, 0)
+    }
+}
+
+// The macOS calls take a resource fork position and options on top.
+#[cfg(target_os = "macos")]
+mod sys {
+    pub unsafe fn listxattr(path: *const libc::c_char,
+                            list: *mut libc::c_char,
+                            size: usize)
+                            -> libc::ssize_t {
+        libc::listxattr(path, list, size, 0)
+    }
+
+    pub unsafe fn getxattr(path: *const libc::c_char,
+                           name: *const libc::c_char,
+                           value: *mut libc::c_void,
+                           size: usize)
+                           -> libc::ssize_t {
+        libc::getxattr(path, name, value, size, 0, 0)
+    }
+
+    pub unsafe fn setxattr(path: *const libc::c_char,
+                           name: *const libc::c_char,
+                           value: *const libc::c_void,
+                           size: usize)
+                           -> libc::c_int {
+        libc::setxattr(path, name, value, size, 0, 0)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub(crate) fn list(_: &Path) -> io::Result<Vec<CString>> {
+    Ok(Vec::new())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub(crate) fn get(_: &Path, _: &CString) -> io::Result<Vec<u8>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Extended attributes are not supported"))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub(crate) fn set(_: &Path, _: &CString, _: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Extended attributes are not supported"))
+}
@@ -0,0 +1,186 @@
+// Invalid arguments come back as error results instead of taking the process
+// down.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// Checks that `result` failed on a NULL pointer and releases it.
+unsafe fn assert_null_pointer(result: *mut U64Result) {
+    assert!((*result).is_error);
+    assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+    u64_result_free(result);
+}
+
+unsafe fn message(error: &Error) -> String {
+    CStr::from_ptr(error.message).to_str().unwrap().to_string()
+}
+
+#[test]
+fn unknown_dir_entry_attr_is_rejected() {
+    let dir = common::test_dir("arguments_attr");
+    let path = c_path(&dir.join("src"));
+    let config = [DIR_ENTRY_ATTR_NAME, 200];
+    unsafe {
+        let result = dir_ls(path.as_ptr(), config.as_ptr(), config.len(), ptr::null(),
+                            SYMLINK_POLICY_KEEP);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        assert_eq!(message(&(*result).error), "Unknown DirEntryAttr value 200");
+        ls_result_free(result);
+
+        let result = dir_get_details_entry(path.as_ptr(), config.as_ptr(), config.len());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        details_entry_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unknown_symlink_policy_is_rejected() {
+    let dir = common::test_dir("arguments_symlinks");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let config = [DIR_ENTRY_ATTR_NAME];
+    let options = CopyOptions {
+        symlinks: 9,
+        ..common::options()
+    };
+    unsafe {
+        let result = dir_ls(from.as_ptr(), config.as_ptr(), config.len(), ptr::null(), 9);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        assert_eq!(message(&(*result).error), "Invalid symlink policy: 9");
+        ls_result_free(result);
+
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options, ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        u64_result_free(result);
+    }
+    assert!(!dir.join("dst").join("src").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_paths_are_rejected() {
+    let dir = common::test_dir("arguments_null");
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    let from = c_path(&dir.join("src").join("file"));
+    let to = c_path(&dir.join("dst").join("file"));
+    let config = [DIR_ENTRY_ATTR_NAME];
+    let no_path = OsPath {
+        data: ptr::null(),
+        size: 1,
+    };
+    unsafe {
+        assert_null_pointer(file_copy(ptr::null(), to.as_ptr(), ptr::null(), ptr::null()));
+        assert_null_pointer(file_copy(from.as_ptr(), ptr::null(), ptr::null(), ptr::null()));
+        assert_null_pointer(dir_copy(ptr::null(), to.as_ptr(), ptr::null(), ptr::null()));
+        assert_null_pointer(file_copy_os(no_path, no_path, ptr::null(), ptr::null()));
+        assert_null_pointer(dir_get_size(ptr::null(), ptr::null(), SYMLINK_POLICY_FOLLOW, false,
+                                         ptr::null()));
+        assert_null_pointer(file_write_all(from.as_ptr(), ptr::null()));
+
+        let result = dir_ls(ptr::null(), config.as_ptr(), config.len(), ptr::null(),
+                            SYMLINK_POLICY_KEEP);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        ls_result_free(result);
+
+        let result = dir_copy_plan(ptr::null(), to.as_ptr(), ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        plan_result_free(result);
+    }
+    assert!(!dir.join("dst").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_lists_are_rejected_unless_empty() {
+    let dir = common::test_dir("arguments_null_list");
+    let to = c_path(&dir.join("dst"));
+    let config = [DIR_ENTRY_ATTR_NAME];
+    let path = c_path(&dir.join("src"));
+    unsafe {
+        assert_null_pointer(copy_items(ptr::null(), 1, to.as_ptr(), ptr::null(), ptr::null()));
+        assert_null_pointer(move_items(ptr::null(), 1, to.as_ptr(), ptr::null(), ptr::null()));
+        assert_null_pointer(remove_items(ptr::null(), 1, false, ptr::null()));
+
+        // A list holding a NULL path fails the same way.
+        let list = [ptr::null()];
+        assert_null_pointer(copy_items(list.as_ptr(), 1, to.as_ptr(), ptr::null(), ptr::null()));
+
+        let result = copy_items(ptr::null(), 0, to.as_ptr(), ptr::null(), ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 0);
+        u64_result_free(result);
+
+        let result = dir_ls(path.as_ptr(), ptr::null(), 1, ptr::null(), SYMLINK_POLICY_KEEP);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        ls_result_free(result);
+
+        let result = dir_ls(path.as_ptr(), config.as_ptr(), 0, ptr::null(), SYMLINK_POLICY_KEEP);
+        assert!(!(*result).is_error);
+        ls_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_options_refuse_to_overwrite() {
+    let dir = common::test_dir("arguments_null_options");
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    let from = c_path(&dir.join("src").join("file"));
+    let to = c_path(&dir.join("dst").join("file"));
+    unsafe {
+        let result = file_copy(from.as_ptr(), to.as_ptr(), ptr::null(), ptr::null());
+        assert!(!(*result).is_error);
+        u64_result_free(result);
+
+        let result = file_copy(from.as_ptr(), to.as_ptr(), ptr::null(), ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_ALREADY_EXISTS);
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_callback_is_rejected() {
+    let dir = common::test_dir("arguments_null_callback");
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        assert_null_pointer(dir_copy_with_progress(from.as_ptr(), to.as_ptr(), ptr::null(), None,
+                                                   ptr::null_mut(), ptr::null()));
+        let from = c_path(&dir.join("src").join("file"));
+        assert_null_pointer(file_copy_with_progress(from.as_ptr(), to.as_ptr(), ptr::null(),
+                                                    None, ptr::null_mut(), ptr::null()));
+    }
+    assert!(!dir.join("dst").join("src").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn null_results_are_freed_quietly() {
+    unsafe {
+        u64_result_free(ptr::null_mut());
+        ls_result_free(ptr::null_mut());
+        plan_result_free(ptr::null_mut());
+        details_entry_result_free(ptr::null_mut());
+        cstring_result_free(ptr::null_mut());
+    }
+}
@@ -0,0 +1,127 @@
+// Per-item outcomes of `copy_items`, `move_items` and `remove_items`, at the
+// index of each path in the call.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// `src` holds `new` (3 bytes), `old` (5 bytes, also in `dst`) and the
+/// directory `sub` (7 bytes).
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::write(dir.join("src").join("new"), b"new").unwrap();
+    fs::write(dir.join("src").join("old"), b"older").unwrap();
+    fs::write(dir.join("src").join("sub").join("file"), b"content").unwrap();
+    fs::write(dir.join("dst").join("old"), b"kept").unwrap();
+    dir
+}
+
+fn c_paths(dir: &Path, names: &[&str]) -> Vec<CString> {
+    names.iter()
+        .map(|name| c_path(&dir.join(name)))
+        .collect()
+}
+
+/// Status and bytes of every item of `result`.
+unsafe fn get_items(result: *const U64Result) -> Vec<(u8, u64)> {
+    (0..u64_result_items_size(result))
+        .map(|index| {
+            let item = &*u64_result_item(result, index);
+            (item.status, item.bytes)
+        })
+        .collect()
+}
+
+#[test]
+fn copy_items_reports_each_item() {
+    let dir = test_dir("items_copy");
+    let items = c_paths(&dir.join("src"), &["new", "old", "missing", "sub"]);
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst"));
+    let options = CopyOptions {
+        skip_exist: true,
+        continue_on_error: true,
+        ..common::options()
+    };
+    unsafe {
+        let result = copy_items(item_ptrs.as_ptr(), item_ptrs.len(), to.as_ptr(), &options,
+                                ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 10);
+        assert_eq!(get_items(result),
+                   vec![(ITEM_STATUS_DONE, 3),
+                        (ITEM_STATUS_SKIPPED, 0),
+                        (ITEM_STATUS_FAILED, 0),
+                        (ITEM_STATUS_DONE, 7)]);
+        assert_eq!((*u64_result_item(result, 2)).error.code, ERROR_CODE_NOT_FOUND);
+        assert!(u64_result_item(result, 4).is_null());
+        u64_result_free(result);
+    }
+    assert_eq!(fs::read(dir.join("dst").join("old")).unwrap(), b"kept");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copy_items_leaves_items_after_failure_unattempted() {
+    let dir = test_dir("items_stop");
+    let items = c_paths(&dir.join("src"), &["new", "missing", "sub"]);
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = copy_items(item_ptrs.as_ptr(), item_ptrs.len(), to.as_ptr(), ptr::null(),
+                                ptr::null());
+        assert!((*result).is_error);
+        assert_eq!(get_items(result),
+                   vec![(ITEM_STATUS_DONE, 3),
+                        (ITEM_STATUS_FAILED, 0),
+                        (ITEM_STATUS_NOT_ATTEMPTED, 0)]);
+        u64_result_free(result);
+    }
+    assert!(!dir.join("dst").join("sub").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn move_items_reports_moved_bytes() {
+    let dir = test_dir("items_move");
+    let items = c_paths(&dir.join("src"), &["new", "sub"]);
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = move_items(item_ptrs.as_ptr(), item_ptrs.len(), to.as_ptr(), ptr::null(),
+                                ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!(get_items(result), vec![(ITEM_STATUS_DONE, 3), (ITEM_STATUS_DONE, 7)]);
+        u64_result_free(result);
+    }
+    assert!(!dir.join("src").join("new").exists());
+    assert!(!dir.join("src").join("sub").exists());
+    assert!(dir.join("dst").join("sub").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn remove_items_skips_missing_paths() {
+    let dir = test_dir("items_remove");
+    let items = c_paths(&dir.join("src"), &["missing", "new", "sub"]);
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    unsafe {
+        let result = remove_items(item_ptrs.as_ptr(), item_ptrs.len(), false, ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!(get_items(result),
+                   vec![(ITEM_STATUS_SKIPPED, 0), (ITEM_STATUS_DONE, 3), (ITEM_STATUS_DONE, 7)]);
+        u64_result_free(result);
+    }
+    assert_eq!(fs::read_dir(dir.join("src")).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+// Strings handed to C are freed with the layout they were allocated with, also
+// when the text they come from holds a NUL byte.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::CStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fs_extra_ffi::*;
+
+use common::{c_path, test_dir};
+
+/// Stores the size of every allocation in front of it and counts the
+/// deallocations that name a different size.
+struct CheckingAlloc;
+
+static MISMATCHES: AtomicUsize = AtomicUsize::new(0);
+
+fn header(layout: Layout) -> usize {
+    layout.align().max(16)
+}
+
+unsafe impl GlobalAlloc for CheckingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let header = header(layout);
+        let outer = Layout::from_size_align_unchecked(layout.size() + header, header);
+        let ptr = System.alloc(outer);
+        if ptr.is_null() {
+            return ptr;
+        }
+        *(ptr as *mut usize) = layout.size();
+        ptr.add(header)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let header = header(layout);
+        let ptr = ptr.sub(header);
+        let size = *(ptr as *mut usize);
+        if size != layout.size() {
+            MISMATCHES.fetch_add(1, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, Layout::from_size_align_unchecked(size + header, header))
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CheckingAlloc = CheckingAlloc;
+
+#[test]
+fn read_to_string_cuts_content_at_nul() {
+    let dir = test_dir("c_strings_content");
+    let file = dir.join("file");
+    fs::write(&file, b"abc\0defghijkl").unwrap();
+    let path = c_path(&file);
+    unsafe {
+        let result = file_read_to_string(path.as_ptr());
+        assert!(!(*result).is_error);
+        assert_eq!(CStr::from_ptr((*result).ok).to_bytes(), b"abc");
+        cstring_result_free(result);
+    }
+    assert_eq!(MISMATCHES.load(Ordering::SeqCst), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn error_path_with_nul_is_freed() {
+    let dir = test_dir("c_strings_path");
+    let file = dir.join("missing\0file");
+    let bytes = file.as_os_str().as_bytes();
+    let path = OsPath {
+        data: bytes.as_ptr(),
+        size: bytes.len(),
+    };
+    unsafe {
+        let result = file_read_to_string_os(path);
+        assert!((*result).is_error);
+        let error_path = CStr::from_ptr((*result).error.path).to_bytes();
+        assert_eq!(error_path, dir.join("missing").as_os_str().as_bytes());
+        cstring_result_free(result);
+    }
+    assert_eq!(MISMATCHES.load(Ordering::SeqCst), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,224 @@
+// Cancellation tokens stop blocking calls and jobs at the next file or buffer,
+// or hold them there while they are paused.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const FILES: usize = 5;
+const FILE_SIZE: usize = 1000;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    for n in 0..FILES {
+        fs::write(dir.join("src").join(format!("file_{}", n)), vec![b'x'; FILE_SIZE]).unwrap();
+    }
+    dir
+}
+
+/// Cancels the token in `user_data` once the second file has started.
+extern "C" fn cancel_on_second_file(info: DirTransitProcess, user_data: *mut c_void) -> u8 {
+    if info.copied_bytes > FILE_SIZE as u64 {
+        unsafe { cancel_token_cancel(user_data as *const CancelToken) };
+    }
+    TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT
+}
+
+#[test]
+fn token_starts_uncancelled() {
+    unsafe {
+        let token = cancel_token_new();
+        assert!(!cancel_token_is_cancelled(token));
+        cancel_token_cancel(token);
+        assert!(cancel_token_is_cancelled(token));
+        cancel_token_free(token);
+        assert!(!cancel_token_is_cancelled(ptr::null()));
+    }
+}
+
+#[test]
+fn token_starts_unpaused() {
+    unsafe {
+        let token = cancel_token_new();
+        assert!(!cancel_token_is_paused(token));
+        cancel_token_pause(token);
+        assert!(cancel_token_is_paused(token));
+        cancel_token_resume(token);
+        assert!(!cancel_token_is_paused(token));
+        cancel_token_free(token);
+        assert!(!cancel_token_is_paused(ptr::null()));
+    }
+}
+
+#[test]
+fn cancelled_token_stops_dir_copy_before_first_file() {
+    let dir = test_dir("cancel_before");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let token = cancel_token_new();
+        cancel_token_cancel(token);
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), token);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        assert_eq!((*result).ok, 0);
+        u64_result_free(result);
+        cancel_token_free(token);
+    }
+    assert_eq!(fs::read_dir(dir.join("dst")).unwrap().count(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cancel_during_dir_copy_removes_partial_file() {
+    let dir = test_dir("cancel_during");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let options = CopyOptions {
+        buffer_size: 100,
+        ..common::options()
+    };
+    unsafe {
+        let token = cancel_token_new();
+        let result = dir_copy_with_progress(from.as_ptr(),
+                                            to.as_ptr(),
+                                            &options,
+                                            Some(cancel_on_second_file),
+                                            token as *mut c_void,
+                                            token);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        assert_eq!((*result).ok, FILE_SIZE as u64);
+        u64_result_free(result);
+        cancel_token_free(token);
+    }
+    // Only the first file is left, in full.
+    let copied: Vec<_> = fs::read_dir(dir.join("dst").join("src")).unwrap().collect();
+    assert_eq!(copied.len(), 1);
+    assert_eq!(copied[0].as_ref().unwrap().metadata().unwrap().len(), FILE_SIZE as u64);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cancelled_token_stops_size_and_remove() {
+    let dir = test_dir("cancel_size");
+    let path = c_path(&dir.join("src"));
+    unsafe {
+        let token = cancel_token_new();
+        cancel_token_cancel(token);
+        let result = dir_get_size(path.as_ptr(), ptr::null(), SYMLINK_POLICY_FOLLOW, false, token);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        u64_result_free(result);
+
+        let result = dir_remove(path.as_ptr(), false, token);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        assert_eq!((*result).ok, 0);
+        u64_result_free(result);
+        cancel_token_free(token);
+    }
+    assert_eq!(fs::read_dir(dir.join("src")).unwrap().count(), FILES);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cancelled_token_stops_job() {
+    let dir = test_dir("cancel_job");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let token = cancel_token_new();
+        cancel_token_cancel(token);
+        let job = job_dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), token);
+        // The job keeps its own reference to the token.
+        cancel_token_free(token);
+        let result = job_result(job);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        u64_result_free(result);
+        job_free(job);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paused_token_holds_dir_copy_until_resumed() {
+    let dir = test_dir("pause_blocking");
+    let token = cancel_token_new();
+    unsafe { cancel_token_pause(token) };
+    // The pointers cross to the copying thread as addresses.
+    let (paths, shared_token) = ((dir.join("src"), dir.join("dst")), token as usize);
+    let copy = thread::spawn(move || unsafe {
+        let (from, to) = (c_path(&paths.0), c_path(&paths.1));
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), shared_token as *const _);
+        let copied = ((*result).is_error, (*result).ok);
+        u64_result_free(result);
+        copied
+    });
+    thread::sleep(Duration::from_millis(100));
+    assert!(!copy.is_finished());
+    let target = dir.join("dst").join("src");
+    assert_eq!(fs::read_dir(&target).map_or(0, |entries| entries.count()), 0);
+
+    unsafe { cancel_token_resume(token) };
+    assert_eq!(copy.join().unwrap(), (false, (FILES * FILE_SIZE) as u64));
+    assert_eq!(fs::read_dir(&target).unwrap().count(), FILES);
+    unsafe { cancel_token_free(token) };
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paused_token_holds_job_until_resumed() {
+    let dir = test_dir("pause_job");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let token = cancel_token_new();
+        cancel_token_pause(token);
+        let job = job_dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), token);
+        assert!(!job_wait(job, 50));
+        let paused = job_progress(job);
+        assert_eq!(paused.state, JOB_STATE_PAUSED);
+        assert_eq!(paused.copied_bytes, 0);
+
+        cancel_token_resume(token);
+        assert!(job_wait(job, JOB_WAIT_FOREVER));
+        let result = job_result(job);
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, (FILES * FILE_SIZE) as u64);
+        u64_result_free(result);
+        job_free(job);
+        cancel_token_free(token);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paused_token_can_be_cancelled() {
+    let dir = test_dir("pause_cancel");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let token = cancel_token_new();
+        cancel_token_pause(token);
+        let job = job_dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), token);
+        assert!(!job_wait(job, 50));
+        cancel_token_cancel(token);
+        let result = job_result(job);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        assert_eq!((*result).ok, 0);
+        u64_result_free(result);
+        job_free(job);
+        cancel_token_free(token);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+// Helpers shared by the integration tests, each test file uses only some of them.
+
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::mem::{self, MaybeUninit};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use fs_extra_ffi::*;
+
+/// Gives a fresh directory named after the test and this process, holding
+/// empty `src` and `dst` directories.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fs_extra_ffi_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::create_dir_all(dir.join("dst")).unwrap();
+    dir
+}
+
+pub fn c_path(path: &Path) -> CString {
+    CString::new(path.as_os_str().as_bytes()).unwrap()
+}
+
+/// The default options, as a C caller gets them.
+pub fn options() -> CopyOptions {
+    let mut options = MaybeUninit::<CopyOptions>::uninit();
+    unsafe {
+        copy_options_init(options.as_mut_ptr(), mem::size_of::<CopyOptions>());
+        options.assume_init()
+    }
+}
+
+pub unsafe fn error_path(failure: *const FileError) -> PathBuf {
+    PathBuf::from(std::ffi::OsString::from_vec(CStr::from_ptr((*failure).error.path).to_bytes().to_vec()))
+}
+
+/// Counts live heap allocations, a test binary installs it with
+/// `#[global_allocator]` and reads `live_allocations` around the calls it checks.
+pub struct CountingAlloc;
+
+static LIVE_ALLOCATIONS: AtomicIsize = AtomicIsize::new(0);
+
+pub fn live_allocations() -> isize {
+    LIVE_ALLOCATIONS.load(Ordering::SeqCst)
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_ALLOCATIONS.fetch_sub(1, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
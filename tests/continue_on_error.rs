@@ -0,0 +1,134 @@
+// Continue-on-error mode: the transfers go past the files they cannot handle and
+// list them in `file_errors` with the phase they failed in.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::{c_path, error_path};
+
+/// `src` holds two files and a symlink leading nowhere, which fails to read
+/// when followed.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    fs::write(dir.join("src").join("sub").join("file"), b"content").unwrap();
+    symlink("missing", dir.join("src").join("dangling")).unwrap();
+    dir
+}
+
+fn options(continue_on_error: bool) -> CopyOptions {
+    CopyOptions {
+        continue_on_error,
+        ..common::options()
+    }
+}
+
+#[test]
+fn dir_copy_goes_past_unreadable_file() {
+    let dir = test_dir("continue_copy");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options(true), ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 14);
+        assert_eq!(u64_result_file_errors_size(result), 1);
+        let failure = u64_result_file_error(result, 0);
+        assert_eq!((*failure).phase, FILE_ERROR_PHASE_READ);
+        assert_eq!((*failure).error.code, ERROR_CODE_NOT_FOUND);
+        assert_eq!(error_path(failure), dir.join("src").join("dangling"));
+        assert!(u64_result_file_error(result, 1).is_null());
+        u64_result_free(result);
+    }
+    assert!(dir.join("dst").join("src").join("sub").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_copy_stops_at_unreadable_file_by_default() {
+    let dir = test_dir("continue_off");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options(false), ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NOT_FOUND);
+        assert_eq!(u64_result_file_errors_size(result), 0);
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_move_keeps_source_of_skipped_file() {
+    let dir = test_dir("continue_move");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = dir_move(from.as_ptr(), to.as_ptr(), &options(true), ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_file_errors_size(result), 1);
+        u64_result_free(result);
+    }
+    assert!(dir.join("src").join("dangling").symlink_metadata().is_ok());
+    assert!(!dir.join("src").join("file").exists());
+    assert!(dir.join("dst").join("src").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_copy_fails_when_target_directory_cannot_be_created() {
+    let dir = test_dir("continue_top");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("missing").join("dst"));
+    unsafe {
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options(true), ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NOT_FOUND);
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copy_items_records_phase_of_each_failure() {
+    let dir = test_dir("continue_items");
+    let items = [c_path(&dir.join("src").join("sub")), c_path(&dir.join("src").join("file"))];
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let target = dir.join("missing").join("dst");
+    let to = c_path(&target);
+    unsafe {
+        let result = copy_items(item_ptrs.as_ptr(),
+                                item_ptrs.len(),
+                                to.as_ptr(),
+                                &options(true),
+                                ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_file_errors_size(result), 2);
+
+        // The top directory of the item could not be created at the target.
+        let failure = u64_result_file_error(result, 0);
+        assert_eq!((*failure).phase, FILE_ERROR_PHASE_CREATE_DIR);
+        assert_eq!(error_path(failure), target.join("sub"));
+        let failure = u64_result_file_error(result, 1);
+        assert_eq!((*failure).phase, FILE_ERROR_PHASE_WRITE);
+        assert_eq!(error_path(failure), target.join("file"));
+
+        for index in 0..2 {
+            let item = u64_result_item(result, index);
+            assert_eq!((*item).status, ITEM_STATUS_FAILED);
+            assert_eq!((*item).error.code, ERROR_CODE_NOT_FOUND);
+        }
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,213 @@
+// Versioned `CopyOptions`: the library reads the fields its caller's `size`
+// covers and takes the defaults for the rest. Only the sizes of released
+// versions are accepted.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::mem::{self, MaybeUninit};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// `CopyOptions` of a newer library with a field this one does not know.
+#[repr(C)]
+struct NewerCopyOptions {
+    options: CopyOptions,
+    unknown: u64,
+}
+
+/// `sizeof(CopyOptions)` of the version that ended before `field`.
+macro_rules! size_before {
+    ($field:ident) => {
+        mem::offset_of!(CopyOptions, $field).next_multiple_of(mem::align_of::<CopyOptions>())
+    };
+}
+
+/// `src` holds `file` and `sub/file`.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    fs::write(dir.join("src").join("sub").join("file"), b"content").unwrap();
+    dir
+}
+
+/// Copies `src` of `dir` into `dst` with `options`, returns the error code and
+/// message, or `None` on success.
+fn dir_copy_with(dir: &Path, options: *const CopyOptions) -> Option<(u32, String)> {
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), options, ptr::null());
+        let error = if (*result).is_error {
+            let message = CStr::from_ptr((*result).error.message).to_str().unwrap();
+            Some(((*result).error.code, message.to_string()))
+        } else {
+            None
+        };
+        u64_result_free(result);
+        error
+    }
+}
+
+#[test]
+fn null_options_are_the_defaults() {
+    let dir = test_dir("options_null");
+    assert_eq!(dir_copy_with(&dir, ptr::null()), None);
+    assert!(dir.join("dst").join("src").join("sub").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_below_first_version_is_rejected() {
+    let dir = test_dir("options_small");
+    let options = CopyOptions {
+        size: 8,
+        ..common::options()
+    };
+    assert_eq!(dir_copy_with(&dir, &options),
+               Some((ERROR_CODE_INVALID_ARGUMENT, "Invalid options size: 8".to_string())));
+    let options = CopyOptions {
+        size: mem::offset_of!(CopyOptions, preserve_permissions) - 1,
+        ..options
+    };
+    assert_eq!(dir_copy_with(&dir, &options).map(|(code, _)| code),
+               Some(ERROR_CODE_INVALID_ARGUMENT));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fields_past_size_are_not_read() {
+    let dir = test_dir("options_old");
+    // Not a valid policy, read only when `size` covers it.
+    let options = CopyOptions {
+        symlinks: 99,
+        ..common::options()
+    };
+    assert_eq!(dir_copy_with(&dir, &options).map(|(code, _)| code),
+               Some(ERROR_CODE_INVALID_ARGUMENT));
+
+    // The first versioned struct ended before the preserve flags.
+    let options = CopyOptions {
+        size: size_before!(preserve_permissions),
+        ..options
+    };
+    assert_eq!(dir_copy_with(&dir, &options), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn every_released_size_is_accepted() {
+    let dir = test_dir("options_released");
+    let sizes = [size_before!(preserve_permissions),
+                 size_before!(preserve_xattrs),
+                 size_before!(symlinks),
+                 size_before!(preserve_hard_links),
+                 mem::size_of::<CopyOptions>()];
+    for size in sizes {
+        let options = CopyOptions {
+            size,
+            overwrite: true,
+            ..common::options()
+        };
+        assert_eq!(dir_copy_with(&dir, &options), None, "size {}", size);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_between_versions_is_rejected() {
+    let dir = test_dir("options_between");
+    // The end of the preserve flags, without the padding C adds after them.
+    let size = mem::offset_of!(CopyOptions, preserve_xattrs);
+    assert_ne!(size, size_before!(preserve_xattrs));
+    let options = CopyOptions {
+        size,
+        ..common::options()
+    };
+    assert_eq!(dir_copy_with(&dir, &options),
+               Some((ERROR_CODE_INVALID_ARGUMENT, format!("Invalid options size: {}", size))));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_above_this_version_is_rejected() {
+    let dir = test_dir("options_newer");
+    let options = NewerCopyOptions {
+        options: CopyOptions {
+            size: mem::size_of::<NewerCopyOptions>(),
+            ..common::options()
+        },
+        unknown: u64::MAX,
+    };
+    assert_eq!(dir_copy_with(&dir, &options.options).map(|(code, _)| code),
+               Some(ERROR_CODE_INVALID_ARGUMENT));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn init_writes_only_the_given_size() {
+    let size = size_before!(preserve_permissions);
+    unsafe {
+        let mut options = MaybeUninit::<NewerCopyOptions>::zeroed();
+        let fields = options.as_mut_ptr() as *mut CopyOptions;
+        (*fields).preserve_times = true;
+        (*options.as_mut_ptr()).unknown = u64::MAX;
+        copy_options_init(fields, size);
+        let options = options.assume_init();
+        assert_eq!(options.options.size, size);
+        assert_eq!(options.options.buffer_size, common::options().buffer_size);
+        // Past `size`, as a caller of that version does not have them.
+        assert!(options.options.preserve_times);
+        assert_eq!(options.unknown, u64::MAX);
+
+        // A newer caller gets this version's size.
+        let mut options = MaybeUninit::<NewerCopyOptions>::zeroed();
+        (*options.as_mut_ptr()).unknown = u64::MAX;
+        copy_options_init(options.as_mut_ptr() as *mut CopyOptions,
+                          mem::size_of::<NewerCopyOptions>());
+        let options = options.assume_init();
+        assert_eq!(options.options.size, mem::size_of::<CopyOptions>());
+        assert_eq!(options.unknown, u64::MAX);
+
+        // Too small to hold `size`.
+        let mut size = 0usize;
+        copy_options_init(&mut size as *mut usize as *mut CopyOptions, 4);
+        assert_eq!(size, 0);
+        copy_options_init(ptr::null_mut(), mem::size_of::<CopyOptions>());
+    }
+}
+
+#[test]
+fn zero_buffer_size_is_rejected() {
+    let dir = test_dir("options_buffer");
+    let options = CopyOptions {
+        buffer_size: 0,
+        ..common::options()
+    };
+    assert_eq!(dir_copy_with(&dir, &options),
+               Some((ERROR_CODE_INVALID_ARGUMENT, "Invalid buffer_size: 0".to_string())));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn depth_and_content_only_shape_the_copy() {
+    let dir = test_dir("options_depth");
+    let options = CopyOptions {
+        content_only: true,
+        depth: 1,
+        ..common::options()
+    };
+    assert_eq!(dir_copy_with(&dir, &options), None);
+    assert!(dir.join("dst").join("file").exists());
+    assert!(dir.join("dst").join("sub").is_dir());
+    assert!(!dir.join("dst").join("sub").join("file").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,177 @@
+// Every error result carries an `ERROR_CODE_*` value matching the kind of the
+// failure. Interrupted and Cancelled are covered in progress_abort.rs and
+// cancel.rs.
+
+extern crate fs_extra;
+extern crate fs_extra_ffi;
+extern crate libc;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs::{self, File, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::ptr;
+
+use fs_extra::error::ErrorKind;
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// Returns the code, kind and OS error of the failed `result` and releases it.
+unsafe fn get_failure(result: *mut U64Result) -> (u32, String, i32) {
+    assert!((*result).is_error);
+    let error = &(*result).error;
+    let failure = (error.code, CStr::from_ptr(error.kind).to_str().unwrap().to_string(),
+                   error.os_error);
+    u64_result_free(result);
+    failure
+}
+
+#[test]
+fn missing_source_is_not_found() {
+    let dir = common::test_dir("codes_not_found");
+    let from = c_path(&dir.join("src").join("missing"));
+    let to = c_path(&dir.join("dst").join("missing"));
+    unsafe {
+        let (code, kind, _) = get_failure(file_copy(from.as_ptr(), to.as_ptr(), ptr::null(),
+                                                    ptr::null()));
+        assert_eq!(code, ERROR_CODE_NOT_FOUND);
+        assert_eq!(kind, "NotFound");
+        let (code, _, _) = get_failure(dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(),
+                                                ptr::null()));
+        assert_eq!(code, ERROR_CODE_NOT_FOUND);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn existing_target_already_exists() {
+    let dir = common::test_dir("codes_exists");
+    fs::write(dir.join("src").join("file"), b"new").unwrap();
+    fs::write(dir.join("dst").join("file"), b"old").unwrap();
+    let from = c_path(&dir.join("src").join("file"));
+    let to = c_path(&dir.join("dst").join("file"));
+    unsafe {
+        let (code, kind, _) = get_failure(file_copy(from.as_ptr(), to.as_ptr(), ptr::null(),
+                                                    ptr::null()));
+        assert_eq!(code, ERROR_CODE_ALREADY_EXISTS);
+        assert_eq!(kind, "AlreadyExists");
+    }
+    assert_eq!(fs::read(dir.join("dst").join("file")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unreadable_source_is_permission_denied() {
+    let dir = common::test_dir("codes_denied");
+    let file = dir.join("src").join("file");
+    fs::write(&file, b"content").unwrap();
+    fs::set_permissions(&file, Permissions::from_mode(0o000)).unwrap();
+    if File::open(&file).is_ok() {
+        eprintln!("the mode does not keep this user out, nothing to check");
+        fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let from = c_path(&file);
+    let to = c_path(&dir.join("dst").join("file"));
+    unsafe {
+        let (code, _, os_error) = get_failure(file_copy(from.as_ptr(), to.as_ptr(), ptr::null(),
+                                                        ptr::null()));
+        assert_eq!(code, ERROR_CODE_PERMISSION_DENIED);
+        assert_eq!(os_error, libc::EACCES);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wrong_kind_of_source_is_invalid() {
+    let dir = common::test_dir("codes_invalid");
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    let file = c_path(&dir.join("src").join("file"));
+    let folder = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let (code, kind, _) = get_failure(dir_copy(file.as_ptr(), to.as_ptr(), ptr::null(),
+                                                   ptr::null()));
+        assert_eq!(code, ERROR_CODE_INVALID_FOLDER);
+        assert_eq!(kind, "InvalidFolder");
+        let (code, kind, _) = get_failure(file_copy(folder.as_ptr(), to.as_ptr(), ptr::null(),
+                                                    ptr::null()));
+        assert_eq!(code, ERROR_CODE_INVALID_FILE);
+        assert_eq!(kind, "InvalidFile");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn os_error_and_path_name_the_failed_target() {
+    let dir = common::test_dir("codes_os_error");
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    fs::write(dir.join("dst").join("file"), b"content").unwrap();
+    let from = c_path(&dir.join("src").join("file"));
+    let missing_dir = dir.join("dst").join("missing").join("file");
+    let below_file = dir.join("dst").join("file").join("file");
+    let targets = [(missing_dir, ERROR_CODE_NOT_FOUND, libc::ENOENT),
+                   (below_file, ERROR_CODE_IO, libc::ENOTDIR)];
+    for (target, code, os_error) in targets {
+        let to = c_path(&target);
+        unsafe {
+            let result = file_copy(from.as_ptr(), to.as_ptr(), ptr::null(), ptr::null());
+            assert!((*result).is_error);
+            let error = &(*result).error;
+            assert_eq!(error.code, code);
+            assert_eq!(error.os_error, os_error);
+            assert_eq!(CStr::from_ptr(error.path), to.as_c_str());
+            u64_result_free(result);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fs_extra_kinds_map_to_their_codes() {
+    let kinds = [(ErrorKind::InvalidPath, ERROR_CODE_INVALID_PATH, "InvalidPath"),
+                 (ErrorKind::InvalidFileName, ERROR_CODE_INVALID_FILE_NAME, "InvalidFileName"),
+                 (ErrorKind::Interrupted, ERROR_CODE_INTERRUPTED, "Interrupted"),
+                 (ErrorKind::Other, ERROR_CODE_OTHER, "Other")];
+    for (kind, code, name) in kinds {
+        let error = Error::from(fs_extra::error::Error::new(kind, "message"));
+        assert_eq!(error.code, code);
+        unsafe {
+            assert_eq!(CStr::from_ptr(error.kind).to_str().unwrap(), name);
+            assert_eq!(CStr::from_ptr(error.message).to_str().unwrap(), "message");
+        }
+    }
+}
+
+#[test]
+fn every_code_has_a_static_name() {
+    let codes = [(ERROR_CODE_NONE, "None"),
+                 (ERROR_CODE_NOT_FOUND, "NotFound"),
+                 (ERROR_CODE_PERMISSION_DENIED, "PermissionDenied"),
+                 (ERROR_CODE_ALREADY_EXISTS, "AlreadyExists"),
+                 (ERROR_CODE_INTERRUPTED, "Interrupted"),
+                 (ERROR_CODE_INVALID_FOLDER, "InvalidFolder"),
+                 (ERROR_CODE_INVALID_FILE, "InvalidFile"),
+                 (ERROR_CODE_INVALID_FILE_NAME, "InvalidFileName"),
+                 (ERROR_CODE_INVALID_PATH, "InvalidPath"),
+                 (ERROR_CODE_IO, "Io"),
+                 (ERROR_CODE_STRIP_PREFIX, "StripPrefix"),
+                 (ERROR_CODE_OS_STRING, "OsString"),
+                 (ERROR_CODE_OTHER, "Other"),
+                 (ERROR_CODE_INVALID_UTF8, "InvalidUtf8"),
+                 (ERROR_CODE_NULL_POINTER, "NullPointer"),
+                 (ERROR_CODE_INVALID_ARGUMENT, "InvalidArgument"),
+                 (ERROR_CODE_PANIC, "Panic"),
+                 (ERROR_CODE_CANCELLED, "Cancelled"),
+                 (13, "Unknown"),
+                 (u32::MAX, "Unknown")];
+    for (code, name) in codes {
+        let first = fs_extra_error_code_name(code);
+        assert!(!first.is_null());
+        // The same static string every time, nothing to free.
+        assert_eq!(fs_extra_error_code_name(code), first);
+        assert_eq!(unsafe { CStr::from_ptr(first) }.to_str().unwrap(), name);
+    }
+}
@@ -0,0 +1,166 @@
+// Filters attached to the directory operations: patterns, sizes and predicates
+// decide which entries are copied, moved and counted.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// `src` holds `main.rs` (4 bytes), `notes.tmp` (3), `target/out.bin` (10),
+/// `lib/x.rs` (5) and the file `lib/target` (6).
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    let src = dir.join("src");
+    fs::create_dir_all(src.join("target")).unwrap();
+    fs::create_dir_all(src.join("lib")).unwrap();
+    fs::create_dir_all(dir.join("dst")).unwrap();
+    fs::write(src.join("main.rs"), b"main").unwrap();
+    fs::write(src.join("notes.tmp"), b"tmp").unwrap();
+    fs::write(src.join("target").join("out.bin"), b"0123456789").unwrap();
+    fs::write(src.join("lib").join("x.rs"), b"x = 1").unwrap();
+    fs::write(src.join("lib").join("target"), b"target").unwrap();
+    dir
+}
+
+unsafe fn add(result: *mut U64Result) {
+    assert!(!(*result).is_error);
+    u64_result_free(result);
+}
+
+unsafe fn get_size(path: &Path, filter: *const Filter) -> u64 {
+    let path = c_path(path);
+    let result = dir_get_size(path.as_ptr(), filter, SYMLINK_POLICY_FOLLOW, false, ptr::null());
+    assert!(!(*result).is_error);
+    let size = (*result).ok;
+    u64_result_free(result);
+    size
+}
+
+#[test]
+fn exclude_patterns_leave_out_entries() {
+    let dir = test_dir("filter_exclude");
+    unsafe {
+        let filter = filter_new();
+        let pattern = CString::new("target/").unwrap();
+        add(filter_exclude(filter, pattern.as_ptr()));
+        let pattern = CString::new("*.tmp").unwrap();
+        add(filter_exclude(filter, pattern.as_ptr()));
+        // Only the directory `target` goes, the file of that name stays.
+        assert_eq!(get_size(&dir.join("src"), filter), 15);
+        assert_eq!(get_size(&dir.join("src"), ptr::null()), 28);
+
+        let options = CopyOptions {
+            filter,
+            ..common::options()
+        };
+        let from = c_path(&dir.join("src"));
+        let to = c_path(&dir.join("dst"));
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options, ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 15);
+        u64_result_free(result);
+        filter_free(filter);
+    }
+    let copy = dir.join("dst").join("src");
+    assert!(copy.join("main.rs").exists());
+    assert!(copy.join("lib").join("target").exists());
+    assert!(!copy.join("notes.tmp").exists());
+    assert!(!copy.join("target").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn include_patterns_only_apply_to_files() {
+    let dir = test_dir("filter_include");
+    unsafe {
+        let filter = filter_new();
+        let pattern = CString::new("*.rs").unwrap();
+        add(filter_include(filter, pattern.as_ptr()));
+        // The directories are still walked to find `lib/x.rs`.
+        assert_eq!(get_size(&dir.join("src"), filter), 9);
+
+        let pattern = CString::new("lib/*.rs").unwrap();
+        let anchored = filter_new();
+        add(filter_include(anchored, pattern.as_ptr()));
+        assert_eq!(get_size(&dir.join("src"), anchored), 5);
+        filter_free(anchored);
+        filter_free(filter);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_move_leaves_filtered_out_entries() {
+    let dir = test_dir("filter_move");
+    unsafe {
+        let filter = filter_new();
+        add(filter_size(filter, 5, 6));
+        let options = CopyOptions {
+            filter,
+            ..common::options()
+        };
+        let from = c_path(&dir.join("src"));
+        let to = c_path(&dir.join("dst"));
+        let result = dir_move(from.as_ptr(), to.as_ptr(), &options, ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 11);
+        u64_result_free(result);
+        filter_free(filter);
+    }
+    assert!(dir.join("dst").join("src").join("lib").join("x.rs").exists());
+    assert!(!dir.join("src").join("lib").join("x.rs").exists());
+    assert!(dir.join("src").join("main.rs").exists());
+    assert!(dir.join("src").join("target").join("out.bin").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+extern "C" fn keep_rs_files(path: *const c_char, is_dir: bool, _: u64, calls: *mut c_void) -> bool {
+    unsafe { *(calls as *mut usize) += 1 };
+    let path = unsafe { std::ffi::CStr::from_ptr(path) }.to_str().unwrap();
+    is_dir || path.ends_with(".rs")
+}
+
+#[test]
+fn predicate_decides_last() {
+    let dir = test_dir("filter_predicate");
+    let mut calls = 0usize;
+    unsafe {
+        let filter = filter_new();
+        let pattern = CString::new("target").unwrap();
+        add(filter_exclude(filter, pattern.as_ptr()));
+        add(filter_predicate(filter, Some(keep_rs_files), &mut calls as *mut usize as *mut c_void));
+        assert_eq!(get_size(&dir.join("src"), filter), 9);
+        filter_free(filter);
+    }
+    // Asked about `main.rs`, `notes.tmp`, `lib` and `lib/x.rs`, not about what
+    // the pattern leaves out.
+    assert_eq!(calls, 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    unsafe {
+        let filter = filter_new();
+        let pattern = CString::new("[abc").unwrap();
+        let result = filter_exclude(filter, pattern.as_ptr());
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        u64_result_free(result);
+        let result = filter_size(filter, 10, 5);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        u64_result_free(result);
+        let result = filter_include(ptr::null_mut(), pattern.as_ptr());
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        u64_result_free(result);
+        filter_free(filter);
+    }
+}
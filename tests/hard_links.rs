@@ -0,0 +1,192 @@
+// Hard-link aware copies: files sharing their data are copied once and linked
+// at the target, and counted once in the totals and sizes.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const LINKED_SIZE: u64 = 1000;
+
+/// `src` holds `a` and its hard link `sub/b` (1000 bytes) and `c` (10 bytes).
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::write(dir.join("src").join("a"), vec![b'a'; LINKED_SIZE as usize]).unwrap();
+    fs::hard_link(dir.join("src").join("a"), dir.join("src").join("sub").join("b")).unwrap();
+    fs::write(dir.join("src").join("c"), b"0123456789").unwrap();
+    dir
+}
+
+fn options(preserve_hard_links: bool) -> CopyOptions {
+    CopyOptions {
+        preserve_hard_links,
+        ..common::options()
+    }
+}
+
+fn inode(path: &Path) -> u64 {
+    fs::metadata(path).unwrap().ino()
+}
+
+/// Runs `dir_copy` or `dir_move` of `src` into `dst`, returns the error code
+/// and `ok`.
+unsafe fn transfer(dir: &Path, options: &CopyOptions, moving: bool) -> (u32, u64) {
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let result = if moving {
+        dir_move(from.as_ptr(), to.as_ptr(), options, ptr::null())
+    } else {
+        dir_copy(from.as_ptr(), to.as_ptr(), options, ptr::null())
+    };
+    let done = ((*result).error.code, (*result).ok);
+    u64_result_free(result);
+    done
+}
+
+#[test]
+fn copy_recreates_hard_links() {
+    let dir = test_dir("hard_links_copy");
+    assert_eq!(unsafe { transfer(&dir, &options(true), false) }, (ERROR_CODE_NONE, 1010));
+    let copy = dir.join("dst").join("src");
+    assert_eq!(inode(&copy.join("a")), inode(&copy.join("sub").join("b")));
+    assert_ne!(inode(&copy.join("a")), inode(&dir.join("src").join("a")));
+    assert_eq!(fs::metadata(copy.join("a")).unwrap().nlink(), 2);
+    assert_eq!(fs::metadata(copy.join("c")).unwrap().nlink(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copy_without_option_duplicates_data() {
+    let dir = test_dir("hard_links_off");
+    assert_eq!(unsafe { transfer(&dir, &options(false), false) }, (ERROR_CODE_NONE, 2010));
+    let copy = dir.join("dst").join("src");
+    assert_ne!(inode(&copy.join("a")), inode(&copy.join("sub").join("b")));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn move_recreates_hard_links() {
+    let dir = test_dir("hard_links_move");
+    assert_eq!(unsafe { transfer(&dir, &options(true), true) }, (ERROR_CODE_NONE, 1010));
+    let moved = dir.join("dst").join("src");
+    assert!(!dir.join("src").exists());
+    assert_eq!(inode(&moved.join("a")), inode(&moved.join("sub").join("b")));
+    assert_eq!(fs::read(moved.join("sub").join("b")).unwrap().len() as u64, LINKED_SIZE);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn link_of_file_outside_tree_is_copied() {
+    let dir = test_dir("hard_links_outside");
+    fs::remove_file(dir.join("src").join("sub").join("b")).unwrap();
+    fs::hard_link(dir.join("src").join("a"), dir.join("outside")).unwrap();
+    assert_eq!(unsafe { transfer(&dir, &options(true), false) }, (ERROR_CODE_NONE, 1010));
+    let copy = dir.join("dst").join("src");
+    assert_eq!(fs::metadata(copy.join("a")).unwrap().nlink(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Copies `src` of a fresh tree over a `dst/src/sub/b` that exists already,
+/// returns the error code and `ok` with the tree for the caller to remove.
+fn copy_over_existing_link(name: &str, options: &CopyOptions) -> ((u32, u64), PathBuf) {
+    let dir = test_dir(name);
+    let copy = dir.join("dst").join("src");
+    fs::create_dir_all(copy.join("sub")).unwrap();
+    fs::write(copy.join("sub").join("b"), b"old").unwrap();
+    (unsafe { transfer(&dir, options, false) }, dir)
+}
+
+#[test]
+fn existing_link_target_fails_by_default() {
+    let ((code, _), dir) = copy_over_existing_link("hard_links_exist", &options(true));
+    assert_eq!(code, ERROR_CODE_ALREADY_EXISTS);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn existing_link_target_is_skipped() {
+    let skip = CopyOptions {
+        skip_exist: true,
+        ..options(true)
+    };
+    let (done, dir) = copy_over_existing_link("hard_links_skip", &skip);
+    assert_eq!(done, (ERROR_CODE_NONE, 1010));
+    let copy = dir.join("dst").join("src");
+    assert_eq!(fs::read(copy.join("sub").join("b")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn existing_link_target_is_overwritten() {
+    let overwrite = CopyOptions {
+        overwrite: true,
+        ..options(true)
+    };
+    let (done, dir) = copy_over_existing_link("hard_links_overwrite", &overwrite);
+    assert_eq!(done, (ERROR_CODE_NONE, 1010));
+    let copy = dir.join("dst").join("src");
+    assert_eq!(inode(&copy.join("a")), inode(&copy.join("sub").join("b")));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copy_items_links_items_to_each_other() {
+    let dir = test_dir("hard_links_items");
+    let items = [c_path(&dir.join("src").join("a")), c_path(&dir.join("src").join("sub"))];
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = copy_items(item_ptrs.as_ptr(), item_ptrs.len(), to.as_ptr(), &options(true),
+                                ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, LINKED_SIZE);
+        assert_eq!((*u64_result_item(result, 1)).bytes, 0);
+        u64_result_free(result);
+    }
+    assert_eq!(inode(&dir.join("dst").join("a")), inode(&dir.join("dst").join("sub").join("b")));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_counts_linked_data_once() {
+    let dir = test_dir("hard_links_size");
+    let path = c_path(&dir.join("src"));
+    for &(once, size) in &[(true, 1010), (false, 2010)] {
+        unsafe {
+            let result = dir_get_size(path.as_ptr(), ptr::null(), SYMLINK_POLICY_FOLLOW, once,
+                                      ptr::null());
+            assert!(!(*result).is_error);
+            assert_eq!((*result).ok, size);
+            u64_result_free(result);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_lists_hard_links() {
+    let dir = test_dir("hard_links_plan");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let plan = dir_copy_plan(from.as_ptr(), to.as_ptr(), &options(true));
+        assert!(!(*plan).is_error);
+        let links: Vec<_> = (0..plan_result_size(plan))
+            .map(|index| &*plan_result_step(plan, index))
+            .filter(|step| step.action == PLAN_ACTION_HARD_LINK)
+            .collect();
+        assert_eq!(links.len(), 1);
+        assert_eq!((*plan).total_bytes, 1010);
+        plan_result_free(plan);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,62 @@
+// Compiles the generated header with the C compiler, where there is one, so a
+// header the parser in build.rs got wrong fails the tests.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs;
+use std::mem;
+use std::process::Command;
+
+use fs_extra_ffi::*;
+
+const HEADER: &str = concat!(env!("OUT_DIR"), "/fs_extra_ffi.h");
+
+fn has_compiler(compiler: &str) -> bool {
+    Command::new(compiler).arg("--version").output().is_ok_and(|output| output.status.success())
+}
+
+/// Compiles a file that includes the header and checks the sizes C sees
+/// against the Rust ones.
+fn compile_with(compiler: &str, source_name: &str, flags: &[&str]) {
+    if !has_compiler(compiler) {
+        eprintln!("no `{}`, the header is not compiled", compiler);
+        return;
+    }
+    let dir = common::test_dir(&format!("header_{}", compiler.replace('+', "x")));
+    let source = dir.join(source_name);
+    fs::write(&source,
+              format!("#include \"{}\"\n\
+                       #include <assert.h>\n\
+                       static_assert(sizeof(CopyOptions) == {}, \"CopyOptions\");\n\
+                       static_assert(sizeof(Entry) == {}, \"Entry\");\n\
+                       static_assert(sizeof(Error) == {}, \"Error\");\n\
+                       static_assert(sizeof(PlanStep) == {}, \"PlanStep\");\n\
+                       int main(void) {{ return FS_EXTRA_FFI_ABI_VERSION == {} ? 0 : 1; }}\n",
+                      HEADER,
+                      mem::size_of::<CopyOptions>(),
+                      mem::size_of::<Entry>(),
+                      mem::size_of::<Error>(),
+                      mem::size_of::<PlanStep>(),
+                      FS_EXTRA_FFI_ABI_VERSION))
+        .unwrap();
+    let output = Command::new(compiler)
+        .args(flags)
+        .args(["-Wall", "-Wextra", "-Werror", "-fsyntax-only"])
+        .arg(&source)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn header_compiles_as_c() {
+    compile_with("cc", "header.c", &["-std=c11", "-pedantic"]);
+}
+
+#[test]
+fn header_compiles_as_cpp() {
+    compile_with("c++", "header.cpp", &["-std=c++11", "-pedantic"]);
+}
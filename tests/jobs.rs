@@ -0,0 +1,156 @@
+// Background jobs: waiting, polling, collecting the result once and jobs that
+// never start.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const FILES: usize = 20;
+const FILE_SIZE: usize = 10_000;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    for n in 0..FILES {
+        fs::write(dir.join("src").join(format!("file_{}", n)), vec![b'x'; FILE_SIZE]).unwrap();
+    }
+    dir
+}
+
+/// Starts a copy of `dir` slow enough, with its small buffer, to be paused
+/// before it is through.
+unsafe fn start_slow_copy(dir: &Path, cancel: *const CancelToken) -> *mut Job {
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let options = CopyOptions {
+        buffer_size: 10,
+        ..common::options()
+    };
+    job_dir_copy(from.as_ptr(), to.as_ptr(), &options, cancel)
+}
+
+#[test]
+fn job_dir_copy_finishes_with_result() {
+    let dir = test_dir("job_copy");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let job = job_dir_copy(from.as_ptr(), to.as_ptr(), ptr::null(), ptr::null());
+        assert!(!job.is_null());
+        assert!(job_wait(job, JOB_WAIT_FOREVER));
+        let progress = job_progress(job);
+        assert_eq!(progress.state, JOB_STATE_FINISHED);
+        assert_eq!(progress.copied_bytes, (FILES * FILE_SIZE) as u64);
+        // Like in fs_extra, the total counts the directory entries as well.
+        assert!(progress.total_bytes >= progress.copied_bytes);
+
+        let result = job_result(job);
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, progress.copied_bytes);
+        u64_result_free(result);
+        job_free(job);
+    }
+    assert_eq!(fs::read_dir(dir.join("dst").join("src")).unwrap().count(), FILES);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn job_result_is_collected_once() {
+    let dir = test_dir("job_once");
+    let path = c_path(&dir.join("src"));
+    unsafe {
+        let job = job_dir_get_size(path.as_ptr(), ptr::null(), SYMLINK_POLICY_FOLLOW, false,
+                                   ptr::null());
+        let result = job_result(job);
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, (FILES * FILE_SIZE) as u64);
+        u64_result_free(result);
+
+        let result = job_result(job);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        u64_result_free(result);
+        job_free(job);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn job_with_invalid_arguments_comes_back_finished() {
+    unsafe {
+        let job = job_dir_copy(ptr::null(), ptr::null(), ptr::null(), ptr::null());
+        assert!(!job.is_null());
+        assert!(job_wait(job, 0));
+        assert_eq!(job_progress(job).state, JOB_STATE_FINISHED);
+        let result = job_result(job);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        u64_result_free(result);
+        job_free(job);
+    }
+}
+
+#[test]
+fn null_job_reads_as_finished() {
+    unsafe {
+        assert!(job_wait(ptr::null(), 0));
+        assert_eq!(job_progress(ptr::null()).state, JOB_STATE_FINISHED);
+        let result = job_result(ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_NULL_POINTER);
+        u64_result_free(result);
+        job_free(ptr::null_mut());
+    }
+}
+
+#[test]
+fn paused_job_holds_until_resumed() {
+    let dir = test_dir("job_pause");
+    unsafe {
+        let job = start_slow_copy(&dir, ptr::null());
+        job_pause(job);
+        // Let it reach the next buffer, where it stops.
+        thread::sleep(Duration::from_millis(50));
+        let paused = job_progress(job);
+        assert_eq!(paused.state, JOB_STATE_PAUSED);
+        assert!(paused.copied_bytes < (FILES * FILE_SIZE) as u64);
+        assert!(!job_wait(job, 50));
+        assert_eq!(job_progress(job).copied_bytes, paused.copied_bytes);
+
+        job_resume(job);
+        assert!(job_wait(job, JOB_WAIT_FOREVER));
+        let result = job_result(job);
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, (FILES * FILE_SIZE) as u64);
+        u64_result_free(result);
+        job_free(job);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn paused_job_can_be_cancelled() {
+    let dir = test_dir("job_pause_cancel");
+    unsafe {
+        let token = cancel_token_new();
+        let job = start_slow_copy(&dir, token);
+        job_pause(job);
+        cancel_token_cancel(token);
+        let result = job_result(job);
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_CANCELLED);
+        u64_result_free(result);
+        job_free(job);
+        cancel_token_free(token);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+// Counts live heap allocations around repeated listings to check that
+// `ls_result_free` releases the entries and all of their strings.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::{c_path, live_allocations, CountingAlloc};
+
+const FILES: usize = 50;
+const ROUNDS: usize = 100;
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn test_dir() -> PathBuf {
+    let dir = common::test_dir("ls_leaks");
+    let src = dir.join("src");
+    fs::create_dir_all(src.join("sub")).unwrap();
+    for n in 0..FILES {
+        fs::write(src.join(format!("file_{}.txt", n)), b"content").unwrap();
+    }
+    symlink("file_0.txt", src.join("link")).unwrap();
+    dir
+}
+
+/// Lists `path` with every attribute and reads each entry like a caller would,
+/// returns the number of entries.
+fn list(path: &CString) -> usize {
+    let config = [DIR_ENTRY_ATTR_NAME,
+                  DIR_ENTRY_ATTR_EXT,
+                  DIR_ENTRY_ATTR_FULL_NAME,
+                  DIR_ENTRY_ATTR_PATH,
+                  DIR_ENTRY_ATTR_DOS_PATH,
+                  DIR_ENTRY_ATTR_FILE_SIZE,
+                  DIR_ENTRY_ATTR_SIZE,
+                  DIR_ENTRY_ATTR_IS_DIR,
+                  DIR_ENTRY_ATTR_IS_FILE,
+                  DIR_ENTRY_ATTR_MODIFIED,
+                  DIR_ENTRY_ATTR_ACCESSED,
+                  DIR_ENTRY_ATTR_CREATED,
+                  DIR_ENTRY_ATTR_BASE_INFO];
+    unsafe {
+        let result = dir_ls(path.as_ptr(),
+                            config.as_ptr(),
+                            config.len(),
+                            ptr::null(),
+                            SYMLINK_POLICY_KEEP);
+        assert!(!(*result).is_error);
+        let size = ls_result_size(result);
+        for index in 0..size {
+            let entry = &*ls_result_item(result, index);
+            assert!(!CStr::from_ptr(entry.name).to_bytes().is_empty());
+            if entry.is_symlink {
+                assert_eq!(CStr::from_ptr(entry.link_target).to_bytes(), b"file_0.txt");
+            }
+        }
+        assert!(ls_result_item(result, size).is_null());
+        ls_result_free(result);
+        size
+    }
+}
+
+#[test]
+fn dir_ls_frees_entries() {
+    let dir = test_dir();
+    let path = c_path(&dir.join("src"));
+    // The first call may set up state that lives for the whole process.
+    let entries = list(&path);
+
+    let before = live_allocations();
+    for _ in 0..ROUNDS {
+        assert_eq!(list(&path), entries);
+    }
+    let after = live_allocations();
+
+    assert_eq!(entries, FILES + 2);
+    assert_eq!(after, before);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,143 @@
+// Names that are not valid UTF-8 go through the `_os` functions and come back
+// in the results with the bytes the OS gave them.
+
+#![cfg(target_os = "linux")]
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const NAME: &[u8] = b"\xff\xfe";
+const OTHER_NAME: &[u8] = b"\xfe\xff";
+
+fn name(bytes: &[u8]) -> &Path {
+    Path::new(OsStr::from_bytes(bytes))
+}
+
+/// Lends the bytes of `path`, which has to outlive the `OsPath`.
+fn os_path(path: &Path) -> OsPath {
+    let bytes = path.as_os_str().as_bytes();
+    OsPath {
+        data: bytes.as_ptr(),
+        size: bytes.len(),
+    }
+}
+
+/// `src` holds the file `NAME` and the directory `NAME` with the file
+/// `OTHER_NAME` in it.
+fn test_dir(test: &str) -> PathBuf {
+    let dir = common::test_dir(test);
+    let src = dir.join("src");
+    fs::write(src.join(name(NAME)), b"file").unwrap();
+    fs::create_dir(src.join(name(OTHER_NAME))).unwrap();
+    fs::write(src.join(name(OTHER_NAME)).join(name(NAME)), b"nested").unwrap();
+    dir
+}
+
+unsafe fn assert_ok(result: *mut U64Result) {
+    assert!(!(*result).is_error);
+    u64_result_free(result);
+}
+
+#[test]
+fn file_copy_and_move_keep_the_names() {
+    let dir = test_dir("os_paths_file");
+    let from = dir.join("src").join(name(NAME));
+    let copy = dir.join("dst").join(name(NAME));
+    let moved = dir.join("dst").join(name(OTHER_NAME));
+    unsafe {
+        assert_ok(file_copy_os(os_path(&from), os_path(&copy), &common::options(), ptr::null()));
+        assert_ok(file_move_os(os_path(&from), os_path(&moved), &common::options(), ptr::null()));
+    }
+    assert_eq!(fs::read(&copy).unwrap(), b"file");
+    assert_eq!(fs::read(&moved).unwrap(), b"file");
+    assert!(!from.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_copy_and_move_keep_the_names() {
+    let dir = test_dir("os_paths_dir");
+    let from = dir.join("src").join(name(OTHER_NAME));
+    let copy = dir.join("dst").join(name(NAME));
+    fs::create_dir(&copy).unwrap();
+    unsafe {
+        assert_ok(dir_copy_os(os_path(&from), os_path(&copy), &common::options(), ptr::null()));
+        assert_ok(dir_move_os(os_path(&from), os_path(&dir.join("dst")), &common::options(),
+                              ptr::null()));
+    }
+    let copied = copy.join(name(OTHER_NAME)).join(name(NAME));
+    assert_eq!(fs::read(copied).unwrap(), b"nested");
+    let moved = dir.join("dst").join(name(OTHER_NAME)).join(name(NAME));
+    assert_eq!(fs::read(moved).unwrap(), b"nested");
+    assert!(!from.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_ls_returns_the_names_as_bytes() {
+    let dir = test_dir("os_paths_ls");
+    let src = dir.join("src");
+    let config = [DIR_ENTRY_ATTR_NAME, DIR_ENTRY_ATTR_PATH];
+    let c_src = c_path(&src);
+    unsafe {
+        let results = [dir_ls(c_src.as_ptr(), config.as_ptr(), config.len(), ptr::null(),
+                              SYMLINK_POLICY_FOLLOW),
+                       dir_ls_os(os_path(&src), config.as_ptr(), config.len(), ptr::null(),
+                                 SYMLINK_POLICY_FOLLOW)];
+        for result in results {
+            assert!(!(*result).is_error);
+            let mut names: Vec<_> = (0..ls_result_size(result))
+                .map(|index| {
+                    let entry = &*ls_result_item(result, index);
+                    let path = CStr::from_ptr(entry.path).to_bytes();
+                    assert_eq!(Path::new(OsStr::from_bytes(path)).parent(), Some(src.as_path()));
+                    CStr::from_ptr(entry.name).to_bytes().to_vec()
+                })
+                .collect();
+            names.sort();
+            assert_eq!(names, vec![OTHER_NAME.to_vec(), NAME.to_vec()]);
+            ls_result_free(result);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_steps_keep_the_names() {
+    let dir = test_dir("os_paths_plan");
+    let from = dir.join("src").join(name(OTHER_NAME));
+    let to = dir.join("dst");
+    unsafe {
+        let plan = dir_copy_plan_os(os_path(&from), os_path(&to), &common::options());
+        assert!(!(*plan).is_error);
+        let mut steps: Vec<_> = (0..plan_result_size(plan))
+            .map(|index| {
+                let step = &*plan_result_step(plan, index);
+                let from = CStr::from_ptr(step.from).to_bytes();
+                let to = CStr::from_ptr(step.to).to_bytes();
+                (step.action,
+                 PathBuf::from(OsStr::from_bytes(from)),
+                 PathBuf::from(OsStr::from_bytes(to)))
+            })
+            .collect();
+        steps.sort();
+        let target = to.join(name(OTHER_NAME));
+        assert_eq!(steps,
+                   vec![(PLAN_ACTION_CREATE_DIR, from.clone(), target.clone()),
+                        (PLAN_ACTION_COPY_FILE, from.join(name(NAME)), target.join(name(NAME)))]);
+        plan_result_free(plan);
+    }
+    assert!(!to.join(name(OTHER_NAME)).exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
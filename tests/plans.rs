@@ -0,0 +1,131 @@
+// Dry-run plans list the steps of a copy or move without writing anything.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// `src` holds `a` (3 bytes) and `sub/b` (5 bytes); `dst/src/a` exists.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::create_dir_all(dir.join("dst").join("src")).unwrap();
+    fs::write(dir.join("src").join("a"), b"new").unwrap();
+    fs::write(dir.join("src").join("sub").join("b"), b"bytes").unwrap();
+    fs::write(dir.join("dst").join("src").join("a"), b"old").unwrap();
+    dir
+}
+
+/// Action, conflict, size and paths of a step, the paths relative to `dir`.
+type Step = (u8, bool, u64, String, String);
+
+unsafe fn get_steps(plan: *const PlanResult, dir: &Path) -> Vec<Step> {
+    let relative = |path: *const _| {
+        let path = PathBuf::from(CStr::from_ptr(path).to_str().unwrap());
+        path.strip_prefix(dir).map(|path| path.to_str().unwrap().to_string()).unwrap_or_default()
+    };
+    let mut steps: Vec<Step> = (0..plan_result_size(plan))
+        .map(|index| {
+            let step = &*plan_result_step(plan, index);
+            (step.action, step.conflict, step.size, relative(step.from), relative(step.to))
+        })
+        .collect();
+    // The order of the entries within a directory is up to the file system.
+    steps.sort_by(|a, b| (&a.4, &a.3).cmp(&(&b.4, &b.3)));
+    steps
+}
+
+fn step(action: u8, conflict: bool, size: u64, from: &str, to: &str) -> Step {
+    (action, conflict, size, from.to_string(), to.to_string())
+}
+
+#[test]
+fn dir_copy_plan_lists_steps_without_writing() {
+    let dir = test_dir("plan_copy");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let plan = dir_copy_plan(from.as_ptr(), to.as_ptr(), &common::options());
+        assert!(!(*plan).is_error);
+        assert_eq!(get_steps(plan, &dir),
+                   vec![step(PLAN_ACTION_COPY_FILE, true, 3, "src/a", "dst/src/a"),
+                        step(PLAN_ACTION_CREATE_DIR, false, 0, "src/sub", "dst/src/sub"),
+                        step(PLAN_ACTION_COPY_FILE, false, 5, "src/sub/b", "dst/src/sub/b")]);
+        assert_eq!((*plan).total_bytes, 5);
+        plan_result_free(plan);
+    }
+    assert!(!dir.join("dst").join("src").join("sub").exists());
+    assert_eq!(fs::read(dir.join("dst").join("src").join("a")).unwrap(), b"old");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dir_move_plan_overwrites_and_removes_sources() {
+    let dir = test_dir("plan_move");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let options = CopyOptions {
+        overwrite: true,
+        ..common::options()
+    };
+    unsafe {
+        let plan = dir_move_plan(from.as_ptr(), to.as_ptr(), &options);
+        assert!(!(*plan).is_error);
+        assert_eq!(get_steps(plan, &dir),
+                   vec![step(PLAN_ACTION_REMOVE_SOURCE, false, 0, "src", ""),
+                        step(PLAN_ACTION_REMOVE_SOURCE, false, 3, "src/a", ""),
+                        step(PLAN_ACTION_REMOVE_SOURCE, false, 5, "src/sub/b", ""),
+                        step(PLAN_ACTION_OVERWRITE, true, 3, "src/a", "dst/src/a"),
+                        step(PLAN_ACTION_CREATE_DIR, false, 0, "src/sub", "dst/src/sub"),
+                        step(PLAN_ACTION_COPY_FILE, false, 5, "src/sub/b", "dst/src/sub/b")]);
+        assert_eq!((*plan).total_bytes, 8);
+        plan_result_free(plan);
+    }
+    assert!(dir.join("src").join("sub").join("b").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn copy_items_plan_skips_existing() {
+    let dir = test_dir("plan_items");
+    let items = [c_path(&dir.join("src").join("a")), c_path(&dir.join("src").join("sub"))];
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst").join("src"));
+    let options = CopyOptions {
+        skip_exist: true,
+        ..common::options()
+    };
+    unsafe {
+        let plan = copy_items_plan(item_ptrs.as_ptr(), item_ptrs.len(), to.as_ptr(), &options);
+        assert!(!(*plan).is_error);
+        assert_eq!(get_steps(plan, &dir),
+                   vec![step(PLAN_ACTION_SKIP_EXISTING, true, 3, "src/a", "dst/src/a"),
+                        step(PLAN_ACTION_CREATE_DIR, false, 0, "src/sub", "dst/src/sub"),
+                        step(PLAN_ACTION_COPY_FILE, false, 5, "src/sub/b", "dst/src/sub/b")]);
+        assert_eq!((*plan).total_bytes, 5);
+        plan_result_free(plan);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_of_missing_source_fails() {
+    let dir = test_dir("plan_missing");
+    let from = c_path(&dir.join("missing"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let plan = file_copy_plan(from.as_ptr(), to.as_ptr(), std::ptr::null());
+        assert!((*plan).is_error);
+        assert_eq!((*plan).error.code, ERROR_CODE_NOT_FOUND);
+        assert_eq!(plan_result_size(plan), 0);
+        plan_result_free(plan);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,148 @@
+// Metadata preservation on copy: mode bits, times and owner of the sources. A
+// copied file gets the mode of its source whichever copy path is taken.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::fs::{self, File, FileTimes, Permissions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt, chown};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+/// `src` holds `file` with mode 0604 and `sub/file`; `sub` has mode 0555.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::create_dir_all(dir.join("src").join("sub")).unwrap();
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    fs::write(dir.join("src").join("sub").join("file"), b"content").unwrap();
+    fs::set_permissions(dir.join("src").join("file"), Permissions::from_mode(0o604)).unwrap();
+    fs::set_permissions(dir.join("src").join("sub"), Permissions::from_mode(0o555)).unwrap();
+    dir
+}
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().mode() & 0o7777
+}
+
+/// Copies `src/file` of `dir` to `to`, through the path taken with a token
+/// when `with_token` is set.
+fn file_copy_to(dir: &Path, to: &Path, options: &CopyOptions, with_token: bool) {
+    let from = c_path(&dir.join("src").join("file"));
+    let to = c_path(to);
+    unsafe {
+        let token = if with_token { cancel_token_new() } else { ptr::null_mut() };
+        let result = file_copy(from.as_ptr(), to.as_ptr(), options, token);
+        assert!(!(*result).is_error);
+        u64_result_free(result);
+        cancel_token_free(token);
+    }
+}
+
+fn remove_test_dir(dir: &Path) {
+    fs::set_permissions(dir.join("src").join("sub"), Permissions::from_mode(0o755)).unwrap();
+    if let Ok(copy) = fs::metadata(dir.join("dst").join("src").join("sub")) {
+        if copy.is_dir() {
+            fs::set_permissions(dir.join("dst").join("src").join("sub"),
+                                Permissions::from_mode(0o755))
+                .unwrap();
+        }
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn mode_is_preserved_on_both_copy_paths() {
+    let dir = test_dir("preserve_mode");
+    let options = CopyOptions {
+        preserve_permissions: true,
+        ..common::options()
+    };
+    file_copy_to(&dir, &dir.join("dst").join("plain"), &options, false);
+    file_copy_to(&dir, &dir.join("dst").join("token"), &options, true);
+    assert_eq!(mode(&dir.join("dst").join("plain")), 0o604);
+    assert_eq!(mode(&dir.join("dst").join("token")), 0o604);
+    remove_test_dir(&dir);
+}
+
+#[test]
+fn file_mode_is_copied_without_preserve_permissions() {
+    let dir = test_dir("preserve_no_mode");
+    let options = CopyOptions {
+        overwrite: true,
+        ..common::options()
+    };
+    // Overwritten files get it as well as new ones, as with `fs::copy`.
+    let existing = dir.join("dst").join("existing");
+    File::create(&existing).unwrap();
+    fs::set_permissions(&existing, Permissions::from_mode(0o600)).unwrap();
+    for with_token in [false, true] {
+        let new = dir.join("dst").join(format!("new_{}", with_token));
+        file_copy_to(&dir, &new, &options, with_token);
+        file_copy_to(&dir, &existing, &options, with_token);
+        assert_eq!(mode(&new), 0o604);
+        assert_eq!(mode(&existing), 0o604);
+        fs::set_permissions(&existing, Permissions::from_mode(0o600)).unwrap();
+    }
+    remove_test_dir(&dir);
+}
+
+#[test]
+fn times_are_preserved() {
+    let dir = test_dir("preserve_times");
+    let modified = UNIX_EPOCH + Duration::from_secs(946_684_800);
+    let source = File::options().write(true).open(dir.join("src").join("file")).unwrap();
+    source.set_times(FileTimes::new().set_modified(modified).set_accessed(modified)).unwrap();
+    drop(source);
+    let options = CopyOptions {
+        preserve_times: true,
+        ..common::options()
+    };
+    file_copy_to(&dir, &dir.join("dst").join("file"), &options, false);
+    let copy = fs::metadata(dir.join("dst").join("file")).unwrap();
+    assert_eq!(copy.modified().unwrap(), modified);
+    assert_eq!(copy.accessed().unwrap(), modified);
+    remove_test_dir(&dir);
+}
+
+#[test]
+fn owner_is_preserved_with_privileges() {
+    let dir = test_dir("preserve_owner");
+    // Only a privileged process can hand the source to someone else.
+    if chown(dir.join("src").join("file"), Some(4321), Some(4321)).is_ok() {
+        let options = CopyOptions {
+            preserve_owner: true,
+            ..common::options()
+        };
+        file_copy_to(&dir, &dir.join("dst").join("file"), &options, false);
+        let copy = fs::metadata(dir.join("dst").join("file")).unwrap();
+        assert_eq!((copy.uid(), copy.gid()), (4321, 4321));
+    }
+    remove_test_dir(&dir);
+}
+
+#[test]
+fn directory_mode_is_set_after_its_contents() {
+    let dir = test_dir("preserve_dir");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let options = CopyOptions {
+        preserve_permissions: true,
+        ..common::options()
+    };
+    unsafe {
+        let result = dir_copy(from.as_ptr(), to.as_ptr(), &options, ptr::null());
+        assert!(!(*result).is_error);
+        u64_result_free(result);
+    }
+    let copy = dir.join("dst").join("src");
+    assert_eq!(mode(&copy.join("sub")), 0o555);
+    assert!(copy.join("sub").join("file").exists());
+    assert_eq!(mode(&copy.join("file")), 0o604);
+    remove_test_dir(&dir);
+}
@@ -0,0 +1,230 @@
+// Answers of progress callbacks: `ABORT` and unknown values stop the transfer
+// at the call they are given on.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::{c_char, c_void};
+use std::path::PathBuf;
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const FILES: usize = 5;
+
+struct Context {
+    calls: usize,
+    answer: u8,
+}
+
+extern "C" fn on_progress(_: DirTransitProcess, user_data: *mut c_void) -> u8 {
+    let context = unsafe { &mut *(user_data as *mut Context) };
+    context.calls += 1;
+    context.answer
+}
+
+extern "C" fn on_file_progress(_: FileTransitProcess, user_data: *mut c_void) -> u8 {
+    let context = unsafe { &mut *(user_data as *mut Context) };
+    context.calls += 1;
+    context.answer
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    for n in 0..FILES {
+        fs::write(dir.join("src").join(format!("file_{}", n)), vec![b'x'; 1000]).unwrap();
+    }
+    dir
+}
+
+fn count_files(dir: PathBuf) -> usize {
+    fs::read_dir(dir).map_or(0, |entries| entries.count())
+}
+
+fn options(continue_on_error: bool) -> CopyOptions {
+    CopyOptions {
+        buffer_size: 100,
+        continue_on_error,
+        ..common::options()
+    }
+}
+
+/// Runs `dir_copy_with_progress` of a fresh tree answering `answer`, returns
+/// the error code, the number of calls and the files that reached the target.
+fn dir_copy_answering(name: &str, answer: u8, continue_on_error: bool) -> (u32, usize, usize) {
+    let dir = test_dir(name);
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let options = options(continue_on_error);
+    let mut context = Context {
+        calls: 0,
+        answer,
+    };
+    let code = unsafe {
+        let result = dir_copy_with_progress(from.as_ptr(),
+                                            to.as_ptr(),
+                                            &options,
+                                            Some(on_progress),
+                                            &mut context as *mut Context as *mut c_void,
+                                            ptr::null());
+        assert!((*result).is_error);
+        let code = (*result).error.code;
+        u64_result_free(result);
+        code
+    };
+    let copied = count_files(dir.join("dst").join("src"));
+    fs::remove_dir_all(&dir).unwrap();
+    (code, context.calls, copied)
+}
+
+#[test]
+fn dir_copy_stops_on_abort() {
+    let (code, calls, copied) = dir_copy_answering("abort_dir", TRANSIT_PROCESS_RESULT_ABORT, false);
+    assert_eq!(code, ERROR_CODE_INTERRUPTED);
+    assert_eq!(calls, 1);
+    // The partly copied file is removed.
+    assert_eq!(copied, 0);
+}
+
+#[test]
+fn dir_copy_stops_on_abort_in_continue_on_error_mode() {
+    let (code, calls, copied) = dir_copy_answering("abort_continue", TRANSIT_PROCESS_RESULT_ABORT, true);
+    assert_eq!(code, ERROR_CODE_INTERRUPTED);
+    assert_eq!(calls, 1);
+    assert_eq!(copied, 0);
+}
+
+#[test]
+fn dir_copy_stops_on_unknown_answer() {
+    let (code, calls, copied) = dir_copy_answering("abort_unknown", 200, false);
+    assert_eq!(code, ERROR_CODE_INVALID_ARGUMENT);
+    assert_eq!(calls, 1);
+    assert_eq!(copied, 0);
+}
+
+#[test]
+fn copy_items_stops_on_abort() {
+    let dir = test_dir("abort_items");
+    let items: Vec<CString> = (0..FILES)
+        .map(|n| c_path(&dir.join("src").join(format!("file_{}", n))))
+        .collect();
+    let item_ptrs: Vec<_> = items.iter().map(|item| item.as_ptr()).collect();
+    let to = c_path(&dir.join("dst"));
+    let options = options(false);
+    let mut context = Context {
+        calls: 0,
+        answer: TRANSIT_PROCESS_RESULT_ABORT,
+    };
+    unsafe {
+        let result = copy_items_with_progress(item_ptrs.as_ptr(),
+                                              item_ptrs.len(),
+                                              to.as_ptr(),
+                                              &options,
+                                              Some(on_progress),
+                                              &mut context as *mut Context as *mut c_void,
+                                              ptr::null());
+        assert!((*result).is_error);
+        assert_eq!((*result).error.code, ERROR_CODE_INTERRUPTED);
+        assert_eq!(u64_result_item(result, 0).as_ref().unwrap().status, ITEM_STATUS_FAILED);
+        assert_eq!(u64_result_item(result, 1).as_ref().unwrap().status,
+                   ITEM_STATUS_NOT_ATTEMPTED);
+        u64_result_free(result);
+    }
+    assert_eq!(context.calls, 1);
+    assert_eq!(count_files(dir.join("dst")), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+type FileCallback = extern "C" fn(FileTransitProcess, *mut c_void) -> u8;
+
+type FileTransfer = unsafe extern "C" fn(*const c_char,
+                                         *const c_char,
+                                         *const CopyOptions,
+                                         Option<FileCallback>,
+                                         *mut c_void,
+                                         *const CancelToken)
+                                         -> *mut U64Result;
+
+/// Runs `transfer` of a 1000 byte file answering `answer`, returns the error
+/// code, the number of calls and whether the source and the target are there.
+fn file_transfer_answering(name: &str,
+                           transfer: FileTransfer,
+                           answer: u8)
+                           -> (u32, usize, bool, bool) {
+    let dir = test_dir(name);
+    let from = dir.join("src").join("file_0");
+    let to = dir.join("dst").join("file_0");
+    let options = options(false);
+    let mut context = Context {
+        calls: 0,
+        answer,
+    };
+    let code = unsafe {
+        let result = transfer(c_path(&from).as_ptr(),
+                              c_path(&to).as_ptr(),
+                              &options,
+                              Some(on_file_progress),
+                              &mut context as *mut Context as *mut c_void,
+                              ptr::null());
+        assert!((*result).is_error);
+        let code = (*result).error.code;
+        u64_result_free(result);
+        code
+    };
+    let found = (from.exists(), to.exists());
+    fs::remove_dir_all(&dir).unwrap();
+    (code, context.calls, found.0, found.1)
+}
+
+#[test]
+fn file_copy_stops_on_abort() {
+    let answer = TRANSIT_PROCESS_RESULT_ABORT;
+    let (code, calls, source, target) = file_transfer_answering("abort_file_copy",
+                                                                file_copy_with_progress,
+                                                                answer);
+    assert_eq!(code, ERROR_CODE_INTERRUPTED);
+    assert_eq!(calls, 1);
+    assert!(source);
+    // The partly copied file is removed.
+    assert!(!target);
+}
+
+#[test]
+fn file_copy_stops_on_unknown_answer() {
+    let (code, calls, source, target) = file_transfer_answering("abort_file_copy_unknown",
+                                                                file_copy_with_progress,
+                                                                200);
+    assert_eq!(code, ERROR_CODE_INVALID_ARGUMENT);
+    assert_eq!(calls, 1);
+    assert!(source);
+    assert!(!target);
+}
+
+#[test]
+fn file_move_stops_on_abort() {
+    let answer = TRANSIT_PROCESS_RESULT_ABORT;
+    let (code, calls, source, target) = file_transfer_answering("abort_file_move",
+                                                                file_move_with_progress,
+                                                                answer);
+    assert_eq!(code, ERROR_CODE_INTERRUPTED);
+    assert_eq!(calls, 1);
+    // The source stays where it was.
+    assert!(source);
+    assert!(!target);
+}
+
+#[test]
+fn file_move_stops_on_unknown_answer() {
+    let (code, calls, source, target) = file_transfer_answering("abort_file_move_unknown",
+                                                                file_move_with_progress,
+                                                                200);
+    assert_eq!(code, ERROR_CODE_INVALID_ARGUMENT);
+    assert_eq!(calls, 1);
+    assert!(source);
+    assert!(!target);
+}
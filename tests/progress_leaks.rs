@@ -0,0 +1,70 @@
+// Counts live heap allocations around large copies to check that the strings
+// lent to progress callbacks are freed by the library.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+
+use fs_extra_ffi::*;
+
+use common::{c_path, live_allocations, CountingAlloc};
+
+const FILES: usize = 4000;
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+struct Context {
+    calls: usize,
+    name_bytes: usize,
+}
+
+extern "C" fn on_progress(info: DirTransitProcess, user_data: *mut c_void) -> u8 {
+    let context = unsafe { &mut *(user_data as *mut Context) };
+    context.calls += 1;
+    context.name_bytes += unsafe { CStr::from_ptr(info.file_name) }.to_bytes().len();
+    TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT
+}
+
+fn test_dir() -> PathBuf {
+    let dir = common::test_dir("leaks");
+    for n in 0..FILES {
+        fs::write(dir.join("src").join(format!("file_{}", n)), b"content").unwrap();
+    }
+    dir
+}
+
+#[test]
+fn dir_copy_with_progress_frees_callback_strings() {
+    let dir = test_dir();
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    let mut context = Context {
+        calls: 0,
+        name_bytes: 0,
+    };
+    let user_data = &mut context as *mut Context as *mut c_void;
+
+    let before = live_allocations();
+    unsafe {
+        let result = dir_copy_with_progress(from.as_ptr(),
+                                            to.as_ptr(),
+                                            std::ptr::null(),
+                                            Some(on_progress),
+                                            user_data,
+                                            std::ptr::null());
+        assert!(!(*result).is_error);
+        u64_result_free(result);
+    }
+    let after = live_allocations();
+
+    assert!(context.calls >= FILES);
+    assert!(context.name_bytes > 0);
+    assert_eq!(after, before);
+    fs::remove_dir_all(&dir).unwrap();
+}
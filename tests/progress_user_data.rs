@@ -0,0 +1,152 @@
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::ptr;
+use std::thread;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const THREADS: usize = 8;
+const FILES: usize = 4;
+
+struct Context {
+    id: usize,
+    calls: usize,
+    foreign_calls: usize,
+    copied_bytes: u64,
+}
+
+impl Context {
+    fn new(id: usize) -> Context {
+        Context {
+            id,
+            calls: 0,
+            foreign_calls: 0,
+            copied_bytes: 0,
+        }
+    }
+
+    fn as_user_data(&mut self) -> *mut c_void {
+        self as *mut Context as *mut c_void
+    }
+}
+
+extern "C" fn on_dir_progress(info: DirTransitProcess, user_data: *mut c_void) -> u8 {
+    let context = unsafe { &mut *(user_data as *mut Context) };
+    let file_name = unsafe { CStr::from_ptr(info.file_name) }.to_str().unwrap();
+    context.calls += 1;
+    if !file_name.starts_with(&format!("t{}_", context.id)) {
+        context.foreign_calls += 1;
+    }
+    context.copied_bytes = info.copied_bytes;
+    TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT
+}
+
+extern "C" fn on_file_progress(info: FileTransitProcess, user_data: *mut c_void) -> u8 {
+    let context = unsafe { &mut *(user_data as *mut Context) };
+    context.calls += 1;
+    if info.total_bytes != file_size(context.id) {
+        context.foreign_calls += 1;
+    }
+    context.copied_bytes = info.copied_bytes;
+    TRANSIT_PROCESS_RESULT_CONTINUE_OR_ABORT
+}
+
+fn file_size(id: usize) -> u64 {
+    1000 * (id as u64 + 1)
+}
+
+fn test_dir(name: &str, id: usize) -> PathBuf {
+    common::test_dir(&format!("{}_{}", name, id))
+}
+
+fn small_buffer() -> CopyOptions {
+    CopyOptions {
+        buffer_size: 100,
+        ..common::options()
+    }
+}
+
+#[test]
+fn concurrent_dir_copies_report_to_their_own_context() {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|id| {
+            thread::spawn(move || {
+                let dir = test_dir("dir_user_data", id);
+                for n in 0..FILES {
+                    let content = vec![b'x'; file_size(id) as usize];
+                    fs::write(dir.join("src").join(format!("t{}_{}", id, n)), content).unwrap();
+                }
+
+                let mut context = Context::new(id);
+                let options = small_buffer();
+                let from = c_path(&dir.join("src"));
+                let to = c_path(&dir.join("dst"));
+                unsafe {
+                    let result = dir_copy_with_progress(from.as_ptr(),
+                                                        to.as_ptr(),
+                                                        &options,
+                                                        Some(on_dir_progress),
+                                                        context.as_user_data(),
+                                                        ptr::null());
+                    assert!(!(*result).is_error);
+                    assert_eq!((*result).ok, file_size(id) * FILES as u64);
+                    u64_result_free(result);
+                }
+                fs::remove_dir_all(&dir).unwrap();
+                context
+            })
+        })
+        .collect();
+
+    for (id, handle) in handles.into_iter().enumerate() {
+        let context = handle.join().unwrap();
+        assert_eq!(context.id, id);
+        assert_eq!(context.calls, FILES * 10 * (id + 1));
+        assert_eq!(context.foreign_calls, 0);
+    }
+}
+
+#[test]
+fn concurrent_file_copies_report_to_their_own_context() {
+    let handles: Vec<_> = (0..THREADS)
+        .map(|id| {
+            thread::spawn(move || {
+                let dir = test_dir("file_user_data", id);
+                let content = vec![b'x'; file_size(id) as usize];
+                fs::write(dir.join("src").join("file"), content).unwrap();
+
+                let mut context = Context::new(id);
+                let options = small_buffer();
+                let from = c_path(&dir.join("src").join("file"));
+                let to = c_path(&dir.join("dst").join("file"));
+                unsafe {
+                    let result = file_copy_with_progress(from.as_ptr(),
+                                                         to.as_ptr(),
+                                                         &options,
+                                                         Some(on_file_progress),
+                                                         context.as_user_data(),
+                                                         ptr::null());
+                    assert!(!(*result).is_error);
+                    u64_result_free(result);
+                }
+                fs::remove_dir_all(&dir).unwrap();
+                context
+            })
+        })
+        .collect();
+
+    for (id, handle) in handles.into_iter().enumerate() {
+        let context = handle.join().unwrap();
+        assert_eq!(context.calls, 10 * (id + 1));
+        assert_eq!(context.foreign_calls, 0);
+        assert_eq!(context.copied_bytes, file_size(id));
+    }
+}
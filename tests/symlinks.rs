@@ -0,0 +1,235 @@
+// Symlink policies of the copies, moves and sizes, and the checks on followed
+// links: loops back into the tree and links leading outside of it.
+
+extern crate fs_extra_ffi;
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use fs_extra_ffi::*;
+
+use common::{c_path, error_path};
+
+/// `src` holds `file` (7 bytes) and `link`, a symlink to it; `outside` (5
+/// bytes) is next to `src`.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = common::test_dir(name);
+    fs::write(dir.join("src").join("file"), b"content").unwrap();
+    fs::write(dir.join("outside"), b"other").unwrap();
+    symlink("file", dir.join("src").join("link")).unwrap();
+    dir
+}
+
+fn options(symlinks: u8) -> CopyOptions {
+    CopyOptions {
+        symlinks,
+        ..common::options()
+    }
+}
+
+/// Copies `src` of `dir` into `dst`, returns the result for the caller to check
+/// and free.
+unsafe fn copy_src(dir: &Path, options: &CopyOptions) -> *mut U64Result {
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    dir_copy(from.as_ptr(), to.as_ptr(), options, ptr::null())
+}
+
+unsafe fn get_size(path: &Path, symlinks: u8) -> u64 {
+    let path = c_path(path);
+    let result = dir_get_size(path.as_ptr(), ptr::null(), symlinks, false, ptr::null());
+    assert!(!(*result).is_error);
+    let size = (*result).ok;
+    u64_result_free(result);
+    size
+}
+
+#[test]
+fn follow_copies_what_links_lead_to() {
+    let dir = test_dir("links_follow");
+    unsafe {
+        let result = copy_src(&dir, &options(SYMLINK_POLICY_FOLLOW));
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 14);
+        u64_result_free(result);
+    }
+    let link = dir.join("dst").join("src").join("link");
+    assert!(link.symlink_metadata().unwrap().file_type().is_file());
+    assert_eq!(fs::read(link).unwrap(), b"content");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keep_recreates_links() {
+    let dir = test_dir("links_keep");
+    unsafe {
+        let result = copy_src(&dir, &options(SYMLINK_POLICY_KEEP));
+        assert!(!(*result).is_error);
+        // The link counts with the 4 bytes of its target.
+        assert_eq!((*result).ok, 11);
+        u64_result_free(result);
+    }
+    let link = dir.join("dst").join("src").join("link");
+    assert_eq!(fs::read_link(link).unwrap(), PathBuf::from("file"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skip_leaves_links_out() {
+    let dir = test_dir("links_skip");
+    unsafe {
+        let result = copy_src(&dir, &options(SYMLINK_POLICY_SKIP));
+        assert!(!(*result).is_error);
+        assert_eq!((*result).ok, 7);
+        u64_result_free(result);
+    }
+    assert!(dir.join("dst").join("src").join("link").symlink_metadata().is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn sizes_follow_the_policy() {
+    let dir = test_dir("links_size");
+    unsafe {
+        assert_eq!(get_size(&dir.join("src"), SYMLINK_POLICY_FOLLOW), 14);
+        assert_eq!(get_size(&dir.join("src"), SYMLINK_POLICY_KEEP), 11);
+        assert_eq!(get_size(&dir.join("src"), SYMLINK_POLICY_SKIP), 7);
+
+        let path = c_path(&dir.join("src"));
+        let result = dir_get_size(path.as_ptr(), ptr::null(), 3, false, ptr::null());
+        assert_eq!((*result).error.code, ERROR_CODE_INVALID_ARGUMENT);
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn followed_loop_fails() {
+    let dir = test_dir("links_loop");
+    fs::create_dir(dir.join("src").join("sub")).unwrap();
+    symlink("..", dir.join("src").join("sub").join("up")).unwrap();
+    unsafe {
+        let result = copy_src(&dir, &options(SYMLINK_POLICY_FOLLOW));
+        assert!((*result).is_error);
+        let message = CStr::from_ptr((*result).error.message).to_str().unwrap();
+        assert!(message.contains("loops back"), "{}", message);
+        u64_result_free(result);
+
+        let mut options = options(SYMLINK_POLICY_FOLLOW);
+        options.continue_on_error = true;
+        let result = copy_src(&dir, &options);
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_file_errors_size(result), 1);
+        let failure = u64_result_file_error(result, 0);
+        assert_eq!((*failure).phase, FILE_ERROR_PHASE_READ);
+        assert_eq!(error_path(failure), dir.join("src").join("sub").join("up"));
+        u64_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn listed_loop_has_no_size() {
+    let dir = test_dir("links_loop_ls");
+    fs::create_dir(dir.join("src").join("sub")).unwrap();
+    symlink("..", dir.join("src").join("sub").join("up")).unwrap();
+    let src = c_path(&dir.join("src"));
+    let config = [DIR_ENTRY_ATTR_NAME, DIR_ENTRY_ATTR_SIZE];
+    unsafe {
+        let result = dir_ls(src.as_ptr(), config.as_ptr(), config.len(), ptr::null(),
+                            SYMLINK_POLICY_FOLLOW);
+        assert!(!(*result).is_error);
+        let mut sizes: Vec<_> = (0..ls_result_size(result))
+            .map(|index| {
+                let entry = &*ls_result_item(result, index);
+                (CStr::from_ptr(entry.name).to_str().unwrap().to_owned(), entry.size)
+            })
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes,
+                   vec![("file".to_owned(), 7), ("link".to_owned(), 7), ("sub".to_owned(), 0)]);
+        assert_eq!(ls_result_warnings_size(result), 1);
+        let warning = ls_result_warning(result, 0);
+        assert_eq!((*warning).phase, FILE_ERROR_PHASE_READ);
+        assert_eq!(error_path(warning), dir.join("src").join("sub"));
+        ls_result_free(result);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn kept_loop_is_copied_as_link() {
+    let dir = test_dir("links_loop_keep");
+    fs::create_dir(dir.join("src").join("sub")).unwrap();
+    symlink("..", dir.join("src").join("sub").join("up")).unwrap();
+    unsafe {
+        let result = copy_src(&dir, &options(SYMLINK_POLICY_KEEP));
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_warnings_size(result), 0);
+        u64_result_free(result);
+    }
+    let up = dir.join("dst").join("src").join("sub").join("up");
+    assert_eq!(fs::read_link(up).unwrap(), PathBuf::from(".."));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn link_leading_outside_is_a_warning() {
+    let dir = test_dir("links_outside");
+    symlink("../outside", dir.join("src").join("out")).unwrap();
+    for &symlinks in &[SYMLINK_POLICY_FOLLOW, SYMLINK_POLICY_KEEP] {
+        let _ = fs::remove_dir_all(dir.join("dst").join("src"));
+        unsafe {
+            let result = copy_src(&dir, &options(symlinks));
+            assert!(!(*result).is_error);
+            assert_eq!(u64_result_warnings_size(result), 1);
+            let warning = u64_result_warning(result, 0);
+            assert_eq!(error_path(warning), dir.join("src").join("out"));
+            u64_result_free(result);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn followed_move_takes_only_the_link() {
+    let dir = test_dir("links_move");
+    symlink("../outside", dir.join("src").join("out")).unwrap();
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let result = dir_move(from.as_ptr(), to.as_ptr(), ptr::null(), ptr::null());
+        assert!(!(*result).is_error);
+        u64_result_free(result);
+    }
+    assert!(!dir.join("src").exists());
+    assert_eq!(fs::read(dir.join("outside")).unwrap(), b"other");
+    let moved = dir.join("dst").join("src");
+    assert_eq!(fs::read(moved.join("out")).unwrap(), b"other");
+    // Whatever the order of the entries, also when `file` itself is moved.
+    assert_eq!(fs::read(moved.join("link")).unwrap(), b"content");
+    assert_eq!(fs::read(moved.join("file")).unwrap(), b"content");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_lists_kept_links() {
+    let dir = test_dir("links_plan");
+    let from = c_path(&dir.join("src"));
+    let to = c_path(&dir.join("dst"));
+    unsafe {
+        let plan = dir_copy_plan(from.as_ptr(), to.as_ptr(), &options(SYMLINK_POLICY_KEEP));
+        assert!(!(*plan).is_error);
+        let actions: Vec<_> = (0..plan_result_size(plan))
+            .map(|index| (*plan_result_step(plan, index)).action)
+            .collect();
+        assert_eq!(actions.iter().filter(|&&action| action == PLAN_ACTION_COPY_LINK).count(), 1);
+        plan_result_free(plan);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
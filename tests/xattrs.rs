@@ -0,0 +1,173 @@
+// Extended attributes are copied with `preserve_xattrs`, also to targets that
+// end up read-only or are created that way. One the target refuses is a warning.
+
+extern crate fs_extra_ffi;
+extern crate libc;
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Mutex;
+
+use fs_extra_ffi::*;
+
+use common::c_path;
+
+const NAME: &str = "user.fs_extra_ffi";
+const VALUE: &[u8] = b"value";
+
+// The umask is shared by the whole process.
+static UMASK: Mutex<()> = Mutex::new(());
+
+fn set_xattr(path: &Path) -> bool {
+    set_named_xattr(path, NAME, VALUE)
+}
+
+fn set_named_xattr(path: &Path, name: &str, value: &[u8]) -> bool {
+    let name = CString::new(name).unwrap();
+    let path = c_path(path);
+    unsafe {
+        libc::setxattr(path.as_ptr(),
+                       name.as_ptr(),
+                       value.as_ptr() as *const libc::c_void,
+                       value.len(),
+                       0) == 0
+    }
+}
+
+fn get_xattr(path: &Path) -> Option<Vec<u8>> {
+    let name = CString::new(NAME).unwrap();
+    let path = c_path(path);
+    let mut buf = [0u8; 64];
+    let size = unsafe {
+        libc::getxattr(path.as_ptr(),
+                       name.as_ptr(),
+                       buf.as_mut_ptr() as *mut libc::c_void,
+                       buf.len())
+    };
+    if size < 0 {
+        None
+    } else {
+        Some(buf[..size as usize].to_vec())
+    }
+}
+
+/// `src/file` has mode 0444 and the attribute, `None` where the file system of
+/// the temp directory takes no user attributes.
+fn test_dir(name: &str) -> Option<PathBuf> {
+    let dir = common::test_dir(name);
+    let file = dir.join("src").join("file");
+    fs::write(&file, b"content").unwrap();
+    if !set_xattr(&file) {
+        fs::remove_dir_all(&dir).unwrap();
+        return None;
+    }
+    fs::set_permissions(&file, Permissions::from_mode(0o444)).unwrap();
+    Some(dir)
+}
+
+fn file_copy_to(dir: &Path, to: &Path, options: &CopyOptions, with_token: bool) {
+    let from = c_path(&dir.join("src").join("file"));
+    let to = c_path(to);
+    unsafe {
+        let token = if with_token { cancel_token_new() } else { ptr::null_mut() };
+        let result = file_copy(from.as_ptr(), to.as_ptr(), options, token);
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_warnings_size(result), 0);
+        u64_result_free(result);
+        cancel_token_free(token);
+    }
+}
+
+#[test]
+fn xattrs_reach_read_only_targets() {
+    let _umask = UMASK.lock().unwrap();
+    let dir = match test_dir("xattrs_read_only") {
+        Some(dir) => dir,
+        None => return,
+    };
+    let options = CopyOptions {
+        preserve_permissions: true,
+        preserve_xattrs: true,
+        ..common::options()
+    };
+    for &(name, with_token) in &[("plain", false), ("token", true)] {
+        let to = dir.join("dst").join(name);
+        file_copy_to(&dir, &to, &options, with_token);
+        assert_eq!(get_xattr(&to).as_deref(), Some(VALUE));
+        assert_eq!(fs::metadata(&to).unwrap().mode() & 0o777, 0o444);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn xattrs_reach_targets_created_read_only() {
+    let _umask = UMASK.lock().unwrap();
+    let dir = match test_dir("xattrs_umask") {
+        Some(dir) => dir,
+        None => return,
+    };
+    let options = CopyOptions {
+        preserve_xattrs: true,
+        ..common::options()
+    };
+    let to = dir.join("dst").join("file");
+    let umask = unsafe { libc::umask(0o277) };
+    file_copy_to(&dir, &to, &options, false);
+    unsafe { libc::umask(umask) };
+    assert_eq!(get_xattr(&to).as_deref(), Some(VALUE));
+    // The mode of the source, which a copy gives the file whatever the umask,
+    // is put back.
+    assert_eq!(fs::metadata(&to).unwrap().mode() & 0o777, 0o444);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refused_attribute_is_a_warning() {
+    // A value that tmpfs takes and that does not fit the one block ext4 keeps
+    // the attributes of a file in.
+    const BIG_NAME: &str = "user.fs_extra_ffi_big";
+    let big_value = vec![b'x'; 8000];
+    let source_dir = Path::new("/dev/shm")
+        .join(format!("fs_extra_ffi_xattrs_{}", std::process::id()));
+    let dir = common::test_dir("xattrs_refused");
+    let from = source_dir.join("file");
+    let to = dir.join("dst").join("file");
+    let probe = dir.join("probe");
+    let _ = fs::create_dir(&source_dir);
+    fs::write(&from, b"content").unwrap();
+    fs::write(&probe, b"").unwrap();
+    if !set_xattr(&from) || !set_named_xattr(&from, BIG_NAME, &big_value) ||
+       set_named_xattr(&probe, BIG_NAME, &big_value) {
+        eprintln!("no file systems that take and refuse the attribute, nothing to check");
+        fs::remove_dir_all(&source_dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        return;
+    }
+    let options = CopyOptions {
+        preserve_xattrs: true,
+        ..common::options()
+    };
+    unsafe {
+        let result = file_copy(c_path(&from).as_ptr(), c_path(&to).as_ptr(), &options,
+                               ptr::null());
+        assert!(!(*result).is_error);
+        assert_eq!(u64_result_warnings_size(result), 1);
+        let warning = &*u64_result_warning(result, 0);
+        assert_eq!(warning.phase, FILE_ERROR_PHASE_WRITE);
+        assert_eq!(common::error_path(warning), to);
+        assert_ne!(warning.error.os_error, 0);
+        let message = CStr::from_ptr(warning.error.message).to_str().unwrap();
+        assert!(message.contains(BIG_NAME), "{}", message);
+        u64_result_free(result);
+    }
+    // The attributes after or before the refused one are copied all the same.
+    assert_eq!(get_xattr(&to).as_deref(), Some(VALUE));
+    assert_eq!(fs::read(&to).unwrap(), b"content");
+    fs::remove_dir_all(&source_dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}